@@ -0,0 +1,38 @@
+//! Serial vs parallel discovery walk over a synthetic tree.
+//!
+//! `cargo bench --bench discovery`; set `ATC_BENCH_FILES` to change the tree size.
+
+use air_traffic_control::discovery::{list_files_with, search_files_with_progress, PatternOptions, WalkOptions};
+use std::time::{Duration, Instant};
+
+fn time(label: &str, runs: u32, mut f: impl FnMut() -> usize) {
+    let mut best = Duration::MAX;
+    let mut found = 0;
+    for _ in 0..runs {
+        let start = Instant::now();
+        found = f();
+        best = best.min(start.elapsed());
+    }
+    println!("{:<40} {:>10.2?} ({} entries)", label, best, found);
+}
+
+fn main() {
+    let files: usize = std::env::var("ATC_BENCH_FILES").ok().and_then(|v| v.parse().ok()).unwrap_or(50_000);
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..files {
+        let sub = dir.path().join(format!("pkg{}/src/mod{}", i % 100, i % 37));
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(sub.join(format!("file{}.rs", i)), b"").unwrap();
+    }
+    let root = dir.path().to_string_lossy().to_string();
+    println!("{} files under {}", files, root);
+
+    for (name, threads) in [("serial", 1), ("parallel", 0)] {
+        let opts = WalkOptions { threads, ..Default::default() };
+        time(&format!("list max=500 {}", name), 5, || list_files_with(&root, 500, opts).len());
+        time(&format!("list all {}", name), 5, || list_files_with(&root, usize::MAX, opts).len());
+        time(&format!("search file9\\d+ {}", name), 5, || {
+            search_files_with_progress(&root, r"file9\d+\.rs$", PatternOptions::default(), usize::MAX, opts, usize::MAX, |_| {}).unwrap().len()
+        });
+    }
+}
@@ -0,0 +1,44 @@
+//! Per-row vs batched message inserts into a fresh SQLite database.
+//!
+//! `cargo bench --bench storage`; set `ATC_BENCH_MESSAGES` to change the history length.
+
+use air_traffic_control::session::{Message, MessageStatus};
+use air_traffic_control::settings::SessionSettings;
+use air_traffic_control::storage::{SessionRepository, SqliteSessionRepository};
+use chrono::Utc;
+use std::time::Instant;
+use uuid::Uuid;
+
+fn messages(n: usize) -> Vec<Message> {
+    (0..n)
+        .map(|i| Message {
+            id: Uuid::new_v4(),
+            role: if i % 2 == 0 { "user".into() } else { "assistant".into() },
+            content_summary: format!("message {}", i),
+            content: Some(format!("message {} with some longer body text", i)),
+            model_used: None,
+            created_at: Utc::now(),
+            status: MessageStatus::Complete,
+        })
+        .collect()
+}
+
+#[tokio::main]
+async fn main() {
+    let n: usize = std::env::var("ATC_BENCH_MESSAGES").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000);
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("bench.db").to_string_lossy());
+    let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+
+    let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+    let start = Instant::now();
+    for msg in messages(n) {
+        repo.append_message(id, msg).await.unwrap();
+    }
+    println!("{:<24} {:>10.2?} ({} messages)", "append_message loop", start.elapsed(), n);
+
+    let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+    let start = Instant::now();
+    repo.append_messages(id, messages(n)).await.unwrap();
+    println!("{:<24} {:>10.2?} ({} messages)", "append_messages batch", start.elapsed(), n);
+}
@@ -0,0 +1,2 @@
+-- monotonically incremented on every settings update; used for ETags
+ALTER TABLE sessions ADD COLUMN settings_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- arguments a tool was invoked with (JSON); NULL for events recorded before this migration
+ALTER TABLE tool_events ADD COLUMN args_json TEXT NULL;
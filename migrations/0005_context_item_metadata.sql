@@ -0,0 +1,2 @@
+-- source metadata for context items (e.g. page title and post-redirect URL for url items)
+ALTER TABLE context_items ADD COLUMN metadata_json TEXT NULL;
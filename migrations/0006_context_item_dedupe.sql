@@ -0,0 +1,5 @@
+-- dedupe repeated includes of identical content; legacy rows keep a NULL hash
+ALTER TABLE context_items ADD COLUMN content_hash TEXT NULL;
+ALTER TABLE context_items ADD COLUMN last_seen TEXT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_context_items_dedupe ON context_items(session_id, kind, key, content_hash);
@@ -0,0 +1,9 @@
+-- per-context-item embedding vectors (little-endian f32), used by context.retrieve
+CREATE TABLE IF NOT EXISTS context_embeddings (
+  context_item_id TEXT PRIMARY KEY,
+  model TEXT NOT NULL,
+  dims INTEGER NOT NULL,
+  vector BLOB NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(context_item_id) REFERENCES context_items(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- full message text for exports; rows written before this keep only content_summary
+ALTER TABLE messages ADD COLUMN content TEXT NULL;
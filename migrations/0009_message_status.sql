@@ -0,0 +1,2 @@
+-- 'pending' marks a user turn whose reply hasn't been recorded yet; existing rows are complete
+ALTER TABLE messages ADD COLUMN status TEXT NOT NULL DEFAULT 'complete';
@@ -0,0 +1,2 @@
+-- backs client_id-scoped session listing
+CREATE INDEX IF NOT EXISTS idx_sessions_client_id_created_at ON sessions(client_id, created_at);
@@ -0,0 +1,12 @@
+-- deterministic (temperature 0) model responses keyed by a hash of the request;
+-- `request_json` is compared on lookup so a hash collision is a miss, not a wrong answer
+CREATE TABLE IF NOT EXISTS response_cache (
+  key TEXT PRIMARY KEY,
+  request_json TEXT NOT NULL,
+  model TEXT NOT NULL,
+  content TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_response_cache_expires_at ON response_cache(expires_at);
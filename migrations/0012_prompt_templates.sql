@@ -0,0 +1,7 @@
+-- named prompt scaffolds with {{placeholders}}, rendered into session messages
+CREATE TABLE IF NOT EXISTS prompt_templates (
+  name TEXT PRIMARY KEY,
+  body TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
@@ -0,0 +1,8 @@
+-- paths the file tools changed per session, committed selectively by git.commit_changes
+CREATE TABLE IF NOT EXISTS written_files (
+  session_id TEXT NOT NULL,
+  path TEXT NOT NULL,
+  written_at TEXT NOT NULL,
+  PRIMARY KEY(session_id, path),
+  FOREIGN KEY(session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
@@ -0,0 +1,30 @@
+-- allow caller-supplied 'text' context items. SQLite can't alter a CHECK, so the
+-- table is rebuilt; embeddings are set aside first because dropping the old table
+-- cascades to them.
+CREATE TABLE context_embeddings_backup AS SELECT * FROM context_embeddings;
+
+CREATE TABLE context_items_new (
+  id TEXT PRIMARY KEY,
+  session_id TEXT NOT NULL,
+  kind TEXT NOT NULL CHECK(kind IN ('file','url','text')),
+  key TEXT NOT NULL,
+  content_excerpt TEXT NOT NULL,
+  byte_len INTEGER NOT NULL,
+  created_at TEXT NOT NULL,
+  content_hash TEXT NULL,
+  last_seen TEXT NULL,
+  metadata_json TEXT NULL,
+  FOREIGN KEY(session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+INSERT INTO context_items_new (id, session_id, kind, key, content_excerpt, byte_len, created_at, content_hash, last_seen, metadata_json)
+  SELECT id, session_id, kind, key, content_excerpt, byte_len, created_at, content_hash, last_seen, metadata_json FROM context_items;
+
+DROP TABLE context_items;
+ALTER TABLE context_items_new RENAME TO context_items;
+
+CREATE INDEX IF NOT EXISTS idx_context_items_session_created_at ON context_items(session_id, created_at);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_context_items_dedupe ON context_items(session_id, kind, key, content_hash);
+
+INSERT INTO context_embeddings SELECT * FROM context_embeddings_backup;
+DROP TABLE context_embeddings_backup;
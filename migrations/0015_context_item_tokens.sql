@@ -0,0 +1,3 @@
+-- estimated token count per context item; NULL rows (written before this column)
+-- fall back to the bytes/4 estimate
+ALTER TABLE context_items ADD COLUMN token_len INTEGER NULL;
@@ -0,0 +1,2 @@
+-- tool events dropped by the per-session max_tool_events cap, so trimming stays visible
+ALTER TABLE sessions ADD COLUMN tool_events_trimmed INTEGER NOT NULL DEFAULT 0;
@@ -1,11 +1,16 @@
 use crate::storage::SessionRepository;
 use crate::session::ToolEvent;
 use crate::discovery::read_file_under_root;
-use crate::server::{fetch_and_extract, is_allowed_host};
+use crate::net::{fetch_and_extract, is_allowed_host};
 use chrono::Utc;
 use uuid::Uuid;
 use serde_json::json;
-use crate::agent::tools::{ToolRegistry, ToolContext as ToolsContext, Tool};
+use crate::agent::tools::{blocking, ToolRegistry, ToolContext as ToolsContext, Tool, ToolStatus, ProgressEvent};
+
+/// Returned when a mutating tool is dispatched against a read-only session.
+#[derive(Debug, thiserror::Error)]
+#[error("session is read-only")]
+pub struct ReadOnlySession;
 
 pub struct AgentContext<'a, R: SessionRepository> {
     pub repo: &'a R,
@@ -21,9 +26,10 @@ pub enum EngineCommand<'a> {
 pub async fn execute<R: SessionRepository>(ctx: AgentContext<'_, R>, cmd: EngineCommand<'_>) -> anyhow::Result<String> {
     match cmd {
         EngineCommand::IncludeFile { session_id, project_root, path, max_bytes } => {
-            let content = read_file_under_root(project_root, path, max_bytes)?;
+            let (root, rel) = (project_root.to_string(), path.to_string());
+            let content = blocking(move || read_file_under_root(&root, &rel, max_bytes)).await?;
             ctx.repo.add_context_item(session_id, "file", path, &content, content.len() as i64).await?;
-            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "include_file".into(), summary: format!("included {} ({} chars)", path, content.len()), status: "ok".into(), error: None, created_at: Utc::now() }).await?;
+            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "include_file".into(), summary: format!("included {} ({} chars)", path, content.len()), status: ToolStatus::Ok.as_str().into(), error: None, args: Some(json!({"path": path, "max_bytes": max_bytes})), created_at: Utc::now() }).await?;
             Ok(format!("file:{} bytes:{}", path, content.len()))
         }
         EngineCommand::IncludeUrl { session_id, allowlist, url, max_bytes } => {
@@ -33,35 +39,64 @@ pub async fn execute<R: SessionRepository>(ctx: AgentContext<'_, R>, cmd: Engine
             if !is_allowed_host(&allowlist_opt, host) {
                 anyhow::bail!("forbidden host");
             }
-            let content = fetch_and_extract(url, max_bytes).await?;
-            ctx.repo.add_context_item(session_id, "url", url, &content, content.len() as i64).await?;
-            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "include_url".into(), summary: format!("included {} ({} chars)", url, content.len()), status: "ok".into(), error: None, created_at: Utc::now() }).await?;
+            let page = fetch_and_extract(url, max_bytes, &[], &allowlist_opt).await?;
+            let content = page.content;
+            let metadata = json!({"title": page.title, "final_url": page.final_url});
+            ctx.repo.add_context_item_with_metadata(session_id, "url", url, &content, content.len() as i64, Some(metadata)).await?;
+            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "include_url".into(), summary: format!("included {} ({} chars)", url, content.len()), status: ToolStatus::Ok.as_str().into(), error: None, args: Some(json!({"url": url, "max_bytes": max_bytes})), created_at: Utc::now() }).await?;
             Ok(format!("url:{} bytes:{}", url, content.len()))
         }
         EngineCommand::AddRuleSystem { session_id, name, content } => {
             ctx.repo.upsert_rule(name, content).await?;
-            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "add_rule".into(), summary: format!("system rule upserted: {}", name), status: "ok".into(), error: None, created_at: Utc::now() }).await?;
+            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "add_rule".into(), summary: format!("system rule upserted: {}", name), status: ToolStatus::Ok.as_str().into(), error: None, args: Some(json!({"system": true, "name": name, "content": content})), created_at: Utc::now() }).await?;
             Ok(format!("system rule:{}", name))
         }
         EngineCommand::AddRuleRepo { session_id, project_root, name, content, repo_dir } => {
             let path = std::path::Path::new(project_root).join(repo_dir).join(format!("{}.md", slugify(name)));
             let parent = path.parent().unwrap_or(std::path::Path::new(project_root)).to_path_buf();
-            std::fs::create_dir_all(&parent)?;
-            std::fs::write(&path, content.as_bytes())?;
-            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "add_rule".into(), summary: format!("repo rule written: {}", path.display()), status: "ok".into(), error: None, created_at: Utc::now() }).await?;
+            tokio::fs::create_dir_all(&parent).await?;
+            tokio::fs::write(&path, content.as_bytes()).await?;
+            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: "add_rule".into(), summary: format!("repo rule written: {}", path.display()), status: ToolStatus::Ok.as_str().into(), error: None, args: Some(json!({"name": name, "content": content, "repo_dir": repo_dir})), created_at: Utc::now() }).await?;
             Ok(format!("repo rule:{}", path.display()))
         }
     }
 }
 
 pub async fn dispatch_tool<R: SessionRepository>(ctx: AgentContext<'_, R>, session_id: Uuid, tool_name: &str, args: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    dispatch_tool_with_progress(ctx, session_id, tool_name, args, None).await
+}
+
+/// [`dispatch_tool`] with a channel the tool may report progress on; the sender is dropped when the tool finishes.
+pub async fn dispatch_tool_with_progress<R: SessionRepository>(ctx: AgentContext<'_, R>, session_id: Uuid, tool_name: &str, args: serde_json::Value, progress: Option<tokio::sync::mpsc::Sender<ProgressEvent>>) -> anyhow::Result<serde_json::Value> {
     let sess = ctx.repo.get_session(session_id).await?.ok_or_else(|| anyhow::anyhow!("session not found"))?;
     let registry = ToolRegistry::with_default_tools();
     let tool = registry.get(tool_name).ok_or_else(|| anyhow::anyhow!("unknown tool"))?;
-    let tctx = ToolsContext { repo: ctx.repo, session_id, settings: &sess.settings };
-    let res = tool.run(tctx, args).await?;
-    ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: tool.name().into(), summary: res.summary.clone(), status: "ok".into(), error: None, created_at: Utc::now() }).await?;
-    Ok(json!({ "summary": res.summary, "data": res.data }))
+    if tool.mutating() && sess.settings.is_read_only() {
+        return Err(ReadOnlySession.into());
+    }
+    let tctx = ToolsContext { repo: ctx.repo, session_id, settings: &sess.settings, progress };
+    let res = match tool.run(tctx, args.clone()).await {
+        Ok(res) => res,
+        Err(e) => {
+            // record failures with their args so they can be retried later
+            ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: tool.name().into(), summary: format!("error: {}", e), status: ToolStatus::for_error(&e).as_str().into(), error: Some(e.to_string()), args: Some(args), created_at: Utc::now() }).await?;
+            return Err(e);
+        }
+    };
+    ctx.repo.append_tool_event(session_id, ToolEvent { id: Uuid::new_v4(), tool: tool.name().into(), summary: res.summary.clone(), status: res.status.as_str().into(), error: None, args: Some(args), created_at: Utc::now() }).await?;
+    Ok(json!({ "status": res.status, "summary": res.summary, "data": res.data }))
+}
+
+/// Extracts a `{"tool": name, "args": {...}}` call from an assistant reply, optionally wrapped in
+/// a code fence. `None` means the reply is a final answer and the loop should stop.
+pub fn parse_tool_call(reply: &str) -> Option<(String, serde_json::Value)> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    if end < start { return None; }
+    let v: serde_json::Value = serde_json::from_str(&reply[start..=end]).ok()?;
+    let tool = v.get("tool")?.as_str()?.to_string();
+    let args = v.get("args").cloned().unwrap_or_else(|| json!({}));
+    Some((tool, args))
 }
 
 #[cfg(test)]
@@ -74,7 +109,10 @@ mod tests {
 
     async fn setup_session_with_root() -> (SqliteSessionRepository, Uuid, String, tempfile::TempDir) {
         let dir = tempdir().unwrap();
-        let root = dir.path().to_string_lossy().to_string();
+        // the database sits beside the project root, so it never shows up in git status
+        let project = dir.path().join("project");
+        fs::create_dir(&project).unwrap();
+        let root = project.to_string_lossy().to_string();
         let db_path = dir.path().join("test.db");
         let url = format!("sqlite://{}", db_path.to_string_lossy());
         let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
@@ -101,6 +139,18 @@ mod tests {
         assert_eq!(c, 1);
     }
 
+    #[tokio::test]
+    async fn include_file_can_normalize_crlf() {
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        fs::write(std::path::Path::new(&root).join("win.txt"), b"a\r\nb\r\n").unwrap();
+        let args = serde_json::json!({"path": "win.txt", "normalize_newlines": true});
+        dispatch_tool(AgentContext { repo: &repo }, sid, "include_file", args).await.unwrap();
+        let items = repo.list_context_items(sid).await.unwrap();
+        assert_eq!(items[0].content_excerpt, "a\nb\n");
+        let read = dispatch_tool(AgentContext { repo: &repo }, sid, "discovery.read", serde_json::json!({"path": "win.txt"})).await.unwrap();
+        assert_eq!(read["data"]["content"], "a\r\nb\r\n");
+    }
+
     #[tokio::test]
     async fn tool_add_rule_system_and_repo() {
         let (repo, sid, root, _dir) = setup_session_with_root().await;
@@ -136,6 +186,54 @@ mod tests {
         assert!(!std::path::Path::new(&root).join("dir/y.txt").exists());
     }
 
+    #[tokio::test]
+    async fn read_only_session_rejects_writes_but_allows_reads() {
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let mut s = repo.get_session(sid).await.unwrap().unwrap();
+        s.settings.read_only = Some(true);
+        repo.update_settings(sid, s.settings.clone()).await.unwrap();
+        fs::write(std::path::Path::new(&root).join("a.txt"), b"keep").unwrap();
+
+        let err = dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", serde_json::json!({"path": "a.txt", "content": "changed", "dry_run": false})).await.unwrap_err();
+        assert!(err.downcast_ref::<ReadOnlySession>().is_some());
+        assert_eq!(fs::read_to_string(std::path::Path::new(&root).join("a.txt")).unwrap(), "keep");
+
+        let v = dispatch_tool(AgentContext { repo: &repo }, sid, "discovery.read", serde_json::json!({"path": "a.txt"})).await.unwrap();
+        assert_eq!(v["data"]["content"].as_str().unwrap(), "keep");
+    }
+
+    #[tokio::test]
+    async fn failed_tool_event_can_be_replayed_with_stored_args() {
+        use crate::storage::ToolEventFilter;
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let args = serde_json::json!({"path": "later.txt", "max_bytes": 64});
+        assert!(dispatch_tool(AgentContext { repo: &repo }, sid, "discovery.read", args.clone()).await.is_err());
+
+        let failed = repo.get_tool_events(sid, &ToolEventFilter { status: Some("error".into()), tool: None }).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].args, Some(args));
+
+        // the transient cause goes away; replay the stored call
+        fs::write(std::path::Path::new(&root).join("later.txt"), b"now here").unwrap();
+        let ev = failed[0].clone();
+        let v = dispatch_tool(AgentContext { repo: &repo }, sid, &ev.tool, ev.args.unwrap()).await.unwrap();
+        assert_eq!(v["data"]["content"].as_str().unwrap(), "now here");
+    }
+
+    #[tokio::test]
+    async fn tool_events_record_call_args() {
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        fs::write(std::path::Path::new(&root).join("a.txt"), b"hello").unwrap();
+        let args = serde_json::json!({"path": "a.txt", "max_bytes": 64});
+        dispatch_tool(AgentContext { repo: &repo }, sid, "discovery.read", args.clone()).await.unwrap();
+        execute(AgentContext { repo: &repo }, EngineCommand::AddRuleSystem { session_id: sid, name: "style", content: "Be terse." }).await.unwrap();
+
+        let s = repo.get_session(sid).await.unwrap().unwrap();
+        assert_eq!(s.tool_history.len(), 2);
+        assert_eq!(s.tool_history[0].args, Some(args));
+        assert_eq!(s.tool_history[1].args.as_ref().unwrap()["name"], "style");
+    }
+
     #[tokio::test]
     async fn tool_discovery_list_search() {
         let (repo, sid, root, _dir) = setup_session_with_root().await;
@@ -190,14 +288,224 @@ mod tests {
         let st = dispatch_tool(AgentContext { repo: &repo }, sid, "git.status", serde_json::json!({})).await.unwrap();
         assert!(st["data"].as_array().unwrap().iter().any(|e| e["path"].as_str().unwrap().ends_with("a.txt")));
         // add and commit
-        let _ = dispatch_tool(AgentContext { repo: &repo }, sid, "git.add_all", serde_json::json!({})).await.unwrap();
-        let cm = dispatch_tool(AgentContext { repo: &repo }, sid, "git.commit", serde_json::json!({"message": "test"})).await.unwrap();
+        let _ = dispatch_tool(AgentContext { repo: &repo }, sid, "git.add_all", serde_json::json!({"dry_run": false})).await.unwrap();
+        let cm = dispatch_tool(AgentContext { repo: &repo }, sid, "git.commit", serde_json::json!({"message": "test", "dry_run": false})).await.unwrap();
         assert!(cm["data"]["commit"].as_str().unwrap().len() > 5);
         // diff should be non-empty only if there are uncommitted changes
         let df = dispatch_tool(AgentContext { repo: &repo }, sid, "git.diff", serde_json::json!({})).await.unwrap();
         let diff_str = df["data"]["diff"].as_str().unwrap();
         assert!(diff_str.is_empty() || diff_str.contains("diff --git"));
     }
+
+    #[tokio::test]
+    async fn commit_changes_commits_only_files_the_tools_wrote() {
+        use git2::Repository;
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let _r = Repository::init(&root).unwrap();
+        std::fs::write(std::path::Path::new(&root).join("human.txt"), b"mine").unwrap();
+        let _ = dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", serde_json::json!({"path": "agent.txt", "content": "x", "dry_run": false})).await.unwrap();
+        assert_eq!(repo.written_files(sid).await.unwrap(), vec!["agent.txt"]);
+
+        let cm = dispatch_tool(AgentContext { repo: &repo }, sid, "git.commit_changes", serde_json::json!({"message": "agent", "dry_run": false})).await.unwrap();
+        assert_eq!(cm["data"]["paths"], serde_json::json!(["agent.txt"]));
+        assert!(repo.written_files(sid).await.unwrap().is_empty());
+        let st = crate::git_ops::status(&root).unwrap();
+        assert_eq!(st.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), vec!["human.txt"]);
+        assert!(dispatch_tool(AgentContext { repo: &repo }, sid, "git.commit_changes", serde_json::json!({"message": "again", "dry_run": false})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn file_history_tool_lists_commits_for_path() {
+        use git2::Repository;
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let _r = Repository::init(&root).unwrap();
+        fs::write(std::path::Path::new(&root).join("a.txt"), b"one").unwrap();
+        crate::git_ops::add_all(&root).unwrap();
+        let first = crate::git_ops::commit(&root, "add a").unwrap();
+        fs::write(std::path::Path::new(&root).join("b.txt"), b"two").unwrap();
+        crate::git_ops::add_all(&root).unwrap();
+        crate::git_ops::commit(&root, "add b").unwrap();
+        let out = dispatch_tool(AgentContext { repo: &repo }, sid, "git.file_history", serde_json::json!({"path": "a.txt"})).await.unwrap();
+        assert_eq!(out["data"].as_array().unwrap().len(), 1);
+        assert_eq!(out["data"][0]["id"], first);
+        assert_eq!(out["data"][0]["summary"], "add a");
+        assert!(dispatch_tool(AgentContext { repo: &repo }, sid, "git.file_history", serde_json::json!({})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn add_text_stores_labelled_context_within_budget() {
+        let (repo, sid, _root, _dir) = setup_session_with_root().await;
+        let args = serde_json::json!({"label": "stack trace", "text": "panicked at src/lib.rs:3"});
+        let out = dispatch_tool(AgentContext { repo: &repo }, sid, "context.add_text", args.clone()).await.unwrap();
+        assert_eq!(out["data"]["refreshed"], false);
+        let again = dispatch_tool(AgentContext { repo: &repo }, sid, "context.add_text", args).await.unwrap();
+        assert_eq!(again["data"]["refreshed"], true);
+        let items = repo.list_context_items(sid).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].kind.as_str(), items[0].key.as_str()), ("text", "stack trace"));
+
+        let mut s = repo.get_session(sid).await.unwrap().unwrap();
+        s.settings.context_budget_bytes = Some(30);
+        repo.update_settings(sid, s.settings).await.unwrap();
+        let err = dispatch_tool(AgentContext { repo: &repo }, sid, "context.add_text", serde_json::json!({"label": "spec", "text": "too much text"})).await.unwrap_err();
+        assert!(err.to_string().contains("context budget exceeded"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn add_text_enforces_token_budget_when_set() {
+        let (repo, sid, _root, _dir) = setup_session_with_root().await;
+        let mut s = repo.get_session(sid).await.unwrap().unwrap();
+        let text = "word ".repeat(40);
+        s.settings.context_budget_tokens = Some(crate::tokens::estimate_tokens(&text) as u64);
+        repo.update_settings(sid, s.settings).await.unwrap();
+        let out = dispatch_tool(AgentContext { repo: &repo }, sid, "context.add_text", serde_json::json!({"label": "a", "text": text})).await.unwrap();
+        assert_eq!(out["data"]["budget"]["used_tokens"], out["data"]["budget"]["max_tokens"]);
+        let err = dispatch_tool(AgentContext { repo: &repo }, sid, "context.add_text", serde_json::json!({"label": "b", "text": "one more"})).await.unwrap_err();
+        assert!(err.to_string().contains("tokens"), "{err}");
+        // re-adding the same text only refreshes it, so a full budget doesn't refuse it
+        let again = dispatch_tool(AgentContext { repo: &repo }, sid, "context.add_text", serde_json::json!({"label": "a", "text": text})).await.unwrap();
+        assert_eq!(again["data"]["refreshed"], true);
+    }
+
+    #[tokio::test]
+    async fn write_requires_preview_token_when_session_asks() {
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let mut s = repo.get_session(sid).await.unwrap().unwrap();
+        s.settings.require_confirm_token = Some(true);
+        repo.update_settings(sid, s.settings).await.unwrap();
+        let write = |extra: serde_json::Value| {
+            let mut args = serde_json::json!({"path": "a.txt", "content": "new"});
+            args.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            args
+        };
+
+        assert!(dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", write(serde_json::json!({"dry_run": false}))).await.is_err());
+        let preview = dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", write(serde_json::json!({"dry_run": true}))).await.unwrap();
+        let token = preview["data"]["output"]["confirm_token"].clone();
+        dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", write(serde_json::json!({"dry_run": false, "confirm_token": token}))).await.unwrap();
+        assert_eq!(fs::read_to_string(std::path::Path::new(&root).join("a.txt")).unwrap(), "new");
+
+        let delete = |extra: serde_json::Value| {
+            let mut args = serde_json::json!({"path": "a.txt"});
+            args.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            args
+        };
+        assert!(dispatch_tool(AgentContext { repo: &repo }, sid, "files.delete", delete(serde_json::json!({"dry_run": false}))).await.is_err());
+        let preview = dispatch_tool(AgentContext { repo: &repo }, sid, "files.delete", delete(serde_json::json!({"dry_run": true}))).await.unwrap();
+        let token = preview["data"]["output"]["confirm_token"].clone();
+        dispatch_tool(AgentContext { repo: &repo }, sid, "files.delete", delete(serde_json::json!({"dry_run": false, "confirm_token": token}))).await.unwrap();
+        assert!(!std::path::Path::new(&root).join("a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn tool_events_record_typed_status() {
+        use crate::storage::ToolEventFilter;
+        let (repo, sid, _root, _dir) = setup_session_with_root().await;
+        let preview = dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", serde_json::json!({"path": "a.txt", "content": "x", "dry_run": true})).await.unwrap();
+        assert_eq!(preview["status"], "no_op");
+        let applied = dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", serde_json::json!({"path": "a.txt", "content": "x", "dry_run": false})).await.unwrap();
+        assert_eq!(applied["status"], "ok");
+
+        let mut s = repo.get_session(sid).await.unwrap().unwrap();
+        s.settings.require_confirm_token = Some(true);
+        repo.update_settings(sid, s.settings).await.unwrap();
+        assert!(dispatch_tool(AgentContext { repo: &repo }, sid, "files.write", serde_json::json!({"path": "a.txt", "content": "y", "dry_run": false})).await.is_err());
+
+        let status = |st: &str| ToolEventFilter { status: Some(st.into()), tool: None };
+        assert_eq!(repo.get_tool_events(sid, &status("no_op")).await.unwrap().len(), 1);
+        assert_eq!(repo.get_tool_events(sid, &status("ok")).await.unwrap().len(), 1);
+        assert_eq!(repo.get_tool_events(sid, &status("needs_approval")).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn search_reports_progress_when_channel_given() {
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        for i in 0..1500 {
+            fs::write(std::path::Path::new(&root).join(format!("f{}.txt", i)), b"x").unwrap();
+        }
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let out = dispatch_tool_with_progress(AgentContext { repo: &repo }, sid, "discovery.search", serde_json::json!({"pattern": "f1", "max": 10000}), Some(tx)).await.unwrap();
+        assert!(!out["data"].as_array().unwrap().is_empty());
+        let ev = rx.recv().await.unwrap();
+        assert_eq!(ev.tool, "discovery.search");
+        assert_eq!(ev.message, "scanned 1000 files");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn git_tools_preview_by_default() {
+        use git2::Repository;
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let _r = Repository::init(&root).unwrap();
+        std::fs::write(std::path::Path::new(&root).join("a.txt"), b"content").unwrap();
+
+        let add = dispatch_tool(AgentContext { repo: &repo }, sid, "git.add_all", serde_json::json!({})).await.unwrap();
+        assert_eq!(add["data"]["dry_run"], true);
+        let cm = dispatch_tool(AgentContext { repo: &repo }, sid, "git.commit", serde_json::json!({"message": "m"})).await.unwrap();
+        assert_eq!(cm["data"]["dry_run"], true);
+        assert_eq!(crate::git_ops::head_oid(&root).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn git_tools_preview_under_dry_run_policy() {
+        use git2::Repository;
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        let _r = Repository::init(&root).unwrap();
+        std::fs::write(std::path::Path::new(&root).join("a.txt"), b"content").unwrap();
+        let mut s = repo.get_session(sid).await.unwrap().unwrap();
+        s.settings.tool_policies = Some(crate::settings::ToolPolicies { dry_run: Some(true), max_read_bytes: None });
+        repo.update_settings(sid, s.settings).await.unwrap();
+
+        let add = dispatch_tool(AgentContext { repo: &repo }, sid, "git.add_all", serde_json::json!({})).await.unwrap();
+        assert_eq!(add["data"]["dry_run"], true);
+        assert!(add["data"]["would_stage"].as_array().unwrap().iter().any(|p| p == "a.txt"));
+        let cm = dispatch_tool(AgentContext { repo: &repo }, sid, "git.commit", serde_json::json!({"message": "m"})).await.unwrap();
+        assert_eq!(cm["data"]["dry_run"], true);
+        assert_eq!(crate::git_ops::head_oid(&root).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn scripted_model_drives_read_write_done_loop() {
+        use crate::models::{LanguageModel, ModelRequest, ScriptedModel};
+        let (repo, sid, root, _dir) = setup_session_with_root().await;
+        fs::write(std::path::Path::new(&root).join("a.txt"), b"old").unwrap();
+        let model = ScriptedModel::new(vec![
+            r#"{"tool": "discovery.read", "args": {"path": "a.txt"}}"#,
+            r#"{"tool": "files.write", "args": {"path": "a.txt", "content": "new", "dry_run": false}}"#,
+            "Done: a.txt updated.",
+        ]);
+
+        let mut prompt = "update a.txt".to_string();
+        let mut turns = 0;
+        loop {
+            turns += 1;
+            let reply = model.generate(ModelRequest { model: "scripted".into(), prompt: prompt.clone(), ..Default::default() }).await.unwrap();
+            let Some((tool, args)) = parse_tool_call(&reply.content) else { break };
+            let out = dispatch_tool(AgentContext { repo: &repo }, sid, &tool, args).await.unwrap();
+            prompt = format!("Tool {} returned:\n{}", tool, out);
+        }
+
+        assert_eq!(turns, 3);
+        assert_eq!(fs::read_to_string(std::path::Path::new(&root).join("a.txt")).unwrap(), "new");
+        {
+            let prompts = model.prompts.lock().unwrap();
+            assert!(prompts[1].contains("discovery.read") && prompts[1].contains("old"));
+        }
+        let events = repo.get_tool_events(sid, &Default::default()).await.unwrap();
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn parse_tool_call_handles_fences_and_final_answers() {
+        let (tool, args) = parse_tool_call("```json\n{\"tool\": \"discovery.read\", \"args\": {\"path\": \"a.rs\"}}\n```").unwrap();
+        assert_eq!(tool, "discovery.read");
+        assert_eq!(args["path"], "a.rs");
+        let (tool, args) = parse_tool_call("{\"tool\": \"git.status\"}").unwrap();
+        assert_eq!(tool, "git.status");
+        assert_eq!(args, json!({}));
+        assert!(parse_tool_call("All done, the bug is fixed.").is_none());
+        assert!(parse_tool_call("{\"answer\": 42}").is_none());
+    }
 }
 
 fn slugify(name: &str) -> String {
@@ -206,5 +514,3 @@ fn slugify(name: &str) -> String {
     while s.contains("--") { s = s.replace("--", "-"); }
     s.trim_matches('-').to_string()
 }
-
-
@@ -0,0 +1,73 @@
+use super::{Tool, ToolContext, ToolResult, ToolStatus};
+use serde_json::Value;
+
+pub struct RemoveTool;
+pub struct AddTextTool;
+
+impl Tool for RemoveTool {
+    fn name(&self) -> &'static str { "context.remove" }
+    fn mutating(&self) -> bool { true }
+
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let item_id = args.get("item_id").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing item_id"))?;
+            let item_id = uuid::Uuid::parse_str(item_id)?;
+            if !ctx.repo.delete_context_item(ctx.session_id, item_id).await? {
+                anyhow::bail!("context item not found: {}", item_id);
+            }
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("removed context item:{}", item_id), data: Some(serde_json::json!({"item_id": item_id, "removed": true})) })
+        })
+    }
+}
+
+impl Tool for AddTextTool {
+    fn name(&self) -> &'static str { "context.add_text" }
+    fn mutating(&self) -> bool { true }
+
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let label = args.get("label").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing label"))?;
+            let text = args.get("text").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing text"))?;
+            let data = add_text(&ctx, label, text).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("text:{} bytes:{}", label, text.len()), data: Some(data) })
+        })
+    }
+}
+
+/// Stores `text` as a `text` context item keyed by `label`, refusing it when it
+/// would push the session past its context byte budget (or token budget, when
+/// set). Identical text under the same label is refreshed rather than stored twice,
+/// and since that adds nothing it is never refused.
+pub async fn add_text(ctx: &ToolContext<'_>, label: &str, text: &str) -> anyhow::Result<Value> {
+    if label.trim().is_empty() { anyhow::bail!("label must not be empty"); }
+    if text.is_empty() { anyhow::bail!("text must not be empty"); }
+    if ctx.repo.find_context_item(ctx.session_id, "text", label, text).await?.is_none() {
+        check_budget(ctx, text).await?;
+    }
+    let inserted = ctx.repo.add_context_item(ctx.session_id, "text", label, text, text.len() as i64).await?;
+    let budget = budget(ctx).await?;
+    Ok(serde_json::json!({"label": label, "bytes": text.len(), "refreshed": !inserted, "budget": budget}))
+}
+
+async fn check_budget(ctx: &ToolContext<'_>, text: &str) -> anyhow::Result<()> {
+    let usage = ctx.repo.context_usage(ctx.session_id).await?;
+    let max_bytes = ctx.settings.context_budget_bytes.unwrap_or(crate::settings::DEFAULT_CONTEXT_BUDGET_BYTES);
+    if usage.used_bytes + text.len() as u64 > max_bytes {
+        anyhow::bail!("context budget exceeded: {} used + {} new > {} bytes", usage.used_bytes, text.len(), max_bytes);
+    }
+    if let Some(max_tokens) = ctx.settings.context_budget_tokens {
+        let new_tokens = crate::tokens::estimate_tokens(text) as u64;
+        if usage.used_tokens + new_tokens > max_tokens {
+            anyhow::bail!("context budget exceeded: {} used + {} new > {} tokens", usage.used_tokens, new_tokens, max_tokens);
+        }
+    }
+    Ok(())
+}
+
+/// `{used_bytes, max_bytes, used_tokens, max_tokens, item_count}` for the session, as returned by
+/// include tools and `/context/budget`. `max_tokens` is null unless `context_budget_tokens` is set.
+pub async fn budget(ctx: &ToolContext<'_>) -> anyhow::Result<Value> {
+    let usage = ctx.repo.context_usage(ctx.session_id).await?;
+    let max_bytes = ctx.settings.context_budget_bytes.unwrap_or(crate::settings::DEFAULT_CONTEXT_BUDGET_BYTES);
+    Ok(serde_json::json!({"used_bytes": usage.used_bytes, "max_bytes": max_bytes, "used_tokens": usage.used_tokens, "max_tokens": ctx.settings.context_budget_tokens, "item_count": usage.item_count}))
+}
@@ -1,18 +1,54 @@
-use super::{Tool, ToolContext, ToolResult};
+use super::{blocking, Tool, ToolContext, ToolResult, ToolStatus};
+use crate::discovery::{GrepLimits, PatternOptions, WalkOptions};
+use crate::settings::global_defaults;
 use serde_json::Value;
 
 pub struct ListTool;
 pub struct SearchTool;
 pub struct ReadTool;
+pub struct GrepTool;
+pub struct TreeTool;
+
+const SEARCH_PROGRESS_EVERY: usize = 1000;
+
+fn walk_options(args: &Value) -> WalkOptions {
+    WalkOptions {
+        include_vcs: args.get("include_vcs").and_then(|v| v.as_bool()).unwrap_or(false),
+        classify: args.get("classify").and_then(|v| v.as_bool()).unwrap_or(false),
+        ..Default::default()
+    }
+}
+
+fn pattern_options(args: &Value) -> PatternOptions {
+    PatternOptions {
+        ignore_case: args.get("ignore_case").and_then(|v| v.as_bool()).unwrap_or(false),
+        whole_word: args.get("whole_word").and_then(|v| v.as_bool()).unwrap_or(false),
+    }
+}
 
 impl Tool for ListTool {
     fn name(&self) -> &'static str { "discovery.list" }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
-            let max = args.get("max").and_then(|v| v.as_u64()).unwrap_or(500) as usize;
-            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let items = crate::discovery::list_files(&root, max);
-            Ok(ToolResult { summary: format!("{} items", items.len()), data: Some(serde_json::to_value(items)?) })
+            let max = args.get("max").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(global_defaults().discovery().max_entries);
+            let root = ctx.settings.read_root().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let opts = walk_options(&args);
+            let items = blocking(move || Ok(crate::discovery::list_files_with(&root, max, opts))).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{} items", items.len()), data: Some(serde_json::to_value(items)?) })
+        })
+    }
+}
+
+impl Tool for TreeTool {
+    fn name(&self) -> &'static str { "discovery.tree" }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let max = args.get("max").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(global_defaults().discovery().max_entries);
+            let depth = args.get("depth").and_then(|v| v.as_u64()).map(|d| d as usize).unwrap_or(crate::discovery::DEFAULT_TREE_DEPTH);
+            let root = ctx.settings.read_root().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let opts = walk_options(&args);
+            let tree = blocking(move || Ok(crate::discovery::tree(&root, depth, max, opts))).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{} items truncated:{}", tree.entries.len(), tree.truncated), data: Some(serde_json::to_value(tree)?) })
         })
     }
 }
@@ -21,11 +57,16 @@ impl Tool for SearchTool {
     fn name(&self) -> &'static str { "discovery.search" }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
-            let max = args.get("max").and_then(|v| v.as_u64()).unwrap_or(500) as usize;
+            let max = args.get("max").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(global_defaults().discovery().max_entries);
             let pattern = args.get("pattern").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing pattern"))?;
-            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let items = crate::discovery::search_files(&root, pattern, max);
-            Ok(ToolResult { summary: format!("{} matches", items.len()), data: Some(serde_json::to_value(items)?) })
+            let root = ctx.settings.read_root().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let opts = walk_options(&args);
+            let (pattern, matching) = (pattern.to_string(), pattern_options(&args));
+            let report = ctx.reporter(self.name());
+            let items = blocking(move || {
+                crate::discovery::search_files_with_progress(&root, &pattern, matching, max, opts, SEARCH_PROGRESS_EVERY, |n| report(format!("scanned {} files", n)))
+            }).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{} matches", items.len()), data: Some(serde_json::to_value(items)?) })
         })
     }
 }
@@ -35,12 +76,33 @@ impl Tool for ReadTool {
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let path = args.get("path").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing path"))?;
-            let max_bytes = args.get("max_bytes").and_then(|v| v.as_u64()).unwrap_or(65536) as usize;
-            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let content = crate::discovery::read_file_under_root(&root, path, max_bytes)?;
-            Ok(ToolResult { summary: format!("read:{} bytes:{}", path, content.len()), data: Some(serde_json::json!({"path": path, "content": content})) })
+            let max_bytes = args.get("max_bytes").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(global_defaults().discovery().read_bytes);
+            let normalize = args.get("normalize_newlines").and_then(|v| v.as_bool()).unwrap_or(false);
+            let root = ctx.settings.read_root().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let p = path.to_string();
+            let content = blocking(move || crate::discovery::read_file_under_root_with(&root, &p, max_bytes, normalize)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("read:{} bytes:{}", path, content.len()), data: Some(serde_json::json!({"path": path, "content": content})) })
         })
     }
 }
 
-
+impl Tool for GrepTool {
+    fn name(&self) -> &'static str { "discovery.grep" }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let defaults = global_defaults().discovery();
+            let pattern = args.get("pattern").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing pattern"))?;
+            let limits = GrepLimits {
+                max_matches: args.get("max").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(defaults.max_entries),
+                max_file_bytes: args.get("max_file_bytes").and_then(|v| v.as_u64()).unwrap_or(defaults.grep_max_file_bytes),
+                max_total_bytes: defaults.grep_max_total_bytes,
+            };
+            let opts = walk_options(&args);
+            let root = ctx.settings.read_root().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let (pattern, matching) = (pattern.to_string(), pattern_options(&args));
+            let report = blocking(move || crate::discovery::grep_files(&root, &pattern, matching, opts, limits)).await?;
+            let summary = format!("{} matches, {} skipped{}", report.matches.len(), report.skipped.len(), if report.truncated { " (truncated)" } else { "" });
+            Ok(ToolResult { status: ToolStatus::Ok, summary, data: Some(serde_json::to_value(report)?) })
+        })
+    }
+}
@@ -1,51 +1,135 @@
-use super::{Tool, ToolContext, ToolResult};
+use super::{blocking, Tool, ToolContext, ToolResult, ToolStatus};
+use crate::file_ops::Confirm;
+use crate::settings::{global_defaults, resolve_dry_run};
 use serde_json::Value;
 
 pub struct WriteTool;
 pub struct MoveTool;
 pub struct DeleteTool;
+pub struct TouchTool;
+pub struct RestoreTool;
+
+/// Remembers paths an applied change touched, so `git.commit_changes` can commit
+/// just those.
+async fn track(ctx: &ToolContext<'_>, applied: bool, paths: &[&str]) -> anyhow::Result<()> {
+    if !applied { return Ok(()); }
+    for path in paths {
+        ctx.repo.record_written_file(ctx.session_id, path).await?;
+    }
+    Ok(())
+}
+
+/// `Some(token)` when the change must carry its preview's `confirm_token` before it is
+/// applied (`require_confirm_token`); the inner token may still be missing.
+fn required_token(ctx: &ToolContext<'_>, args: &Value, dry_run: bool) -> Option<Option<String>> {
+    (!dry_run && ctx.settings.require_confirm_token.unwrap_or(false))
+        .then(|| args.get("confirm_token").and_then(|v| v.as_str()).map(String::from))
+}
 
 impl Tool for WriteTool {
     fn name(&self) -> &'static str { "files.write" }
+    fn mutating(&self) -> bool { true }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let path = args.get("path").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing path"))?;
             let content = args.get("content").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing content"))?;
             let create = args.get("create").and_then(|v| v.as_bool()).unwrap_or(true);
             let preview_bytes = args.get("preview_bytes").and_then(|v| v.as_u64()).unwrap_or(1024) as usize;
-            let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or_else(|| ctx.settings.tool_policies.as_ref().and_then(|p| p.dry_run).unwrap_or(true));
+            let dry_run = resolve_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool()));
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let res = crate::file_ops::write_file_under_root(&root, path, content, create, dry_run, preview_bytes)?;
-            Ok(ToolResult { summary: format!("write:{} applied:{}", path, res.applied), data: Some(serde_json::to_value(res)?) })
+            let atomic = args.get("atomic").and_then(|v| v.as_bool()).unwrap_or(true);
+            let token = required_token(&ctx, &args, dry_run);
+            let (p, content) = (path.to_string(), content.to_string());
+            let res = blocking(move || {
+                if let Some(token) = &token {
+                    crate::file_ops::check_confirm_token(&root, Confirm::Write { rel: &p, content: &content }, token.as_deref())?;
+                }
+                crate::file_ops::write_file_under_root_with_mode(&root, &p, &content, create, dry_run, preview_bytes, atomic)
+            }).await?;
+            track(&ctx, res.applied, &[path]).await?;
+            Ok(ToolResult { status: ToolStatus::applied(res.applied), summary: format!("write:{} applied:{}", path, res.applied), data: Some(serde_json::to_value(res)?) })
         })
     }
 }
 
 impl Tool for MoveTool {
     fn name(&self) -> &'static str { "files.move" }
+    fn mutating(&self) -> bool { true }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let from = args.get("from").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing from"))?;
             let to = args.get("to").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing to"))?;
-            let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or_else(|| ctx.settings.tool_policies.as_ref().and_then(|p| p.dry_run).unwrap_or(true));
+            let dry_run = resolve_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool()));
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let res = crate::file_ops::move_file_under_root(&root, from, to, dry_run)?;
-            Ok(ToolResult { summary: format!("move:{} -> {} applied:{}", from, to, res.applied), data: Some(serde_json::to_value(res)?) })
+            let preview_bytes = args.get("preview_bytes").and_then(|v| v.as_u64()).unwrap_or(1024) as usize;
+            let token = required_token(&ctx, &args, dry_run);
+            let (f, t) = (from.to_string(), to.to_string());
+            let res = blocking(move || {
+                if let Some(token) = &token {
+                    crate::file_ops::check_confirm_token(&root, Confirm::Move { from: &f, to: &t }, token.as_deref())?;
+                }
+                crate::file_ops::move_file_under_root(&root, &f, &t, dry_run, preview_bytes)
+            }).await?;
+            track(&ctx, res.applied, &[from, to]).await?;
+            Ok(ToolResult { status: ToolStatus::applied(res.applied), summary: format!("move:{} -> {} applied:{}", from, to, res.applied), data: Some(serde_json::to_value(res)?) })
         })
     }
 }
 
 impl Tool for DeleteTool {
     fn name(&self) -> &'static str { "files.delete" }
+    fn mutating(&self) -> bool { true }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let path = args.get("path").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing path"))?;
-            let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or_else(|| ctx.settings.tool_policies.as_ref().and_then(|p| p.dry_run).unwrap_or(true));
+            let dry_run = resolve_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool()));
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let res = crate::file_ops::delete_file_under_root(&root, path, dry_run)?;
-            Ok(ToolResult { summary: format!("delete:{} applied:{}", path, res.applied), data: Some(serde_json::to_value(res)?) })
+            let recursive = args.get("recursive").and_then(|v| v.as_bool()).unwrap_or(false);
+            let preview_bytes = args.get("preview_bytes").and_then(|v| v.as_u64()).unwrap_or(1024) as usize;
+            let token = required_token(&ctx, &args, dry_run);
+            let (p, soft) = (path.to_string(), ctx.settings.soft_delete());
+            let res = blocking(move || {
+                if let Some(token) = &token {
+                    crate::file_ops::check_confirm_token(&root, Confirm::Delete { rel: &p }, token.as_deref())?;
+                }
+                crate::file_ops::delete_file_under_root(&root, &p, recursive, soft, dry_run, preview_bytes)
+            }).await?;
+            track(&ctx, res.applied, &[path]).await?;
+            Ok(ToolResult { status: ToolStatus::applied(res.applied), summary: format!("delete:{} files:{} applied:{}", path, res.output.count, res.applied), data: Some(serde_json::to_value(res)?) })
         })
     }
 }
 
+impl Tool for TouchTool {
+    fn name(&self) -> &'static str { "files.touch" }
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let path = args.get("path").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing path"))?;
+            let ok_if_exists = args.get("ok_if_exists").and_then(|v| v.as_bool()).unwrap_or(false);
+            let dry_run = resolve_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool()));
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let p = path.to_string();
+            let res = blocking(move || crate::file_ops::touch_file_under_root(&root, &p, ok_if_exists, dry_run)).await?;
+            track(&ctx, res.applied, &[path]).await?;
+            Ok(ToolResult { status: ToolStatus::applied(res.applied), summary: format!("touch:{} applied:{}", path, res.applied), data: Some(serde_json::to_value(res)?) })
+        })
+    }
+}
 
+impl Tool for RestoreTool {
+    fn name(&self) -> &'static str { "files.restore" }
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let trashed = args.get("trashed_as").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing trashed_as"))?;
+            let to = args.get("to").and_then(|v| v.as_str());
+            let dry_run = resolve_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool()));
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let (t, to) = (trashed.to_string(), to.map(String::from));
+            let res = blocking(move || crate::file_ops::restore_from_trash(&root, &t, to.as_deref(), dry_run)).await?;
+            track(&ctx, res.applied, &[res.output.as_str()]).await?;
+            Ok(ToolResult { status: ToolStatus::applied(res.applied), summary: format!("restore:{} -> {} applied:{}", trashed, res.output, res.applied), data: Some(serde_json::to_value(res)?) })
+        })
+    }
+}
@@ -1,54 +1,211 @@
-use super::{Tool, ToolContext, ToolResult};
+use super::{blocking, Tool, ToolContext, ToolResult, ToolStatus};
+use crate::git_ops::PathBase;
+use crate::settings::{global_defaults, resolve_commit_trailers, resolve_git_dry_run, resolve_git_remote_dry_run};
 use serde_json::Value;
 
 pub struct StatusTool;
 pub struct DiffTool;
+pub struct FileHistoryTool;
 pub struct AddAllTool;
 pub struct CommitTool;
+pub struct CommitChangesTool;
+pub struct PushTool;
+pub struct FetchTool;
+pub struct PullTool;
+pub struct MergeTool;
+
+/// `paths` arg: `"project_root"` (default) or `"repo"`.
+fn path_base(args: &Value) -> anyhow::Result<PathBase> {
+    match args.get("paths") {
+        None | Some(Value::Null) => Ok(PathBase::ProjectRoot),
+        Some(v) => Ok(serde_json::from_value(v.clone())?),
+    }
+}
 
 impl Tool for StatusTool {
     fn name(&self) -> &'static str { "git.status" }
-    fn run<'a>(&'a self, ctx: ToolContext<'a>, _args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let st = crate::git_ops::status(&root)?;
-            Ok(ToolResult { summary: format!("{} entries", st.len()), data: Some(serde_json::to_value(st)?) })
+            let base = path_base(&args)?;
+            let st = blocking(move || crate::git_ops::status_with(&root, base)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{} entries", st.len()), data: Some(serde_json::to_value(st)?) })
         })
     }
 }
 
 impl Tool for DiffTool {
     fn name(&self) -> &'static str { "git.diff" }
-    fn run<'a>(&'a self, ctx: ToolContext<'a>, _args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let d = crate::git_ops::diff_porcelain(&root)?;
-            Ok(ToolResult { summary: format!("{} chars", d.len()), data: Some(serde_json::json!({"diff": d})) })
+            let base = path_base(&args)?;
+            let d = blocking(move || crate::git_ops::diff_with(&root, base)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{} chars", d.len()), data: Some(serde_json::json!({"diff": d})) })
+        })
+    }
+}
+
+impl Tool for FileHistoryTool {
+    fn name(&self) -> &'static str { "git.file_history" }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let path = args.get("path").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing path"))?.to_string();
+            let max = args.get("max").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(crate::git_ops::DEFAULT_LOG_MAX);
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let summary_path = path.clone();
+            let commits = blocking(move || crate::git_ops::file_history(&root, &path, max)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{}: {} commits", summary_path, commits.len()), data: Some(serde_json::to_value(commits)?) })
         })
     }
 }
 
 impl Tool for AddAllTool {
     fn name(&self) -> &'static str { "git.add_all" }
-    fn run<'a>(&'a self, ctx: ToolContext<'a>, _args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            crate::git_ops::add_all(&root)?;
-            Ok(ToolResult { summary: "git add -A".into(), data: Some(serde_json::json!({"ok": true})) })
+            if resolve_git_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                let paths = blocking(move || crate::git_ops::preview_add_all(&root)).await?;
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("git add -A (dry run): {} paths", paths.len()), data: Some(serde_json::json!({"dry_run": true, "would_stage": paths})) });
+            }
+            blocking(move || crate::git_ops::add_all(&root)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: "git add -A".into(), data: Some(serde_json::json!({"ok": true})) })
         })
     }
 }
 
 impl Tool for CommitTool {
     fn name(&self) -> &'static str { "git.commit" }
+    fn mutating(&self) -> bool { true }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let message = args.get("message").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing message"))?;
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let oid = crate::git_ops::commit(&root, message)?;
-            Ok(ToolResult { summary: format!("commit:{}", oid), data: Some(serde_json::json!({"commit": oid})) })
+            // ad-hoc trailers: {"trailers": {"Co-authored-by": "Name <email>"}}
+            let extra = match args.get("trailers") {
+                Some(t) => serde_json::from_value(t.clone()).map_err(|_| anyhow::anyhow!("trailers must be an object of strings"))?,
+                None => Default::default(),
+            };
+            let trailers = resolve_commit_trailers(ctx.settings, &extra);
+            if resolve_git_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                let message = crate::git_ops::with_trailers(message, &trailers)?;
+                let preview = blocking(move || crate::git_ops::preview_commit(&root, &message)).await?;
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("commit (dry run): {} staged", preview.staged.len()), data: Some(serde_json::json!({"dry_run": true, "preview": preview})) });
+            }
+            let signing = crate::git_ops::CommitSigning::from_settings(ctx.settings)?;
+            let message = message.to_string();
+            let oid = blocking(move || crate::git_ops::commit_signed(&root, &message, &trailers, signing.as_ref())).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("commit:{}", oid), data: Some(serde_json::json!({"commit": oid})) })
         })
     }
 }
 
+impl Tool for CommitChangesTool {
+    fn name(&self) -> &'static str { "git.commit_changes" }
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let message = args.get("message").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing message"))?;
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let extra = match args.get("trailers") {
+                Some(t) => serde_json::from_value(t.clone()).map_err(|_| anyhow::anyhow!("trailers must be an object of strings"))?,
+                None => Default::default(),
+            };
+            let trailers = resolve_commit_trailers(ctx.settings, &extra);
+            let paths = ctx.repo.written_files(ctx.session_id).await?;
+            if paths.is_empty() { anyhow::bail!("no files written in this session"); }
+            if resolve_git_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("commit (dry run): {} tracked paths", paths.len()), data: Some(serde_json::json!({"dry_run": true, "paths": paths})) });
+            }
+            let signing = crate::git_ops::CommitSigning::from_settings(ctx.settings)?;
+            let (message, p) = (message.to_string(), paths.clone());
+            let oid = blocking(move || crate::git_ops::commit_paths(&root, &p, &message, &trailers, signing.as_ref())).await?;
+            ctx.repo.clear_written_files(ctx.session_id).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("commit:{} ({} paths)", oid, paths.len()), data: Some(serde_json::json!({"commit": oid, "paths": paths})) })
+        })
+    }
+}
+
+fn remote_arg(args: &Value) -> String {
+    args.get("remote").and_then(|v| v.as_str()).unwrap_or("origin").to_string()
+}
 
+impl Tool for PushTool {
+    fn name(&self) -> &'static str { "git.push" }
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let remote = remote_arg(&args);
+            let refspec = args.get("refspec").and_then(|v| v.as_str()).map(String::from);
+            if resolve_git_remote_dry_run(ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                let (r, spec) = (remote.clone(), refspec.clone());
+                let preview = blocking(move || crate::git_ops::preview_push(&root, &r, spec.as_deref())).await?;
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("push {} {} (dry run)", remote, preview.refspec), data: Some(serde_json::json!({"dry_run": true, "preview": preview})) });
+            }
+            let r = remote.clone();
+            let pushed = blocking(move || crate::git_ops::push(&root, &r, refspec.as_deref())).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("push {} {}", remote, pushed), data: Some(serde_json::json!({"remote": remote, "refspec": pushed})) })
+        })
+    }
+}
+
+impl Tool for FetchTool {
+    fn name(&self) -> &'static str { "git.fetch" }
+    // updates remote-tracking refs
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let remote = remote_arg(&args);
+            if resolve_git_remote_dry_run(ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("fetch {} (dry run)", remote), data: Some(serde_json::json!({"dry_run": true, "remote": remote})) });
+            }
+            let r = remote.clone();
+            let received = blocking(move || crate::git_ops::fetch(&root, &r)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("fetch {}: {} objects", remote, received), data: Some(serde_json::json!({"remote": remote, "received_objects": received})) })
+        })
+    }
+}
+
+impl Tool for PullTool {
+    fn name(&self) -> &'static str { "git.pull" }
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let remote = remote_arg(&args);
+            if resolve_git_remote_dry_run(ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("pull {} (dry run)", remote), data: Some(serde_json::json!({"dry_run": true, "remote": remote})) });
+            }
+            let r = remote.clone();
+            let outcome = blocking(move || crate::git_ops::pull(&root, &r)).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("pull {}: {:?}", remote, outcome), data: Some(serde_json::to_value(&outcome)?) })
+        })
+    }
+}
+
+impl Tool for MergeTool {
+    fn name(&self) -> &'static str { "git.merge" }
+    fn mutating(&self) -> bool { true }
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let branch = args.get("branch").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing branch"))?;
+            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            if resolve_git_dry_run(global_defaults(), ctx.settings, args.get("dry_run").and_then(|v| v.as_bool())) {
+                return Ok(ToolResult { status: ToolStatus::NoOp, summary: format!("merge {} (dry run)", branch), data: Some(serde_json::json!({"dry_run": true, "branch": branch})) });
+            }
+            let b = branch.to_string();
+            let outcome = blocking(move || crate::git_ops::merge(&root, &b)).await?;
+            let summary = match &outcome {
+                crate::git_ops::MergeOutcome::UpToDate => format!("merge {}: up to date", branch),
+                crate::git_ops::MergeOutcome::FastForward { to } => format!("merge {}: fast-forward to {}", branch, to),
+                crate::git_ops::MergeOutcome::Merged { commit } => format!("merge {}: {}", branch, commit),
+                crate::git_ops::MergeOutcome::Conflicts { files } => format!("merge {}: {} conflicted files", branch, files.len()),
+            };
+            Ok(ToolResult { status: ToolStatus::Ok, summary, data: Some(serde_json::to_value(&outcome)?) })
+        })
+    }
+}
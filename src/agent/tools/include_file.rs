@@ -1,21 +1,82 @@
-use super::{Tool, ToolContext, ToolResult};
+use super::{blocking, Tool, ToolContext, ToolResult, ToolStatus};
 use serde_json::Value;
 
 pub struct IncludeFileTool;
 
 impl Tool for IncludeFileTool {
     fn name(&self) -> &'static str { "include_file" }
+    fn mutating(&self) -> bool { true }
 
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
             let path = args.get("path").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing path"))?;
-            let max_bytes = args.get("max_bytes").and_then(|v| v.as_u64()).unwrap_or(65536) as usize;
-            let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
-            let content = crate::discovery::read_file_under_root(&root, path, max_bytes)?;
-            ctx.repo.add_context_item(ctx.session_id, "file", path, &content, content.len() as i64).await?;
-            Ok(ToolResult { summary: format!("file:{} bytes:{}", path, content.len()), data: Some(serde_json::json!({"path": path, "bytes": content.len()})) })
+            let max_bytes = args.get("max_bytes").and_then(|v| v.as_u64()).map(|m| m as usize).unwrap_or(crate::settings::global_defaults().discovery().read_bytes);
+            let normalize = args.get("normalize_newlines").and_then(|v| v.as_bool()).unwrap_or(false);
+            let root = ctx.settings.read_root().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
+            let p = path.to_string();
+            let content = blocking(move || crate::discovery::read_file_under_root_with(&root, &p, max_bytes, normalize)).await?;
+            let Some(chunk_bytes) = args.get("chunk_bytes").and_then(|v| v.as_u64()).map(|n| n as usize) else {
+                let inserted = ctx.repo.add_context_item(ctx.session_id, "file", path, &content, content.len() as i64).await?;
+                let budget = super::context_tools::budget(&ctx).await?;
+                return Ok(ToolResult { status: ToolStatus::Ok, summary: format!("file:{} bytes:{}", path, content.len()), data: Some(serde_json::json!({"path": path, "bytes": content.len(), "refreshed": !inserted, "budget": budget})) });
+            };
+            let overlap = args.get("chunk_overlap").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            let max_chunks = args.get("max_chunks").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_MAX_CHUNKS as u64) as usize;
+            let mut chunks = chunk_text(&content, chunk_bytes, overlap);
+            let truncated = chunks.len() > max_chunks;
+            chunks.truncate(max_chunks);
+            for (i, chunk) in chunks.iter().enumerate() {
+                let meta = serde_json::json!({"source": path, "chunk_index": i, "chunk_count": chunks.len()});
+                ctx.repo.add_context_item_with_metadata(ctx.session_id, "file", path, chunk, chunk.len() as i64, Some(meta)).await?;
+            }
+            let budget = super::context_tools::budget(&ctx).await?;
+            Ok(ToolResult {
+                status: ToolStatus::Ok,
+                summary: format!("file:{} bytes:{} chunks:{}", path, content.len(), chunks.len()),
+                data: Some(serde_json::json!({"path": path, "bytes": content.len(), "chunks": chunks.len(), "truncated": truncated, "budget": budget})),
+            })
         })
     }
 }
 
+const DEFAULT_MAX_CHUNKS: usize = 32;
+
+/// Splits `content` into pieces of at most `size` bytes, each starting `overlap` bytes before the
+/// previous one ended. Boundaries are moved back to the nearest char boundary.
+pub(crate) fn chunk_text(content: &str, size: usize, overlap: usize) -> Vec<&str> {
+    let size = size.max(1);
+    let overlap = overlap.min(size - 1);
+    let floor = |mut i: usize| { while !content.is_char_boundary(i) { i -= 1; } i };
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < content.len() {
+        let mut end = floor((start + size).min(content.len()));
+        if end <= start {
+            // a single char wider than `size`; take it whole
+            end = start + content[start..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+        out.push(&content[start..end]);
+        if end == content.len() { break; }
+        let next = floor(end.saturating_sub(overlap));
+        start = if next > start { next } else { end };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_text_overlaps_and_respects_char_boundaries() {
+        assert_eq!(chunk_text("abcdefgh", 4, 0), vec!["abcd", "efgh"]);
+        assert_eq!(chunk_text("abcdefgh", 4, 2), vec!["abcd", "cdef", "efgh"]);
+        assert_eq!(chunk_text("abcde", 4, 0), vec!["abcd", "e"]);
+        assert!(chunk_text("", 4, 0).is_empty());
+        for c in chunk_text("h\u{e9}llo w\u{f6}rld", 3, 1) {
+            assert!(c.len() <= 3);
+        }
+    }
+}
+
 
@@ -1,10 +1,11 @@
-use super::{Tool, ToolContext, ToolResult};
+use super::{Tool, ToolContext, ToolResult, ToolStatus};
 use serde_json::Value;
 
 pub struct IncludeUrlTool;
 
 impl Tool for IncludeUrlTool {
     fn name(&self) -> &'static str { "include_url" }
+    fn mutating(&self) -> bool { true }
 
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
@@ -12,10 +13,17 @@ impl Tool for IncludeUrlTool {
             let max_bytes = args.get("max_bytes").and_then(|v| v.as_u64()).unwrap_or(262144) as usize;
             let parsed = url::Url::parse(url)?;
             let host = parsed.host_str().ok_or_else(|| anyhow::anyhow!("invalid host"))?;
-            if !crate::server::is_allowed_host(&ctx.settings.network_allowlist, host) { anyhow::bail!("host not allowlisted"); }
-            let content = crate::server::fetch_and_extract(url, max_bytes).await?;
-            ctx.repo.add_context_item(ctx.session_id, "url", url, &content, content.len() as i64).await?;
-            Ok(ToolResult { summary: format!("url:{} bytes:{}", url, content.len()), data: Some(serde_json::json!({"url": url, "bytes": content.len()})) })
+            if !crate::net::is_allowed_host(&ctx.settings.network_allowlist, host) { anyhow::bail!("host not allowlisted"); }
+            let headers: Vec<(String, String)> = args.get("headers").and_then(|v| v.as_object())
+                .map(|m| m.iter().filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string()))).collect())
+                .unwrap_or_default();
+            crate::net::check_fetch_headers(&ctx.settings.network_allowlist, host, &headers)?;
+            let page = crate::net::fetch_and_extract(url, max_bytes, &headers, &ctx.settings.network_allowlist).await?;
+            let content = page.content;
+            let metadata = serde_json::json!({"title": page.title, "final_url": page.final_url});
+            ctx.repo.add_context_item_with_metadata(ctx.session_id, "url", url, &content, content.len() as i64, Some(metadata)).await?;
+            let budget = super::context_tools::budget(&ctx).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("url:{} bytes:{}", url, content.len()), data: Some(serde_json::json!({"url": url, "bytes": content.len(), "title": page.title, "final_url": page.final_url, "budget": budget})) })
         })
     }
 }
@@ -1,5 +1,7 @@
 use async_trait::async_trait;
+use serde::Serialize;
 use serde_json::Value;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 use crate::settings::SessionSettings;
@@ -11,21 +13,98 @@ pub mod rules;
 pub mod discovery_tools;
 pub mod file_tools;
 pub mod git_tools;
+pub mod context_tools;
+#[cfg(feature = "embeddings")]
+pub mod retrieve;
 
 pub struct ToolContext<'a> {
     pub repo: &'a dyn SessionRepository,
     pub session_id: Uuid,
     pub settings: &'a SessionSettings,
+    /// Set when the caller streams progress; tools that don't report ignore it.
+    pub progress: Option<mpsc::Sender<ProgressEvent>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+    pub tool: String,
+    pub message: String,
+}
+
+impl ToolContext<'_> {
+    /// Best-effort progress update; dropped rather than blocking the tool when the receiver lags.
+    pub fn report(&self, tool: &str, message: impl Into<String>) {
+        if let Some(tx) = &self.progress {
+            let _ = tx.try_send(ProgressEvent { tool: tool.to_string(), message: message.into() });
+        }
+    }
+
+    /// Owned [`report`](Self::report) for work moved onto a blocking thread.
+    pub fn reporter(&self, tool: &'static str) -> impl Fn(String) + Send + 'static {
+        let progress = self.progress.clone();
+        move |message| {
+            if let Some(tx) = &progress {
+                let _ = tx.try_send(ProgressEvent { tool: tool.to_string(), message });
+            }
+        }
+    }
+}
+
+/// Runs synchronous filesystem/git2 work on tokio's blocking pool so a slow disk
+/// or a large diff doesn't stall the async workers.
+pub(crate) async fn blocking<T: Send + 'static>(f: impl FnOnce() -> crate::error::Result<T> + Send + 'static) -> anyhow::Result<T> {
+    Ok(tokio::task::spawn_blocking(f).await??)
 }
 
 pub struct ToolResult {
+    pub status: ToolStatus,
     pub summary: String,
     pub data: Option<Value>,
 }
 
+/// Outcome of a tool run, stored as the tool event's `status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolStatus {
+    Ok,
+    /// Nothing changed, e.g. a dry-run preview.
+    NoOp,
+    /// Refused until the caller confirms, e.g. a write without its preview's `confirm_token`.
+    NeedsApproval,
+    Error,
+    Cancelled,
+}
+
+impl ToolStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ToolStatus::Ok => "ok",
+            ToolStatus::NoOp => "no_op",
+            ToolStatus::NeedsApproval => "needs_approval",
+            ToolStatus::Error => "error",
+            ToolStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// `Ok` for a change that was made, `NoOp` for a preview.
+    pub fn applied(applied: bool) -> Self {
+        if applied { ToolStatus::Ok } else { ToolStatus::NoOp }
+    }
+
+    /// Status recorded for a tool that failed with `e`.
+    pub fn for_error(e: &anyhow::Error) -> Self {
+        match e.downcast_ref::<crate::error::Error>() {
+            Some(crate::error::Error::ConfirmationRequired(_)) => ToolStatus::NeedsApproval,
+            _ => ToolStatus::Error,
+        }
+    }
+}
+
 #[async_trait]
 pub trait Tool: Send + Sync {
     fn name(&self) -> &'static str;
+    /// Whether the tool changes session, file, or repo state; such tools are refused on read-only sessions.
+    fn mutating(&self) -> bool { false }
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>>;
 }
 
@@ -40,16 +119,32 @@ impl ToolRegistry {
         r.register(Box::new(include_file::IncludeFileTool));
         r.register(Box::new(include_url::IncludeUrlTool));
         r.register(Box::new(rules::AddRuleTool));
+        r.register(Box::new(rules::ListRulesTool));
+        r.register(Box::new(rules::DeleteRuleTool));
         r.register(Box::new(discovery_tools::ListTool));
         r.register(Box::new(discovery_tools::SearchTool));
         r.register(Box::new(discovery_tools::ReadTool));
+        r.register(Box::new(discovery_tools::GrepTool));
+        r.register(Box::new(discovery_tools::TreeTool));
         r.register(Box::new(file_tools::WriteTool));
         r.register(Box::new(file_tools::MoveTool));
         r.register(Box::new(file_tools::DeleteTool));
+        r.register(Box::new(file_tools::TouchTool));
+        r.register(Box::new(file_tools::RestoreTool));
         r.register(Box::new(git_tools::StatusTool));
         r.register(Box::new(git_tools::DiffTool));
+        r.register(Box::new(git_tools::FileHistoryTool));
         r.register(Box::new(git_tools::AddAllTool));
         r.register(Box::new(git_tools::CommitTool));
+        r.register(Box::new(git_tools::CommitChangesTool));
+        r.register(Box::new(git_tools::PushTool));
+        r.register(Box::new(git_tools::FetchTool));
+        r.register(Box::new(git_tools::PullTool));
+        r.register(Box::new(git_tools::MergeTool));
+        r.register(Box::new(context_tools::RemoveTool));
+        r.register(Box::new(context_tools::AddTextTool));
+        #[cfg(feature = "embeddings")]
+        r.register(Box::new(retrieve::RetrieveTool::new(std::sync::Arc::new(crate::embeddings::OpenAIEmbedder::from_env()))));
         r
     }
     pub fn register(&mut self, t: Box<dyn Tool>) { self.tools.push(t); }
@@ -58,4 +153,8 @@ impl ToolRegistry {
     }
 }
 
+impl Default for ToolRegistry {
+    fn default() -> Self { Self::new() }
+}
+
 
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::{Tool, ToolContext, ToolResult, ToolStatus};
+use crate::embeddings::{cosine_similarity, Embedder};
+use serde_json::Value;
+
+pub struct RetrieveTool {
+    embedder: Arc<dyn Embedder>,
+}
+
+impl RetrieveTool {
+    pub fn new(embedder: Arc<dyn Embedder>) -> Self { Self { embedder } }
+}
+
+impl Tool for RetrieveTool {
+    fn name(&self) -> &'static str { "context.retrieve" }
+
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let query = args.get("query").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing query"))?;
+            let k = args.get("k").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+            let model = self.embedder.model();
+
+            // embed any items added since the last retrieval
+            let items = ctx.repo.list_context_items(ctx.session_id).await?;
+            let embedded: HashSet<_> = ctx.repo.list_context_embeddings(ctx.session_id, model).await?.into_iter().map(|(id, _)| id).collect();
+            let missing: Vec<_> = items.iter().filter(|i| !embedded.contains(&i.id)).collect();
+            if !missing.is_empty() {
+                let inputs: Vec<String> = missing.iter().map(|i| i.content_excerpt.clone()).collect();
+                let vectors = self.embedder.embed(&inputs).await?;
+                for (item, v) in missing.iter().zip(vectors) {
+                    ctx.repo.set_context_embedding(item.id, model, &v).await?;
+                }
+            }
+
+            let q = self.embedder.embed(&[query.to_string()]).await?.pop().ok_or_else(|| anyhow::anyhow!("empty query embedding"))?;
+            let mut scored: Vec<(f32, uuid::Uuid)> = ctx.repo.list_context_embeddings(ctx.session_id, model).await?
+                .into_iter()
+                .map(|(id, v)| (cosine_similarity(&q, &v), id))
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+            scored.truncate(k);
+
+            let results: Vec<Value> = scored.iter().filter_map(|(score, id)| {
+                items.iter().find(|i| i.id == *id).map(|i| serde_json::json!({
+                    "id": i.id,
+                    "kind": i.kind,
+                    "key": i.key,
+                    "score": score,
+                    "content": i.content_excerpt,
+                }))
+            }).collect();
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("retrieved:{} query:{}", results.len(), query), data: Some(serde_json::json!({"results": results})) })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SessionSettings;
+    use crate::storage::{SessionRepository, SqliteSessionRepository};
+    use async_trait::async_trait;
+    use tempfile::tempdir;
+
+    // maps text onto [contains "alpha", contains "beta"]
+    struct KeywordEmbedder;
+
+    #[async_trait]
+    impl Embedder for KeywordEmbedder {
+        fn model(&self) -> &str { "keyword" }
+        async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(inputs.iter().map(|s| vec![s.contains("alpha") as u8 as f32, s.contains("beta") as u8 as f32]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn retrieve_ranks_by_similarity() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("t.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let settings = SessionSettings::default();
+        let sid = repo.create_session(None, settings.clone()).await.unwrap();
+        repo.add_context_item(sid, "file", "a.rs", "alpha only", 10).await.unwrap();
+        repo.add_context_item(sid, "file", "b.rs", "beta only", 9).await.unwrap();
+
+        let tool = RetrieveTool::new(Arc::new(KeywordEmbedder));
+        let ctx = ToolContext { repo: &repo, session_id: sid, settings: &settings, progress: None };
+        let out = tool.run(ctx, serde_json::json!({"query": "beta", "k": 1})).await.unwrap();
+        let results = out.data.unwrap()["results"].as_array().unwrap().clone();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["key"], "b.rs");
+        assert_eq!(repo.list_context_embeddings(sid, "keyword").await.unwrap().len(), 2);
+    }
+}
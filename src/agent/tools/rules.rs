@@ -1,10 +1,15 @@
-use super::{Tool, ToolContext, ToolResult};
+use super::{Tool, ToolContext, ToolResult, ToolStatus};
 use serde_json::Value;
 
+pub const DEFAULT_REPO_RULES_DIR: &str = ".cursor/rules";
+/// Upper bound on rule text injected into a system prompt.
+pub const MAX_REPO_RULE_BYTES: usize = 32 * 1024;
+
 pub struct AddRuleTool;
 
 impl Tool for AddRuleTool {
     fn name(&self) -> &'static str { "add_rule" }
+    fn mutating(&self) -> bool { true }
 
     fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
         Box::pin(async move {
@@ -13,14 +18,43 @@ impl Tool for AddRuleTool {
             let system = args.get("system").and_then(|v| v.as_bool()).unwrap_or(false);
             if system {
                 ctx.repo.upsert_rule(name, content).await?;
-                return Ok(ToolResult { summary: format!("system rule:{}", name), data: None });
+                return Ok(ToolResult { status: ToolStatus::Ok, summary: format!("system rule:{}", name), data: None });
             }
-            let repo_dir = args.get("repo_dir").and_then(|v| v.as_str()).unwrap_or(".cursor/rules");
+            let repo_dir = args.get("repo_dir").and_then(|v| v.as_str()).unwrap_or(DEFAULT_REPO_RULES_DIR);
             let root = ctx.settings.project_root.clone().ok_or_else(|| anyhow::anyhow!("no project_root"))?;
             let path = std::path::Path::new(&root).join(repo_dir).join(format!("{}.md", slugify(name)));
-            std::fs::create_dir_all(path.parent().unwrap_or(std::path::Path::new(&root)))?;
-            std::fs::write(&path, content.as_bytes())?;
-            Ok(ToolResult { summary: format!("repo rule:{}", path.display()), data: None })
+            tokio::fs::create_dir_all(path.parent().unwrap_or(std::path::Path::new(&root))).await?;
+            tokio::fs::write(&path, content.as_bytes()).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("repo rule:{}", path.display()), data: None })
+        })
+    }
+}
+
+pub struct ListRulesTool;
+
+impl Tool for ListRulesTool {
+    fn name(&self) -> &'static str { "rules.list" }
+
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, _args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let rules = ctx.repo.list_rules().await?;
+            let data: Vec<Value> = rules.iter().map(|(name, content)| serde_json::json!({"name": name, "content": content})).collect();
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("{} system rules", rules.len()), data: Some(Value::Array(data)) })
+        })
+    }
+}
+
+pub struct DeleteRuleTool;
+
+impl Tool for DeleteRuleTool {
+    fn name(&self) -> &'static str { "rules.delete" }
+    fn mutating(&self) -> bool { true }
+
+    fn run<'a>(&'a self, ctx: ToolContext<'a>, args: Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<ToolResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let name = args.get("name").and_then(|v| v.as_str()).ok_or_else(|| anyhow::anyhow!("missing name"))?;
+            let deleted = ctx.repo.delete_rule(name).await?;
+            Ok(ToolResult { status: ToolStatus::Ok, summary: format!("system rule:{} deleted:{}", name, deleted), data: Some(serde_json::json!({"name": name, "deleted": deleted})) })
         })
     }
 }
@@ -32,4 +66,56 @@ fn slugify(name: &str) -> String {
     s.trim_matches('-').to_string()
 }
 
+/// Reads every `*.md` rule under `root/repo_dir`, sorted by file name. A missing directory yields no rules.
+pub fn load_repo_rules(root: &str, repo_dir: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let dir = std::path::Path::new(root).join(repo_dir);
+    if !dir.is_dir() { return Ok(Vec::new()); }
+    let mut rules = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") || !path.is_file() { continue; }
+        let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        rules.push((name, std::fs::read_to_string(&path)?));
+    }
+    rules.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(rules)
+}
+
+/// Concatenates rules into a system prompt, dropping whole rules once `max_bytes` would be exceeded.
+pub fn repo_rules_prompt(rules: &[(String, String)], max_bytes: usize) -> Option<String> {
+    let mut out = String::new();
+    for (name, content) in rules {
+        let section = format!("## Rule: {}\n{}\n\n", name, content.trim_end());
+        if out.len() + section.len() > max_bytes { break; }
+        out.push_str(&section);
+    }
+    if out.is_empty() { None } else { Some(out.trim_end().to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn load_repo_rules_reads_markdown_and_caps_prompt() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        assert!(load_repo_rules(&root, DEFAULT_REPO_RULES_DIR).unwrap().is_empty());
+
+        let rules_dir = dir.path().join(DEFAULT_REPO_RULES_DIR);
+        std::fs::create_dir_all(&rules_dir).unwrap();
+        std::fs::write(rules_dir.join("b-style.md"), "use tabs").unwrap();
+        std::fs::write(rules_dir.join("a-tests.md"), "write tests").unwrap();
+        std::fs::write(rules_dir.join("notes.txt"), "ignored").unwrap();
 
+        let rules = load_repo_rules(&root, DEFAULT_REPO_RULES_DIR).unwrap();
+        assert_eq!(rules, vec![("a-tests".to_string(), "write tests".to_string()), ("b-style".to_string(), "use tabs".to_string())]);
+
+        let full = repo_rules_prompt(&rules, MAX_REPO_RULE_BYTES).unwrap();
+        assert!(full.contains("write tests") && full.contains("use tabs"));
+        let capped = repo_rules_prompt(&rules, 30).unwrap();
+        assert!(capped.contains("write tests") && !capped.contains("use tabs"));
+        assert!(repo_rules_prompt(&rules, 5).is_none());
+    }
+}
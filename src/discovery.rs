@@ -1,49 +1,374 @@
-use ignore::WalkBuilder;
-use regex::Regex;
+use crate::error::{Error, Result};
+use ignore::{WalkBuilder, WalkState};
+use regex::{Regex, RegexBuilder};
 use serde::Serialize;
 use std::fs;
 use std::io::Read;
 use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Debug, Serialize)]
 pub struct FileEntry {
     pub path: String,
     pub is_dir: bool,
+    /// Set for files when listing with [`WalkOptions::classify`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<FileKind>,
 }
 
-pub fn list_files(root: &str, max: usize) -> Vec<FileEntry> {
+/// Rough content type of a file, from its extension or, failing that, its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Text,
+    Binary,
+    Image,
+    Unknown,
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp", "ico", "tif", "tiff", "svg", "avif", "heic"];
+const BINARY_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "so", "dylib", "o", "a", "lib", "rlib", "class", "jar", "wasm", "bin", "pyc",
+    "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "tar",
+    "pdf", "woff", "woff2", "ttf", "otf", "mp3", "mp4", "mov", "wav", "ogg", "flac", "sqlite", "db",
+];
+const TEXT_EXTENSIONS: &[&str] = &[
+    "rs", "toml", "lock", "md", "txt", "json", "yaml", "yml", "xml", "html", "css", "scss",
+    "js", "jsx", "ts", "tsx", "mjs", "py", "rb", "go", "java", "kt", "c", "h", "cc", "cpp", "hpp",
+    "cs", "swift", "sh", "bash", "zsh", "sql", "proto", "ini", "cfg", "conf", "env", "csv",
+];
+
+/// Bytes read to classify a file whose extension doesn't settle it.
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// Classifies by extension, sniffing the first [`SNIFF_BYTES`] for anything else:
+/// a NUL byte or invalid UTF-8 means binary.
+pub fn classify_file(path: &Path) -> FileKind {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase());
+    if let Some(ext) = ext.as_deref() {
+        if IMAGE_EXTENSIONS.contains(&ext) { return FileKind::Image; }
+        if BINARY_EXTENSIONS.contains(&ext) { return FileKind::Binary; }
+        if TEXT_EXTENSIONS.contains(&ext) { return FileKind::Text; }
+    }
+    let mut buf = Vec::with_capacity(SNIFF_BYTES);
+    let Ok(f) = fs::File::open(path) else { return FileKind::Unknown };
+    if f.take(SNIFF_BYTES as u64).read_to_end(&mut buf).is_err() { return FileKind::Unknown; }
+    if buf.contains(&0) { return FileKind::Binary; }
+    match std::str::from_utf8(&buf) {
+        Ok(_) => FileKind::Text,
+        // a multi-byte character cut off by the sniff window is still text
+        Err(e) if e.error_len().is_none() => FileKind::Text,
+        Err(_) => FileKind::Binary,
+    }
+}
+
+/// Version-control metadata directories skipped by discovery unless `include_vcs` is set.
+/// gitignore rules never cover the repo's own `.git`, so it has to be filtered explicitly.
+pub const VCS_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Knobs for the discovery walk; the default matches [`list_files`] / [`search_files`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkOptions {
+    /// Walk into `.git`, `.hg` and `.svn` directories.
+    pub include_vcs: bool,
+    /// Walker threads for list/search; 0 lets `ignore` pick, 1 walks serially.
+    pub threads: usize,
+    /// List only: fill in [`FileEntry::kind`] for files. May read the start of each file.
+    pub classify: bool,
+}
+
+/// Entries buffered between walker threads and the collector; workers block
+/// (and then quit) rather than racing far past `max`.
+const PARALLEL_CHANNEL_BOUND: usize = 256;
+
+/// Gitignore-aware walk that skips the soft-delete trash at the top of `root`
+/// and, unless `opts.include_vcs`, VCS metadata directories at any depth.
+fn walk_builder(root: &str, opts: WalkOptions) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(false)
+        .git_ignore(true)
+        .threads(opts.threads)
+        .filter_entry(move |e| {
+            let name = e.file_name();
+            if e.depth() == 1 && name == crate::file_ops::TRASH_DIR { return false; }
+            let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+            opts.include_vcs || !is_dir || !VCS_DIRS.iter().any(|d| name == *d)
+        });
+    builder
+}
+
+fn walk(root: &str, opts: WalkOptions) -> ignore::Walk {
+    walk_builder(root, opts).build()
+}
+
+enum Found {
+    Entry(FileEntry),
+    Scanned(usize),
+}
+
+/// Walks `root`, keeping entries for which `check` returns a [`FileEntry`], until
+/// `max` are collected. Unless `opts.threads == 1` the walk runs in parallel and
+/// workers stop as soon as the cap is hit. Results are sorted by path, since
+/// parallel visiting order isn't stable.
+fn collect_entries(
+    root: &str,
+    opts: WalkOptions,
+    max: usize,
+    every: usize,
+    on_progress: &mut dyn FnMut(usize),
+    check: impl Fn(&ignore::DirEntry) -> Option<FileEntry> + Sync,
+) -> Vec<FileEntry> {
+    let every = every.max(1);
     let mut out = Vec::new();
-    for res in WalkBuilder::new(root).hidden(false).git_ignore(true).build() {
-        if out.len() >= max { break; }
-        if let Ok(dirent) = res {
-            let path = dirent.path();
-            if path == PathBuf::from(root) { continue; }
-            out.push(FileEntry { path: path.to_string_lossy().to_string(), is_dir: path.is_dir() });
+    if max == 0 { return out; }
+    if opts.threads == 1 {
+        let mut scanned = 0usize;
+        for dirent in walk(root, opts).flatten() {
+            scanned += 1;
+            if scanned.is_multiple_of(every) { on_progress(scanned); }
+            if let Some(entry) = check(&dirent) {
+                out.push(entry);
+                if out.len() >= max { break; }
+            }
         }
+    } else {
+        let (tx, rx) = std::sync::mpsc::sync_channel::<Found>(PARALLEL_CHANNEL_BOUND);
+        let scanned = AtomicUsize::new(0);
+        let (check, scanned) = (&check, &scanned);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                walk_builder(root, opts).build_parallel().run(|| {
+                    let tx = tx.clone();
+                    Box::new(move |res| {
+                        let Ok(dirent) = res else { return WalkState::Continue };
+                        let n = scanned.fetch_add(1, Ordering::Relaxed) + 1;
+                        // a failed send means the collector has enough and hung up
+                        if n.is_multiple_of(every) && tx.send(Found::Scanned(n)).is_err() { return WalkState::Quit; }
+                        match check(&dirent) {
+                            Some(entry) => if tx.send(Found::Entry(entry)).is_err() { WalkState::Quit } else { WalkState::Continue },
+                            None => WalkState::Continue,
+                        }
+                    })
+                });
+            });
+            for found in rx.iter() {
+                match found {
+                    Found::Scanned(n) => on_progress(n),
+                    Found::Entry(entry) => {
+                        out.push(entry);
+                        if out.len() >= max { break; }
+                    }
+                }
+            }
+            drop(rx);
+        });
     }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
     out
 }
 
-pub fn search_files(root: &str, pattern: &str, max: usize) -> Vec<FileEntry> {
-    let re = Regex::new(pattern).ok();
-    let mut out = Vec::new();
-    for res in WalkBuilder::new(root).hidden(false).git_ignore(true).build() {
-        if out.len() >= max { break; }
-        if let (Some(re), Ok(dirent)) = (&re, res) {
-            let path = dirent.path();
-            let p = path.to_string_lossy();
-            if re.is_match(&p) {
-                out.push(FileEntry { path: p.to_string(), is_dir: path.is_dir() });
+pub fn list_files(root: &str, max: usize) -> Vec<FileEntry> {
+    list_files_with(root, max, WalkOptions::default())
+}
+
+pub fn list_files_with(root: &str, max: usize, opts: WalkOptions) -> Vec<FileEntry> {
+    collect_entries(root, opts, max, usize::MAX, &mut |_| {}, |dirent| {
+        if dirent.depth() == 0 { return None; }
+        let path = dirent.path();
+        let is_dir = path.is_dir();
+        let kind = (opts.classify && !is_dir).then(|| classify_file(path));
+        Some(FileEntry { path: path.to_string_lossy().to_string(), is_dir, kind })
+    })
+}
+
+/// Default `depth` for [`tree`].
+pub const DEFAULT_TREE_DEPTH: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct TreeEntry {
+    /// Relative to the root.
+    pub path: String,
+    /// 1 for direct children of the root.
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tree {
+    /// `tree`-style rendering, one entry per line, directories suffixed with `/`.
+    pub text: String,
+    pub entries: Vec<TreeEntry>,
+    /// Whether `max` cut the walk short.
+    pub truncated: bool,
+}
+
+/// Entries of `root` down to `depth` levels, in name order, rendered as an
+/// indented tree. Uses the same filtering as [`list_files_with`].
+pub fn tree(root: &str, depth: usize, max: usize, opts: WalkOptions) -> Tree {
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    let walk = walk_builder(root, opts).max_depth(Some(depth)).sort_by_file_name(|a, b| a.cmp(b)).build();
+    for dirent in walk.flatten() {
+        if dirent.depth() == 0 { continue; }
+        if entries.len() >= max {
+            truncated = true;
+            break;
+        }
+        let path = dirent.path();
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        entries.push(TreeEntry { path: rel.to_string_lossy().to_string(), depth: dirent.depth(), is_dir: dirent.file_type().is_some_and(|t| t.is_dir()) });
+    }
+    let text = render_tree(&entries, truncated);
+    Tree { text, entries, truncated }
+}
+
+fn render_tree(entries: &[TreeEntry], truncated: bool) -> String {
+    let mut text = String::from(".\n");
+    // whether the open ancestor at each depth was the last of its siblings
+    let mut last_at: Vec<bool> = Vec::new();
+    for (i, e) in entries.iter().enumerate() {
+        let is_last = entries[i + 1..].iter().find(|n| n.depth <= e.depth).is_none_or(|n| n.depth < e.depth);
+        last_at.truncate(e.depth - 1);
+        for &ancestor_last in &last_at {
+            text.push_str(if ancestor_last { "    " } else { "│   " });
+        }
+        text.push_str(if is_last { "└── " } else { "├── " });
+        let name = Path::new(&e.path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| e.path.clone());
+        text.push_str(&name);
+        if e.is_dir { text.push('/'); }
+        text.push('\n');
+        last_at.push(is_last);
+    }
+    if truncated { text.push_str("…\n"); }
+    text
+}
+
+/// How a search/grep pattern is compiled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PatternOptions {
+    pub ignore_case: bool,
+    /// Only match whole words, i.e. wrap the pattern in `\b...\b`.
+    pub whole_word: bool,
+}
+
+/// Compiles a user-supplied pattern, reporting a bad one as `InvalidInput("invalid regex: ...")`.
+pub fn compile_pattern(pattern: &str, opts: PatternOptions) -> Result<Regex> {
+    let source = if opts.whole_word { format!(r"\b(?:{})\b", pattern) } else { pattern.to_string() };
+    RegexBuilder::new(&source)
+        .case_insensitive(opts.ignore_case)
+        .build()
+        .map_err(|e| Error::InvalidInput(format!("invalid regex: {}", e)))
+}
+
+pub fn search_files(root: &str, pattern: &str, max: usize) -> Result<Vec<FileEntry>> {
+    search_files_with_progress(root, pattern, PatternOptions::default(), max, WalkOptions::default(), usize::MAX, |_| {})
+}
+
+/// Like [`search_files`], calling `on_progress(scanned)` after every `every` walked entries.
+pub fn search_files_with_progress(
+    root: &str,
+    pattern: &str,
+    matching: PatternOptions,
+    max: usize,
+    opts: WalkOptions,
+    every: usize,
+    mut on_progress: impl FnMut(usize),
+) -> Result<Vec<FileEntry>> {
+    let re = compile_pattern(pattern, matching)?;
+    Ok(collect_entries(root, opts, max, every, &mut on_progress, |dirent| {
+        let path = dirent.path();
+        let p = path.to_string_lossy();
+        re.is_match(&p).then(|| FileEntry { path: p.to_string(), is_dir: path.is_dir(), kind: None })
+    }))
+}
+
+/// Bounds for [`grep_files`]; see [`crate::settings::DiscoveryDefaults`] for the defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct GrepLimits {
+    pub max_matches: usize,
+    /// Files larger than this are skipped and listed in [`GrepReport::skipped`].
+    pub max_file_bytes: u64,
+    /// Once this many bytes have been read the scan stops with `truncated` set.
+    pub max_total_bytes: u64,
+}
+
+/// Matched lines are cut to this many characters so minified files don't flood results.
+const MAX_GREP_LINE_CHARS: usize = 500;
+
+#[derive(Debug, Serialize)]
+pub struct GrepMatch {
+    pub path: String,
+    /// 1-based line number.
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GrepReport {
+    pub matches: Vec<GrepMatch>,
+    pub skipped: Vec<SkippedFile>,
+    pub bytes_scanned: u64,
+    /// The match cap or byte budget ended the scan early.
+    pub truncated: bool,
+}
+
+/// Searches file contents line by line for `pattern`, within `limits`.
+pub fn grep_files(root: &str, pattern: &str, matching: PatternOptions, opts: WalkOptions, limits: GrepLimits) -> Result<GrepReport> {
+    let re = compile_pattern(pattern, matching)?;
+    let mut report = GrepReport::default();
+    for dirent in walk(root, opts).flatten() {
+        if !dirent.file_type().is_some_and(|t| t.is_file()) { continue; }
+        let path = dirent.path();
+        let Ok(meta) = dirent.metadata() else { continue };
+        if meta.len() > limits.max_file_bytes {
+            report.skipped.push(SkippedFile { path: path.to_string_lossy().to_string(), bytes: meta.len() });
+            continue;
+        }
+        if report.bytes_scanned + meta.len() > limits.max_total_bytes {
+            report.truncated = true;
+            break;
+        }
+        let Ok(bytes) = fs::read(path) else { continue };
+        report.bytes_scanned += bytes.len() as u64;
+        let text = String::from_utf8_lossy(&bytes);
+        for (i, line) in text.lines().enumerate() {
+            if !re.is_match(line) { continue; }
+            if report.matches.len() >= limits.max_matches {
+                report.truncated = true;
+                return Ok(report);
             }
+            report.matches.push(GrepMatch {
+                path: path.to_string_lossy().to_string(),
+                line: i + 1,
+                text: line.chars().take(MAX_GREP_LINE_CHARS).collect(),
+            });
         }
     }
-    out
+    Ok(report)
 }
 
 fn normalize_root(root: &str) -> Option<PathBuf> {
     let pb = PathBuf::from(root);
     let abs = if pb.is_absolute() { pb } else { std::env::current_dir().ok()?.join(pb) };
-    abs.canonicalize().ok()
+    abs.canonicalize().ok().filter(|p| p.is_dir())
+}
+
+/// Checks that `root` names an existing directory, for use when a project root is configured.
+pub fn validate_root(root: &str) -> Result<PathBuf> {
+    let pb = PathBuf::from(root);
+    let abs = if pb.is_absolute() { pb } else { std::env::current_dir()?.join(pb) };
+    let canonical = abs.canonicalize().map_err(|_| Error::InvalidInput(format!("project_root does not exist: {}", root)))?;
+    if !canonical.is_dir() {
+        return Err(Error::InvalidInput(format!("project_root is not a directory: {}", root)));
+    }
+    Ok(canonical)
 }
 
 pub fn resolve_under_root(root: &str, rel: &str) -> Option<PathBuf> {
@@ -73,16 +398,23 @@ pub fn resolve_under_root(root: &str, rel: &str) -> Option<PathBuf> {
     }
 }
 
-pub fn read_file_under_root(root: &str, rel: &str, max_bytes: usize) -> anyhow::Result<String> {
-    let path = resolve_under_root(root, rel).ok_or_else(|| anyhow::anyhow!("path outside root"))?;
+pub fn read_file_under_root(root: &str, rel: &str, max_bytes: usize) -> Result<String> {
+    read_file_under_root_with(root, rel, max_bytes, false)
+}
+
+/// [`read_file_under_root`], converting CRLF line endings to LF in the returned
+/// text when `normalize_newlines` is set. The file on disk is left as it is.
+pub fn read_file_under_root_with(root: &str, rel: &str, max_bytes: usize, normalize_newlines: bool) -> Result<String> {
+    let path = resolve_under_root(root, rel).ok_or_else(|| Error::PathOutsideRoot(rel.to_string()))?;
     let meta = fs::metadata(&path)?;
-    if !meta.is_file() { return Err(anyhow::anyhow!("not a file")); }
+    if !meta.is_file() { return Err(Error::InvalidInput(format!("not a file: {}", rel))); }
     let mut file = fs::File::open(&path)?;
     let mut buf = String::new();
     // Read up to max_bytes as UTF-8 (lossy on invalid sequences)
     let mut bytes = vec![0u8; max_bytes];
     let n = file.read(&mut bytes)?;
     buf = String::from_utf8_lossy(&bytes[..n]).to_string();
+    if normalize_newlines { buf = buf.replace("\r\n", "\n"); }
     Ok(buf)
 }
 
@@ -100,6 +432,20 @@ mod tests {
         assert!(outside.is_none());
     }
 
+    #[test]
+    fn file_or_missing_root_is_rejected() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, b"x").unwrap();
+        let file_root = file.to_string_lossy().to_string();
+
+        let err = validate_root(&file_root).unwrap_err();
+        assert!(err.to_string().contains("project_root is not a directory"), "{}", err);
+        assert!(validate_root(&dir.path().join("missing").to_string_lossy()).unwrap_err().to_string().contains("does not exist"));
+        assert!(validate_root(&dir.path().to_string_lossy()).is_ok());
+        assert!(resolve_under_root(&file_root, "a.txt").is_none());
+    }
+
     #[test]
     fn read_file_respects_limit() {
         let dir = tempdir().unwrap();
@@ -110,6 +456,149 @@ mod tests {
         let content = read_file_under_root(&root, "a.txt", 5).unwrap();
         assert!(content.len() <= 5);
     }
-}
 
+    #[test]
+    fn read_file_normalizes_crlf_only_when_asked() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("win.txt"), b"one\r\ntwo\r\n").unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        assert_eq!(read_file_under_root(&root, "win.txt", 64).unwrap(), "one\r\ntwo\r\n");
+        assert_eq!(read_file_under_root_with(&root, "win.txt", 64, true).unwrap(), "one\ntwo\n");
+        assert_eq!(fs::read(dir.path().join("win.txt")).unwrap(), b"one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn list_and_search_skip_trash() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".atc-trash/20240101T000000000Z")).unwrap();
+        fs::write(dir.path().join(".atc-trash/20240101T000000000Z/old.rs"), b"x").unwrap();
+        fs::write(dir.path().join("new.rs"), b"y").unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let listed = list_files(&root, 100);
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].path.ends_with("new.rs"));
+        assert!(search_files(&root, r"\.rs$", 100).unwrap().iter().all(|e| !e.path.contains(".atc-trash")));
+    }
+
+    #[test]
+    fn vcs_dirs_skipped_unless_included() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git/objects")).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), b"ref: refs/heads/main").unwrap();
+        fs::create_dir_all(dir.path().join("vendor/.svn")).unwrap();
+        fs::write(dir.path().join("vendor/lib.rs"), b"x").unwrap();
+        fs::write(dir.path().join(".gitignore"), b"").unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+
+        let listed = list_files(&root, 100);
+        assert!(listed.iter().all(|e| !e.path.contains(".git/") && !e.path.ends_with(".git") && !e.path.contains(".svn")));
+        assert!(listed.iter().any(|e| e.path.ends_with(".gitignore")));
+        assert!(listed.iter().any(|e| e.path.ends_with("lib.rs")));
+
+        let all = list_files_with(&root, 100, WalkOptions { include_vcs: true, ..Default::default() });
+        assert!(all.iter().any(|e| e.path.ends_with(".git/HEAD")));
+        assert!(all.iter().any(|e| e.path.ends_with(".svn")));
+    }
 
+    #[test]
+    fn grep_skips_large_files_and_stops_at_budget() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "alpha\nneedle one\n").unwrap();
+        fs::write(dir.path().join("big.log"), "needle\n".repeat(100)).unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+
+        let limits = GrepLimits { max_matches: 100, max_file_bytes: 64, max_total_bytes: 1024 };
+        let report = grep_files(&root, "needle", PatternOptions::default(), WalkOptions::default(), limits).unwrap();
+        assert_eq!(report.matches.len(), 1);
+        assert_eq!(report.matches[0].line, 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].path.ends_with("big.log"));
+        assert!(!report.truncated);
+
+        let tight = GrepLimits { max_total_bytes: 4, ..limits };
+        let report = grep_files(&root, "needle", PatternOptions::default(), WalkOptions::default(), tight).unwrap();
+        assert!(report.truncated);
+        assert!(report.matches.is_empty());
+
+        assert!(matches!(grep_files(&root, "(", PatternOptions::default(), WalkOptions::default(), limits), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn pattern_options_adjust_matching() {
+        let plain = compile_pattern("todo", PatternOptions::default()).unwrap();
+        assert!(!plain.is_match("TODO: fix"));
+        let ci = compile_pattern("todo", PatternOptions { ignore_case: true, whole_word: false }).unwrap();
+        assert!(ci.is_match("TODO: fix"));
+        let word = compile_pattern("log|err", PatternOptions { ignore_case: false, whole_word: true }).unwrap();
+        assert!(word.is_match("an err here"));
+        assert!(!word.is_match("catalog errors"));
+        assert!(matches!(compile_pattern("[", PatternOptions::default()), Err(Error::InvalidInput(m)) if m.starts_with("invalid regex")));
+    }
+
+    #[test]
+    fn parallel_walk_matches_serial_and_respects_cap() {
+        let dir = tempdir().unwrap();
+        for d in 0..8 {
+            fs::create_dir_all(dir.path().join(format!("d{}", d))).unwrap();
+            for f in 0..25 {
+                fs::write(dir.path().join(format!("d{}/f{}.txt", d, f)), b"x").unwrap();
+            }
+        }
+        let root = dir.path().to_string_lossy().to_string();
+        let serial = list_files_with(&root, usize::MAX, WalkOptions { threads: 1, ..Default::default() });
+        let parallel = list_files_with(&root, usize::MAX, WalkOptions { threads: 4, ..Default::default() });
+        assert_eq!(serial.len(), 8 + 8 * 25);
+        let paths = |v: &[FileEntry]| v.iter().map(|e| e.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&serial), paths(&parallel));
+
+        assert_eq!(list_files_with(&root, 10, WalkOptions { threads: 4, ..Default::default() }).len(), 10);
+        assert!(list_files(&root, 0).is_empty());
+        let found = search_files(&root, r"f1\.txt$", 100).unwrap();
+        assert_eq!(found.len(), 8);
+    }
+
+    #[test]
+    fn tree_renders_nested_entries_to_depth() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::create_dir_all(dir.path().join("src/bin")).unwrap();
+        fs::write(dir.path().join("src/bin/tool.rs"), b"").unwrap();
+        fs::write(dir.path().join("src/lib.rs"), b"").unwrap();
+        fs::write(dir.path().join("Cargo.toml"), b"").unwrap();
+
+        let t = tree(&root, 3, 100, WalkOptions::default());
+        assert!(!t.truncated);
+        assert_eq!(t.text, ".\n├── Cargo.toml\n└── src/\n    ├── bin/\n    │   └── tool.rs\n    └── lib.rs\n");
+        assert_eq!(t.entries[3].path, Path::new("src").join("bin").join("tool.rs").to_string_lossy());
+        assert_eq!(t.entries[3].depth, 3);
+
+        let shallow = tree(&root, 1, 100, WalkOptions::default());
+        assert_eq!(shallow.entries.len(), 2);
+        let capped = tree(&root, 3, 2, WalkOptions::default());
+        assert!(capped.truncated);
+        assert!(capped.text.ends_with("…\n"));
+    }
+
+    #[test]
+    fn classify_uses_extension_then_content() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::write(dir.path().join("logo.PNG"), b"not really a png").unwrap();
+        fs::write(dir.path().join("main.rs"), b"fn main() {}").unwrap();
+        fs::write(dir.path().join("Makefile"), b"all:\n\ttrue\n").unwrap();
+        fs::write(dir.path().join("blob"), [0x7f, b'E', b'L', b'F', 0, 0, 1]).unwrap();
+        fs::write(dir.path().join("latin1"), [b'c', b'a', b'f', 0xe9, b'!']).unwrap();
+        assert_eq!(classify_file(&dir.path().join("logo.PNG")), FileKind::Image);
+        assert_eq!(classify_file(&dir.path().join("main.rs")), FileKind::Text);
+        assert_eq!(classify_file(&dir.path().join("Makefile")), FileKind::Text);
+        assert_eq!(classify_file(&dir.path().join("blob")), FileKind::Binary);
+        assert_eq!(classify_file(&dir.path().join("latin1")), FileKind::Binary);
+        assert_eq!(classify_file(&dir.path().join("missing")), FileKind::Unknown);
+
+        let plain = list_files(&root, 100);
+        assert!(plain.iter().all(|e| e.kind.is_none()));
+        let classified = list_files_with(&root, 100, WalkOptions { classify: true, ..Default::default() });
+        let blob = classified.iter().find(|e| e.path.ends_with("blob")).unwrap();
+        assert_eq!(blob.kind, Some(FileKind::Binary));
+    }
+}
@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Model name vectors are stored under; vectors from different models are never compared.
+    fn model(&self) -> &str;
+    async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>>;
+}
+
+#[derive(Clone)]
+pub struct OpenAIEmbedder {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+impl OpenAIEmbedder {
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| "https://api.openai.com/v1".into());
+        let api_key = std::env::var("OPENAI_API_KEY").ok();
+        let model = std::env::var("ATC_EMBEDDING_MODEL").unwrap_or_else(|_| "text-embedding-3-small".into());
+        Self { base_url, api_key, model }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OaiEmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct OaiEmbeddingResponse {
+    data: Vec<OaiEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OaiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    fn model(&self) -> &str { &self.model }
+
+    async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        if inputs.is_empty() { return Ok(Vec::new()); }
+        let url = format!("{}/embeddings", self.base_url.trim_end_matches('/'));
+        let body = OaiEmbeddingRequest { model: &self.model, input: inputs };
+        let mut rb = crate::net::http_client().post(url).json(&body);
+        if let Some(key) = &self.api_key {
+            rb = rb.bearer_auth(key);
+        }
+        let resp = rb.send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("embeddings call failed: {}", resp.status());
+        }
+        let mut v: OaiEmbeddingResponse = resp.json().await?;
+        if v.data.len() != inputs.len() {
+            anyhow::bail!("embeddings call returned {} vectors for {} inputs", v.data.len(), inputs.len());
+        }
+        v.data.sort_by_key(|e| e.index);
+        Ok(v.data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+/// Cosine similarity in [-1, 1]; 0 for mismatched lengths or zero vectors.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() { return 0.0; }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 { return 0.0; }
+    dot / (na.sqrt() * nb.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_similarity_basics() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 2.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+    }
+}
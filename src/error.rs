@@ -0,0 +1,47 @@
+use crate::git_ops::RemoteAuthFailed;
+use crate::storage::ContextLimitExceeded;
+
+/// Failures from the core modules (`discovery`, `file_ops`, `git_ops`, `storage`).
+/// The CLI and HTTP layers wrap these in `anyhow` or map them to status codes.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A relative path resolved outside the session's project root.
+    #[error("path outside root: {0}")]
+    PathOutsideRoot(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("already exists: {0}")]
+    AlreadyExists(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    /// The repository isn't in a state the operation can run from (detached HEAD, merge in progress, ...).
+    #[error("invalid repository state: {0}")]
+    InvalidState(String),
+    #[error(transparent)]
+    ContextLimit(#[from] ContextLimitExceeded),
+    #[error(transparent)]
+    RemoteAuth(#[from] RemoteAuthFailed),
+    /// The remote was unreachable or refused the update.
+    #[error("remote {remote}: {message}")]
+    Remote { remote: String, message: String },
+    /// A write needed the `confirm_token` of a matching dry-run preview.
+    #[error("confirmation required: {0}")]
+    ConfirmationRequired(String),
+    #[error("signing failed: {0}")]
+    Signing(String),
+    /// Neither the root nor any directory above it holds a git repository.
+    #[error("not a git repository: {0}")]
+    NotARepository(String),
+    #[error("git: {0}")]
+    Git(#[from] git2::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("database: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("migration: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("serialization: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
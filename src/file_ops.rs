@@ -1,14 +1,44 @@
 use crate::discovery::resolve_under_root;
+use crate::error::{Error, Result};
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 #[derive(Debug, Serialize)]
 pub struct EditPreview {
     pub before_preview: String,
     pub after_preview: String,
+    /// The existing file is longer than `before_preview`.
+    pub before_truncated: bool,
+    /// The new content is longer than `after_preview`.
+    pub after_truncated: bool,
+    /// Set on dry runs; pass it back to apply this exact write when the session
+    /// has `require_confirm_token`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm_token: Option<String>,
+}
+
+/// A change a `confirm_token` vouches for.
+#[derive(Debug, Clone, Copy)]
+pub enum Confirm<'a> {
+    Write { rel: &'a str, content: &'a str },
+    Move { from: &'a str, to: &'a str },
+    Delete { rel: &'a str },
+}
+
+impl Confirm<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Confirm::Write { rel, .. } => format!("write to {}", rel),
+            Confirm::Move { from, to } => format!("move of {} to {}", from, to),
+            Confirm::Delete { rel } => format!("delete of {}", rel),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -17,11 +47,128 @@ pub struct OperationResult<T> {
     pub output: T,
 }
 
+/// At most `max_bytes` of `bytes`, cut back to a char boundary rather than
+/// splitting a multi-byte sequence.
 fn cap_utf8(mut bytes: Vec<u8>, max_bytes: usize) -> String {
-    if bytes.len() > max_bytes { bytes.truncate(max_bytes); }
+    if bytes.len() > max_bytes {
+        bytes.truncate(max_bytes);
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            // an incomplete sequence at the end has no error_len
+            if e.error_len().is_none() { bytes.truncate(e.valid_up_to()); }
+        }
+    }
     String::from_utf8_lossy(&bytes).to_string()
 }
 
+/// The first `max_bytes` of the file at `path`, without reading the rest, and
+/// whether the file is longer than that.
+fn read_capped(path: &Path, max_bytes: usize) -> Result<(String, bool)> {
+    let mut bytes = Vec::new();
+    // one byte past the cap tells whether anything was left out
+    fs::File::open(path)?.take(max_bytes as u64 + 1).read_to_end(&mut bytes)?;
+    let truncated = bytes.len() > max_bytes;
+    Ok((cap_utf8(bytes, max_bytes), truncated))
+}
+
+/// The first `max_bytes` of the file at `path`, without reading the rest.
+fn read_preview(path: &Path, max_bytes: usize) -> Result<String> {
+    Ok(read_capped(path, max_bytes)?.0)
+}
+
+/// Key for confirm tokens: `ATC_CONFIRM_SECRET`, else random per process, in which
+/// case previews from before a restart no longer confirm.
+fn confirm_secret() -> &'static [u8] {
+    static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+    SECRET.get_or_init(|| match std::env::var("ATC_CONFIRM_SECRET") {
+        Ok(s) if !s.is_empty() => s.into_bytes(),
+        _ => [uuid::Uuid::new_v4().into_bytes(), uuid::Uuid::new_v4().into_bytes()].concat(),
+    })
+}
+
+/// Digest of what is at `path` right now: a file's bytes, a directory's entries
+/// with their sizes and mtimes, or a marker for nothing there.
+fn path_state(path: &Path) -> Result<Vec<u8>> {
+    let mut h = Sha256::new();
+    let meta = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(b"absent".to_vec()),
+        Err(e) => return Err(e.into()),
+    };
+    if meta.is_dir() {
+        let mut entries = Vec::new();
+        dir_state(path, path, &mut entries)?;
+        entries.sort();
+        for e in entries { h.update(e.as_bytes()); h.update([0u8]); }
+    } else {
+        std::io::copy(&mut fs::File::open(path)?, &mut h)?;
+    }
+    Ok(h.finalize().to_vec())
+}
+
+fn dir_state(dir: &Path, base: &Path, out: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        let path = entry.path();
+        let modified = meta.modified().ok().and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok()).map(|d| d.as_nanos()).unwrap_or(0);
+        out.push(format!("{}:{}:{}", path.strip_prefix(base).unwrap_or(&path).display(), meta.len(), modified));
+        if entry.file_type()?.is_dir() { dir_state(&path, base, out)?; }
+    }
+    Ok(())
+}
+
+fn confirm_mac(root: &str, op: Confirm<'_>) -> Result<Hmac<Sha256>> {
+    let state = |rel: &str| -> Result<Vec<u8>> {
+        path_state(&resolve_under_root(root, rel).ok_or_else(|| Error::PathOutsideRoot(rel.to_string()))?)
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(confirm_secret()).expect("hmac takes any key length");
+    let mut field = |bytes: &[u8]| { mac.update(&(bytes.len() as u64).to_be_bytes()); mac.update(bytes); };
+    match op {
+        Confirm::Write { rel, content } => {
+            field(b"write");
+            field(rel.as_bytes());
+            field(&Sha256::digest(content.as_bytes()));
+            field(&state(rel)?);
+        }
+        Confirm::Move { from, to } => {
+            field(b"move");
+            field(from.as_bytes());
+            field(to.as_bytes());
+            field(&state(from)?);
+            field(&state(to)?);
+        }
+        Confirm::Delete { rel } => {
+            field(b"delete");
+            field(rel.as_bytes());
+            field(&state(rel)?);
+        }
+    }
+    Ok(mac)
+}
+
+/// Token a dry-run preview hands out for `op`. It is keyed by a server secret and
+/// covers the current state of the files involved, so it stops confirming once
+/// they change.
+pub fn confirm_token(root: &str, op: Confirm<'_>) -> Result<String> {
+    let tag = confirm_mac(root, op)?.finalize().into_bytes();
+    Ok(tag.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Fails with `ConfirmationRequired` unless `token` came from a preview of this
+/// exact change, made while the files involved looked as they do now.
+pub fn check_confirm_token(root: &str, op: Confirm<'_>, token: Option<&str>) -> Result<()> {
+    let Some(token) = token else {
+        return Err(Error::ConfirmationRequired(format!("preview the {} with dry_run and pass its confirm_token", op.describe())));
+    };
+    let bytes = (0..token.len()).step_by(2)
+        .map(|i| token.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+        .collect::<Option<Vec<u8>>>();
+    match bytes {
+        Some(bytes) if confirm_mac(root, op)?.verify_slice(&bytes).is_ok() => Ok(()),
+        _ => Err(Error::ConfirmationRequired(format!("confirm_token does not match a current preview of this {}", op.describe()))),
+    }
+}
+
 pub fn write_file_under_root(
     root: &str,
     rel: &str,
@@ -29,62 +176,285 @@ pub fn write_file_under_root(
     create: bool,
     dry_run: bool,
     preview_bytes: usize,
-) -> anyhow::Result<OperationResult<EditPreview>> {
-    let path = resolve_under_root(root, rel).ok_or_else(|| anyhow::anyhow!("path outside root"))?;
+) -> Result<OperationResult<EditPreview>> {
+    write_file_under_root_with_mode(root, rel, content, create, dry_run, preview_bytes, true)
+}
+
+/// Like [`write_file_under_root`], but `atomic = false` writes in place instead of temp-then-rename.
+pub fn write_file_under_root_with_mode(
+    root: &str,
+    rel: &str,
+    content: &str,
+    create: bool,
+    dry_run: bool,
+    preview_bytes: usize,
+    atomic: bool,
+) -> Result<OperationResult<EditPreview>> {
+    let path = resolve_under_root(root, rel).ok_or_else(|| Error::PathOutsideRoot(rel.to_string()))?;
 
     let existed = path.exists();
     if !existed && !create {
-        return Err(anyhow::anyhow!("file does not exist (use create=true to create)"));
+        return Err(Error::NotFound(format!("{} (use create=true to create)", rel)));
     }
 
-    let mut before_bytes = Vec::new();
-    if existed {
-        let mut f = fs::File::open(&path)?;
-        f.read_to_end(&mut before_bytes)?;
-    }
+    // only the preview is returned, so don't load the whole existing file
+    let (before_preview, before_truncated) = if existed { read_capped(&path, preview_bytes)? } else { (String::new(), false) };
     let after_bytes = content.as_bytes().to_vec();
+    let after_truncated = after_bytes.len() > preview_bytes;
 
     if !dry_run {
-        let mut f = fs::File::create(&path)?;
-        f.write_all(content.as_bytes())?;
+        if atomic {
+            write_atomic(&path, content.as_bytes())?;
+        } else {
+            let mut f = fs::File::create(&path)?;
+            f.write_all(content.as_bytes())?;
+        }
     }
 
     Ok(OperationResult {
         applied: !dry_run,
         output: EditPreview {
-            before_preview: cap_utf8(before_bytes, preview_bytes),
+            before_preview,
             after_preview: cap_utf8(after_bytes, preview_bytes),
+            before_truncated,
+            after_truncated,
+            confirm_token: if dry_run { Some(confirm_token(root, Confirm::Write { rel, content })?) } else { None },
         },
     })
 }
 
+/// Writes to a sibling temp file and renames it over `path`, so readers never
+/// observe a torn file. Keeps the original permissions on overwrite and falls
+/// back to a direct write if the rename fails (e.g. cross-device).
+fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let dir = path.parent().ok_or_else(|| Error::InvalidInput(format!("{} has no parent", path.display())))?;
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let tmp = dir.join(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4()));
+    let perms = fs::metadata(path).ok().map(|m| m.permissions());
+    {
+        let mut f = fs::File::create(&tmp)?;
+        f.write_all(content)?;
+        f.sync_all()?;
+    }
+    if let Some(perms) = perms {
+        fs::set_permissions(&tmp, perms)?;
+    }
+    if fs::rename(&tmp, path).is_err() {
+        let _ = fs::remove_file(&tmp);
+        let mut f = fs::File::create(path)?;
+        f.write_all(content)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveOutcome {
+    pub from: String,
+    pub to: String,
+    /// Whether `to` already exists and is (or would be) replaced.
+    pub overwrites: bool,
+    /// Start of the file being replaced at `to`, in dry runs only.
+    pub overwritten_preview: Option<String>,
+    /// Set on dry runs; pass it back to apply this exact move when the session
+    /// has `require_confirm_token`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm_token: Option<String>,
+}
+
 pub fn move_file_under_root(
     root: &str,
     from_rel: &str,
     to_rel: &str,
     dry_run: bool,
-) -> anyhow::Result<OperationResult<String>> {
-    let from = resolve_under_root(root, from_rel).ok_or_else(|| anyhow::anyhow!("source outside root"))?;
-    let to = resolve_under_root(root, to_rel).ok_or_else(|| anyhow::anyhow!("dest outside root"))?;
-    if !from.exists() { return Err(anyhow::anyhow!("source does not exist")); }
+    preview_bytes: usize,
+) -> Result<OperationResult<MoveOutcome>> {
+    let from = resolve_under_root(root, from_rel).ok_or_else(|| Error::PathOutsideRoot(from_rel.to_string()))?;
+    let to = resolve_under_root(root, to_rel).ok_or_else(|| Error::PathOutsideRoot(to_rel.to_string()))?;
+    if !from.exists() { return Err(Error::NotFound(from_rel.to_string())); }
+    let overwrites = to.exists();
+    let overwritten_preview = if dry_run && to.is_file() { Some(read_preview(&to, preview_bytes)?) } else { None };
+    let confirm_token = if dry_run { Some(confirm_token(root, Confirm::Move { from: from_rel, to: to_rel })?) } else { None };
     if !dry_run {
         fs::create_dir_all(to.parent().unwrap_or(PathBuf::new().as_path()))?;
         fs::rename(&from, &to)?;
     }
-    Ok(OperationResult { applied: !dry_run, output: format!("{} -> {}", from.display(), to.display()) })
+    Ok(OperationResult {
+        applied: !dry_run,
+        output: MoveOutcome { from: from.display().to_string(), to: to.display().to_string(), overwrites, overwritten_preview, confirm_token },
+    })
 }
 
+/// Most paths listed in a [`DeleteOutcome`]; `count` is always the full total.
+pub const MAX_DELETE_LISTING: usize = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct DeleteOutcome {
+    pub path: String,
+    /// Files (relative to root) removed, or that would be in a dry run.
+    pub removed: Vec<String>,
+    pub count: usize,
+    /// Where a soft delete moved the target, relative to [`TRASH_DIR`]; pass to [`restore_from_trash`].
+    pub trashed_as: Option<String>,
+    /// Start of the file being removed, in dry runs of a single file only.
+    pub preview: Option<String>,
+    /// Set on dry runs; pass it back to apply this exact delete when the session
+    /// has `require_confirm_token`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm_token: Option<String>,
+}
+
+/// Files under `dir`, depth first, relative to `root`.
+fn files_under(dir: &Path, root: &Path, out: &mut Vec<String>, count: &mut usize) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // don't follow symlinked directories out of the tree; the link itself is what gets removed
+        if entry.file_type()?.is_dir() {
+            files_under(&path, root, out, count)?;
+        } else {
+            *count += 1;
+            if out.len() < MAX_DELETE_LISTING {
+                out.push(path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deletes a file or directory under root. The root itself is never deleted, and a
+/// non-empty directory requires `recursive`. With `soft`, the target is moved into
+/// [`TRASH_DIR`] instead of removed. The outcome lists the affected files and, for a
+/// dry run on a single file, the first `preview_bytes` of its content.
 pub fn delete_file_under_root(
     root: &str,
     rel: &str,
+    recursive: bool,
+    soft: bool,
     dry_run: bool,
-) -> anyhow::Result<OperationResult<String>> {
-    let path = resolve_under_root(root, rel).ok_or_else(|| anyhow::anyhow!("path outside root"))?;
-    if !path.exists() { return Err(anyhow::anyhow!("file does not exist")); }
+    preview_bytes: usize,
+) -> Result<OperationResult<DeleteOutcome>> {
+    let path = resolve_under_root(root, rel).ok_or_else(|| Error::PathOutsideRoot(rel.to_string()))?;
+    let root_path = resolve_under_root(root, ".").ok_or_else(|| Error::NotFound(root.to_string()))?;
+    if path == root_path {
+        return Err(Error::InvalidInput("refusing to delete the project root".into()));
+    }
+    if !path.exists() { return Err(Error::NotFound(rel.to_string())); }
+    let mut removed = Vec::new();
+    let mut count = 0;
+    let is_dir = path.is_dir();
+    if is_dir {
+        files_under(&path, &root_path, &mut removed, &mut count)?;
+        let empty = fs::read_dir(&path)?.next().is_none();
+        if !empty && !recursive {
+            return Err(Error::InvalidInput(format!("{} is a non-empty directory; pass recursive=true to delete it", rel)));
+        }
+    } else {
+        count = 1;
+        removed.push(path.strip_prefix(&root_path).unwrap_or(&path).to_string_lossy().to_string());
+    }
+    // anything already in the trash is removed for good
+    let trash = root_path.join(TRASH_DIR);
+    let trashed_as = if soft && !path.starts_with(&trash) {
+        let rel_path = path.strip_prefix(&root_path).unwrap_or(&path).to_path_buf();
+        Some(trash_slot(&trash, &rel_path))
+    } else {
+        None
+    };
+    let preview = if dry_run && !is_dir { Some(read_preview(&path, preview_bytes)?) } else { None };
+    let confirm_token = if dry_run { Some(confirm_token(root, Confirm::Delete { rel })?) } else { None };
     if !dry_run {
-        if path.is_file() { fs::remove_file(&path)?; } else { fs::remove_dir_all(&path)?; }
+        match &trashed_as {
+            Some(slot) => {
+                let dest = trash.join(slot);
+                fs::create_dir_all(dest.parent().unwrap_or(&trash))?;
+                fs::rename(&path, &dest)?;
+            }
+            None if is_dir => fs::remove_dir_all(&path)?,
+            None => fs::remove_file(&path)?,
+        }
+    }
+    let trashed_as = trashed_as.map(|p| p.to_string_lossy().to_string());
+    Ok(OperationResult { applied: !dry_run, output: DeleteOutcome { path: path.display().to_string(), removed, count, trashed_as, preview, confirm_token } })
+}
+
+/// Directory under the project root that soft deletes move files into.
+pub const TRASH_DIR: &str = ".atc-trash";
+
+/// `<timestamp>/<original relative path>`, bumping the timestamp on collision so
+/// the original location can always be recovered from the name alone.
+fn trash_slot(trash: &Path, rel: &Path) -> PathBuf {
+    let mut stamp = Utc::now().format("%Y%m%dT%H%M%S%3fZ").to_string();
+    let mut n = 1;
+    while trash.join(&stamp).join(rel).exists() {
+        n += 1;
+        stamp = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%3fZ"), n);
     }
-    Ok(OperationResult { applied: !dry_run, output: path.display().to_string() })
+    Path::new(&stamp).join(rel)
+}
+
+/// Moves a soft-deleted entry (`trashed_as` from [`DeleteOutcome`]) back to its
+/// original path, or to `to` when given. Refuses to overwrite an existing file.
+pub fn restore_from_trash(
+    root: &str,
+    trashed_as: &str,
+    to: Option<&str>,
+    dry_run: bool,
+) -> Result<OperationResult<String>> {
+    let trash_rel = Path::new(TRASH_DIR).join(trashed_as);
+    let trash_rel = trash_rel.to_string_lossy();
+    let from = resolve_under_root(root, &trash_rel).ok_or_else(|| Error::PathOutsideRoot(trashed_as.to_string()))?;
+    let trash = resolve_under_root(root, TRASH_DIR).ok_or_else(|| Error::NotFound(TRASH_DIR.to_string()))?;
+    if !from.starts_with(&trash) || from == trash {
+        return Err(Error::InvalidInput(format!("{} is not a trashed entry", trashed_as)));
+    }
+    if !from.exists() { return Err(Error::NotFound(trashed_as.to_string())); }
+    let original = match to {
+        Some(to) => to.to_string(),
+        None => {
+            // drop the leading timestamp component
+            let rel = from.strip_prefix(&trash).unwrap_or(&from);
+            let mut comps = rel.components();
+            comps.next();
+            let original = comps.as_path().to_string_lossy().to_string();
+            if original.is_empty() {
+                return Err(Error::InvalidInput(format!("{} is not a trashed entry", trashed_as)));
+            }
+            original
+        }
+    };
+    let dest = resolve_under_root(root, &original).ok_or_else(|| Error::PathOutsideRoot(original.clone()))?;
+    if dest.exists() { return Err(Error::AlreadyExists(original)); }
+    if !dry_run {
+        fs::create_dir_all(dest.parent().unwrap_or(PathBuf::new().as_path()))?;
+        fs::rename(&from, &dest)?;
+    }
+    Ok(OperationResult { applied: !dry_run, output: original })
+}
+
+/// Creates an empty file. An existing path is an error unless `ok_if_exists`, in
+/// which case it is left as is and `applied` is false.
+pub fn touch_file_under_root(
+    root: &str,
+    rel: &str,
+    ok_if_exists: bool,
+    dry_run: bool,
+) -> Result<OperationResult<String>> {
+    let path = resolve_under_root(root, rel).ok_or_else(|| Error::PathOutsideRoot(rel.to_string()))?;
+    let output = path.display().to_string();
+    if path.exists() {
+        if ok_if_exists { return Ok(OperationResult { applied: false, output }); }
+        return Err(Error::AlreadyExists(rel.to_string()));
+    }
+    if !dry_run {
+        // create_new closes the gap between the exists check and the create
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && ok_if_exists => return Ok(OperationResult { applied: false, output }),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(Error::AlreadyExists(rel.to_string())),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(OperationResult { applied: !dry_run, output })
 }
 
 #[cfg(test)]
@@ -107,6 +477,182 @@ mod tests {
         assert!(res.output.before_preview.contains("old"));
         assert!(res.output.after_preview.contains("new content"));
     }
-}
 
+    #[test]
+    fn write_preview_of_large_file_is_bounded_and_marked_truncated() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        // 'é' is two bytes, so a 5-byte cap falls mid-character
+        let big = "é".repeat(512 * 1024);
+        fs::write(dir.path().join("big.txt"), &big).unwrap();
+        let res = write_file_under_root(&root, "big.txt", "short", false, true, 5).unwrap();
+        assert_eq!(res.output.before_preview, "éé");
+        assert!(res.output.before_truncated);
+        assert_eq!(res.output.after_preview, "short");
+        assert!(!res.output.after_truncated);
+
+        fs::write(dir.path().join("small.txt"), b"tiny").unwrap();
+        let res = write_file_under_root(&root, "small.txt", "much longer content", false, true, 5).unwrap();
+        assert_eq!(res.output.before_preview, "tiny");
+        assert!(!res.output.before_truncated);
+        assert!(res.output.after_truncated);
+    }
+
+    #[test]
+    fn confirm_token_matches_only_the_previewed_write() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let write = |rel, content| Confirm::Write { rel, content };
+        let preview = write_file_under_root(&root, "a.txt", "new", true, true, 32).unwrap();
+        let token = preview.output.confirm_token.unwrap();
+        assert!(check_confirm_token(&root, write("a.txt", "new"), Some(&token)).is_ok());
+        assert!(matches!(check_confirm_token(&root, write("a.txt", "other"), Some(&token)), Err(Error::ConfirmationRequired(_))));
+        assert!(matches!(check_confirm_token(&root, write("b.txt", "new"), Some(&token)), Err(Error::ConfirmationRequired(_))));
+        assert!(matches!(check_confirm_token(&root, write("a.txt", "new"), None), Err(Error::ConfirmationRequired(_))));
+        assert!(matches!(check_confirm_token(&root, Confirm::Delete { rel: "a.txt" }, Some(&token)), Err(Error::ConfirmationRequired(_))));
+        assert!(matches!(check_confirm_token(&root, write("a.txt", "new"), Some("not-hex")), Err(Error::ConfirmationRequired(_))));
+        let applied = write_file_under_root(&root, "a.txt", "new", true, false, 32).unwrap();
+        assert_eq!(applied.output.confirm_token, None);
+    }
 
+    #[test]
+    fn confirm_token_goes_stale_when_the_file_changes() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::write(dir.path().join("a.txt"), b"old").unwrap();
+        let write = Confirm::Write { rel: "a.txt", content: "new" };
+        let token = write_file_under_root(&root, "a.txt", "new", true, true, 32).unwrap().output.confirm_token.unwrap();
+        assert!(check_confirm_token(&root, write, Some(&token)).is_ok());
+        fs::write(dir.path().join("a.txt"), b"changed meanwhile").unwrap();
+        assert!(matches!(check_confirm_token(&root, write, Some(&token)), Err(Error::ConfirmationRequired(_))));
+
+        let delete = Confirm::Delete { rel: "a.txt" };
+        let token = delete_file_under_root(&root, "a.txt", false, false, true, 32).unwrap().output.confirm_token.unwrap();
+        assert!(check_confirm_token(&root, delete, Some(&token)).is_ok());
+        let moved = Confirm::Move { from: "a.txt", to: "b.txt" };
+        let token = move_file_under_root(&root, "a.txt", "b.txt", true, 32).unwrap().output.confirm_token.unwrap();
+        assert!(check_confirm_token(&root, moved, Some(&token)).is_ok());
+        fs::write(dir.path().join("b.txt"), b"appeared").unwrap();
+        assert!(matches!(check_confirm_token(&root, moved, Some(&token)), Err(Error::ConfirmationRequired(_))));
+    }
+
+    #[test]
+    fn atomic_write_replaces_content_and_leaves_no_temp_file() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let p = dir.path().join("a.txt");
+        fs::write(&p, b"old").unwrap();
+        let res = write_file_under_root(&root, "a.txt", "new", false, false, 32).unwrap();
+        assert!(res.applied);
+        assert_eq!(fs::read_to_string(&p).unwrap(), "new");
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_write_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let p = dir.path().join("run.sh");
+        fs::write(&p, b"#!/bin/sh").unwrap();
+        fs::set_permissions(&p, fs::Permissions::from_mode(0o755)).unwrap();
+        write_file_under_root_with_mode(&root, "run.sh", "#!/bin/sh\necho hi", false, false, 32, true).unwrap();
+        let mode = fs::metadata(&p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o755);
+    }
+
+    #[test]
+    fn touch_creates_empty_file_and_respects_existing() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let p = dir.path().join(".gitkeep");
+
+        assert!(!touch_file_under_root(&root, ".gitkeep", false, true).unwrap().applied);
+        assert!(!p.exists());
+
+        assert!(touch_file_under_root(&root, ".gitkeep", false, false).unwrap().applied);
+        assert_eq!(fs::read(&p).unwrap(), b"");
+
+        assert!(matches!(touch_file_under_root(&root, ".gitkeep", false, false), Err(Error::AlreadyExists(_))));
+        fs::write(&p, b"keep").unwrap();
+        assert!(!touch_file_under_root(&root, ".gitkeep", true, false).unwrap().applied);
+        assert_eq!(fs::read(&p).unwrap(), b"keep");
+
+        assert!(matches!(touch_file_under_root(&root, "../x", false, false), Err(Error::PathOutsideRoot(_))));
+    }
+
+    #[test]
+    fn delete_guards_root_and_non_empty_directories() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        fs::write(dir.path().join("src/a.rs"), b"a").unwrap();
+        fs::write(dir.path().join("src/nested/b.rs"), b"b").unwrap();
+        fs::create_dir(dir.path().join("empty")).unwrap();
+
+        assert!(matches!(delete_file_under_root(&root, ".", true, false, false, 64), Err(Error::InvalidInput(_))));
+        assert!(matches!(delete_file_under_root(&root, "src/..", true, false, false, 64), Err(Error::InvalidInput(_))));
+        assert!(matches!(delete_file_under_root(&root, "src", false, false, false, 64), Err(Error::InvalidInput(_))));
+
+        let preview = delete_file_under_root(&root, "src", true, false, true, 64).unwrap();
+        assert!(!preview.applied);
+        assert_eq!(preview.output.count, 2);
+        let mut removed = preview.output.removed.clone();
+        removed.sort();
+        assert_eq!(removed, vec!["src/a.rs".to_string(), "src/nested/b.rs".to_string()]);
+        assert!(dir.path().join("src/a.rs").exists());
+
+        assert!(delete_file_under_root(&root, "empty", false, false, false, 64).unwrap().applied);
+        assert!(delete_file_under_root(&root, "src", true, false, false, 64).unwrap().applied);
+        assert!(!dir.path().join("src").exists());
+    }
+
+    #[test]
+    fn soft_delete_moves_to_trash_and_restores() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/a.rs"), b"a").unwrap();
+
+        let res = delete_file_under_root(&root, "src/a.rs", false, true, false, 64).unwrap();
+        let trashed = res.output.trashed_as.clone().unwrap();
+        assert!(trashed.ends_with("src/a.rs"));
+        assert!(!dir.path().join("src/a.rs").exists());
+        assert!(dir.path().join(TRASH_DIR).join(&trashed).exists());
+
+        fs::write(dir.path().join("src/a.rs"), b"new").unwrap();
+        assert!(matches!(restore_from_trash(&root, &trashed, None, false), Err(Error::AlreadyExists(_))));
+        fs::remove_file(dir.path().join("src/a.rs")).unwrap();
+
+        let restored = restore_from_trash(&root, &trashed, None, false).unwrap();
+        assert_eq!(restored.output, "src/a.rs");
+        assert_eq!(fs::read_to_string(dir.path().join("src/a.rs")).unwrap(), "a");
+        assert!(matches!(restore_from_trash(&root, "../src", None, false), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn dry_runs_preview_removed_and_overwritten_content() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::write(dir.path().join("a.txt"), b"alpha content").unwrap();
+        fs::write(dir.path().join("b.txt"), b"bravo").unwrap();
+
+        let del = delete_file_under_root(&root, "a.txt", false, false, true, 5).unwrap();
+        assert_eq!(del.output.preview.as_deref(), Some("alpha"));
+        assert!(dir.path().join("a.txt").exists());
+
+        let mv = move_file_under_root(&root, "a.txt", "b.txt", true, 64).unwrap();
+        assert!(mv.output.overwrites);
+        assert_eq!(mv.output.overwritten_preview.as_deref(), Some("bravo"));
+        let mv = move_file_under_root(&root, "a.txt", "c.txt", true, 64).unwrap();
+        assert!(!mv.output.overwrites);
+        assert_eq!(mv.output.overwritten_preview, None);
+
+        let applied = move_file_under_root(&root, "a.txt", "b.txt", false, 64).unwrap();
+        assert!(applied.output.overwrites);
+        assert_eq!(applied.output.overwritten_preview, None);
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt")).unwrap(), "alpha content");
+    }
+}
@@ -1,11 +1,20 @@
 use crate::discovery::resolve_under_root;
-use git2::{Repository, StatusOptions, DiffFormat};
-use serde::Serialize;
-use std::path::PathBuf;
+use git2::{Cred, CredentialType, DiffFormat, FetchOptions, PushOptions, RemoteCallbacks, Repository, StatusOptions};
+use crate::error::{Error, Result};
+use crate::settings::{SessionSettings, SigningFormat};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-fn open_repo(root: &str) -> anyhow::Result<Repository> {
-    let root = resolve_under_root(root, ".").ok_or_else(|| anyhow::anyhow!("invalid root"))?;
-    let repo = Repository::discover(root)?;
+fn open_repo(root: &str) -> Result<Repository> {
+    let root = resolve_under_root(root, ".").ok_or_else(|| Error::NotFound(format!("root {}", root)))?;
+    let repo = Repository::discover(&root).map_err(|e| match e.code() {
+        git2::ErrorCode::NotFound => Error::NotARepository(root.display().to_string()),
+        _ => Error::Git(e),
+    })?;
     Ok(repo)
 }
 
@@ -15,61 +24,677 @@ pub struct GitStatusEntry {
     pub status: String,
 }
 
-pub fn status(root: &str) -> anyhow::Result<Vec<GitStatusEntry>> {
+/// What status and diff paths are relative to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathBase {
+    /// The repository's top-level directory, as git reports them.
+    #[default]
+    Repo,
+    /// The `root` passed in, which may be a subdirectory of the repository, so
+    /// paths line up with the file tools.
+    ProjectRoot,
+}
+
+/// Where `root` sits inside the repository's working directory, as path components;
+/// empty when `root` is the top level.
+fn root_prefix(repo: &Repository, root: &str) -> Result<Vec<String>> {
+    let workdir = repo.workdir().ok_or_else(|| Error::InvalidState("bare repository".into()))?;
+    let workdir = workdir.canonicalize()?;
+    let root = resolve_under_root(root, ".").ok_or_else(|| Error::NotFound(format!("root {}", root)))?;
+    let rel = root.strip_prefix(&workdir).map_err(|_| Error::InvalidInput(format!("{} is outside the repository", root.display())))?;
+    Ok(rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect())
+}
+
+/// `root_prefix` as a `/`-separated pathspec limiting git to `root`'s subtree;
+/// `None` when `root` is the top level.
+fn root_pathspec(repo: &Repository, root: &str) -> Result<Option<String>> {
+    let prefix = root_prefix(repo, root)?;
+    Ok((!prefix.is_empty()).then(|| prefix.join("/")))
+}
+
+/// Repo-relative `/`-terminated path of `root`'s soft-delete trash. Files moved there
+/// by a delete are not changes to report or stage.
+fn trash_prefix(repo: &Repository, root: &str) -> Result<String> {
+    let mut prefix = root_prefix(repo, root)?;
+    prefix.push(crate::file_ops::TRASH_DIR.to_string());
+    Ok(format!("{}/", prefix.join("/")))
+}
+
+pub fn status(root: &str) -> Result<Vec<GitStatusEntry>> {
+    status_with(root, PathBase::Repo)
+}
+
+/// [`status`] with paths relative to `base`. Like `git status .`, a
+/// [`PathBase::ProjectRoot`] status only covers files under `root`.
+pub fn status_with(root: &str, base: PathBase) -> Result<Vec<GitStatusEntry>> {
     let repo = open_repo(root)?;
+    let spec = match base {
+        PathBase::Repo => None,
+        PathBase::ProjectRoot => root_pathspec(&repo, root)?,
+    };
     let mut opts = StatusOptions::new();
     opts.include_untracked(true).recurse_untracked_dirs(true);
+    if let Some(spec) = &spec { opts.pathspec(spec); }
     let statuses = repo.statuses(Some(&mut opts))?;
+    let trash = trash_prefix(&repo, root)?;
+    let strip = spec.map(|s| format!("{s}/")).unwrap_or_default();
     let mut out = Vec::new();
     for e in statuses.iter() {
         let s = e.status();
-        let path = e.path().unwrap_or("").to_string();
+        let path = e.path().unwrap_or("");
+        if path.starts_with(&trash) { continue; }
+        let path = path.strip_prefix(strip.as_str()).unwrap_or(path).to_string();
         let status = format!("{:?}", s);
         out.push(GitStatusEntry { path, status });
     }
     Ok(out)
 }
 
-pub fn diff_porcelain(root: &str) -> anyhow::Result<String> {
+/// Returns the commit oid HEAD points to, or `None` for a repo without commits.
+pub fn head_oid(root: &str) -> Result<Option<String>> {
+    let repo = open_repo(root)?;
+    let oid = repo.head().ok().and_then(|h| h.peel_to_commit().ok()).map(|c| c.id().to_string());
+    Ok(oid)
+}
+
+pub fn diff_porcelain(root: &str) -> Result<String> {
+    diff_with(root, PathBase::Repo)
+}
+
+/// [`diff_porcelain`] with paths relative to `base`. Like `git diff --relative`, a
+/// [`PathBase::ProjectRoot`] diff only covers files under `root`.
+pub fn diff_with(root: &str, base: PathBase) -> Result<String> {
     let repo = open_repo(root)?;
+    let spec = match base {
+        PathBase::Repo => None,
+        PathBase::ProjectRoot => root_pathspec(&repo, root)?,
+    };
     let head = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
-    let mut diff = repo.diff_tree_to_workdir(head.as_ref(), None)?;
+    let mut opts = git2::DiffOptions::new();
+    if let Some(spec) = &spec { opts.pathspec(spec); }
+    let prefix = spec.map(|s| format!("{s}/")).unwrap_or_default();
+    let diff = repo.diff_tree_to_workdir(head.as_ref(), Some(&mut opts))?;
     let mut s = String::new();
     diff.print(DiffFormat::Patch, |_, _, l| {
         let c = l.origin();
         let content = std::str::from_utf8(l.content()).unwrap_or("");
         s.push(c);
-        s.push_str(content);
+        // only file headers carry paths; hunks are left alone
+        if c == 'F' && !prefix.is_empty() {
+            s.push_str(&content.replace(&format!("a/{prefix}"), "a/").replace(&format!("b/{prefix}"), "b/"));
+        } else {
+            s.push_str(content);
+        }
         true
     })?;
     Ok(s)
 }
 
-pub fn add_all(root: &str) -> anyhow::Result<()> {
+/// Stages every change under `root`. When `root` is a subdirectory of the
+/// repository, files outside it are left alone.
+pub fn add_all(root: &str) -> Result<()> {
     let repo = open_repo(root)?;
+    let spec = root_pathspec(&repo, root)?.unwrap_or_else(|| "*".into());
+    let trash = trash_prefix(&repo, root)?;
     let mut idx = repo.index()?;
-    idx.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    // a positive return skips the path
+    let skip_trash = &mut |path: &Path, _: &[u8]| -> i32 { path.to_string_lossy().starts_with(&trash) as i32 };
+    idx.add_all([spec].iter(), git2::IndexAddOption::DEFAULT, Some(skip_trash as &mut git2::IndexMatchedPath))?;
     idx.write()?;
     Ok(())
 }
 
-pub fn commit(root: &str, message: &str) -> anyhow::Result<String> {
+/// Paths `add_all` would stage: working-tree changes not yet in the index.
+pub fn preview_add_all(root: &str) -> Result<Vec<String>> {
+    let repo = open_repo(root)?;
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true).recurse_untracked_dirs(true);
+    if let Some(spec) = root_pathspec(&repo, root)? { opts.pathspec(spec); }
+    let wt = git2::Status::WT_NEW | git2::Status::WT_MODIFIED | git2::Status::WT_DELETED | git2::Status::WT_TYPECHANGE | git2::Status::WT_RENAMED;
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let trash = trash_prefix(&repo, root)?;
+    Ok(statuses.iter().filter(|e| e.status().intersects(wt)).filter_map(|e| e.path().map(String::from)).filter(|p| !p.starts_with(&trash)).collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommitPreview {
+    pub message: String,
+    pub parent: Option<String>,
+    pub staged: Vec<String>,
+}
+
+/// Repo-relative paths whose index entry differs from HEAD.
+fn staged_paths(repo: &Repository) -> Result<Vec<String>> {
+    let idx = git2::Status::INDEX_NEW | git2::Status::INDEX_MODIFIED | git2::Status::INDEX_DELETED | git2::Status::INDEX_TYPECHANGE | git2::Status::INDEX_RENAMED;
+    let statuses = repo.statuses(None)?;
+    Ok(statuses.iter().filter(|e| e.status().intersects(idx)).filter_map(|e| e.path().map(String::from)).collect())
+}
+
+/// What `commit` would record, without writing anything.
+pub fn preview_commit(root: &str, message: &str) -> Result<CommitPreview> {
     let repo = open_repo(root)?;
+    let staged = staged_paths(&repo)?;
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok()).map(|c| c.id().to_string());
+    Ok(CommitPreview { message: message.to_string(), parent, staged })
+}
+
+fn is_trailer_line(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(k, _)| !k.is_empty() && !k.contains(char::is_whitespace))
+}
+
+/// Appends `Key: Value` trailers to `message`. They go in a paragraph of their own,
+/// separated from the subject/body by a blank line, or join an existing trailer block.
+pub fn with_trailers(message: &str, trailers: &[(String, String)]) -> Result<String> {
+    if trailers.is_empty() { return Ok(message.to_string()); }
+    let message = message.trim_end();
+    let mut out = message.to_string();
+    let last_paragraph = message.rsplit("\n\n").next().unwrap_or("");
+    // a single-line message is only a subject, even if it looks like `Key: Value`
+    let joins_block = message.contains("\n\n") && last_paragraph.lines().all(is_trailer_line);
+    out.push_str(if joins_block { "\n" } else { "\n\n" });
+    for (key, value) in trailers {
+        if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == ':') {
+            return Err(Error::InvalidInput(format!("invalid trailer key: {:?}", key)));
+        }
+        if value.contains('\n') {
+            return Err(Error::InvalidInput(format!("trailer value for {} must be a single line", key)));
+        }
+        out.push_str(&format!("{}: {}\n", key, value.trim()));
+    }
+    Ok(out)
+}
+
+pub fn commit(root: &str, message: &str) -> Result<String> {
+    commit_with_trailers(root, message, &[])
+}
+
+pub fn commit_with_trailers(root: &str, message: &str, trailers: &[(String, String)]) -> Result<String> {
+    commit_signed(root, message, trailers, None)
+}
+
+/// Key used to sign commits with an external `gpg` or `ssh-keygen`.
+#[derive(Debug, Clone)]
+pub struct CommitSigning {
+    pub format: SigningFormat,
+    /// gpg key id, or path to the ssh private key (or its `.pub` when the key lives in an agent)
+    pub key: String,
+}
+
+impl CommitSigning {
+    /// Signing config requested by the session; `Ok(None)` when `sign_commits` is off.
+    pub fn from_settings(settings: &SessionSettings) -> Result<Option<Self>> {
+        if !settings.sign_commits.unwrap_or(false) { return Ok(None); }
+        let key = settings.signing_key.clone().ok_or_else(|| Error::InvalidInput("sign_commits is set but signing_key is not".into()))?;
+        Ok(Some(Self { format: settings.signing_format.unwrap_or_default(), key }))
+    }
+
+    /// Detached, armored signature over `payload`, as git stores in the `gpgsig` header.
+    fn sign(&self, payload: &str) -> Result<String> {
+        let mut cmd = match self.format {
+            SigningFormat::Gpg => {
+                let mut c = Command::new(std::env::var("ATC_GPG_PROGRAM").unwrap_or_else(|_| "gpg".into()));
+                c.args(["--batch", "--status-fd=2", "-bsau", &self.key]);
+                c
+            }
+            SigningFormat::Ssh => {
+                // with no file argument ssh-keygen signs stdin and writes the signature to stdout
+                let mut c = Command::new("ssh-keygen");
+                c.args(["-Y", "sign", "-n", "git", "-f", &self.key]);
+                c
+            }
+        };
+        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+            .map_err(|e| Error::Signing(format!("failed to start {:?} signer: {}", self.format, e)))?;
+        child.stdin.take().ok_or_else(|| Error::Signing("signer stdin unavailable".into()))?.write_all(payload.as_bytes())?;
+        let out = child.wait_with_output()?;
+        if !out.status.success() {
+            return Err(Error::Signing(String::from_utf8_lossy(&out.stderr).trim().to_string()));
+        }
+        let signature = String::from_utf8(out.stdout).map_err(|_| Error::Signing("signature is not UTF-8".into()))?;
+        if signature.trim().is_empty() { return Err(Error::Signing("signer produced no signature".into())); }
+        Ok(signature)
+    }
+}
+
+/// Staged changes outside `root`'s subtree, which a commit from `root` would
+/// otherwise sweep in.
+fn staged_outside_root(repo: &Repository, root: &str) -> Result<Vec<String>> {
+    let Some(spec) = root_pathspec(repo, root)? else { return Ok(Vec::new()) };
+    let prefix = format!("{spec}/");
+    Ok(staged_paths(repo)?.into_iter().filter(|p| !p.starts_with(&prefix)).collect())
+}
+
+/// Stages exactly `paths` (relative to `root`; new, changed and deleted files alike,
+/// directories recursively) and commits them like [`commit_signed`]. Fails with
+/// `InvalidState`, before committing, when anything else is already staged.
+pub fn commit_paths(root: &str, paths: &[String], message: &str, trailers: &[(String, String)], signing: Option<&CommitSigning>) -> Result<String> {
+    if paths.is_empty() { return Err(Error::InvalidInput("no paths to commit".into())); }
+    let repo = open_repo(root)?;
+    let workdir = repo.workdir().ok_or_else(|| Error::InvalidState("bare repository".into()))?.canonicalize()?;
+    let root_abs = resolve_under_root(root, ".").ok_or_else(|| Error::NotFound(format!("root {}", root)))?;
+    let specs = paths.iter().map(|p| {
+        // a path deleted along with its parent no longer resolves
+        let full = resolve_under_root(root, p).unwrap_or_else(|| root_abs.join(p));
+        let rel = full.strip_prefix(&workdir).map_err(|_| Error::InvalidInput(format!("{} is outside the repository", p)))?;
+        Ok(rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<_>>().join("/"))
+    }).collect::<Result<Vec<String>>>()?;
+    // checked before staging, so a refusal leaves the index as it was
+    let others: Vec<String> = staged_paths(&repo)?.into_iter()
+        .filter(|p| !specs.iter().any(|s| p == s || p.starts_with(&format!("{s}/"))))
+        .collect();
+    if !others.is_empty() {
+        return Err(Error::InvalidState(format!("other changes are staged: {}", others.join(", "))));
+    }
+    let mut idx = repo.index()?;
+    idx.add_all(specs.iter(), git2::IndexAddOption::DEFAULT, None)?;
+    // add_all skips deletions
+    idx.update_all(specs.iter(), None)?;
+    idx.write()?;
+    commit_signed(root, message, trailers, signing)
+}
+
+/// Commits the index like [`commit_with_trailers`], signing it when `signing` is given.
+/// Refuses when `root` is a subdirectory and changes outside it are staged.
+pub fn commit_signed(root: &str, message: &str, trailers: &[(String, String)], signing: Option<&CommitSigning>) -> Result<String> {
+    let message = &with_trailers(message, trailers)?;
+    let mut repo = open_repo(root)?;
+    // concluding a conflicted merge: the MERGE_HEAD commits become additional parents.
+    // Collected first, since mergehead_foreach borrows the repository mutably.
+    let merging = repo.state() == git2::RepositoryState::Merge;
+    let mut merge_heads: Vec<git2::Oid> = Vec::new();
+    if merging { repo.mergehead_foreach(|oid| { merge_heads.push(*oid); true })?; }
+    // a merge commit records the whole merged tree, wherever it was staged from
+    if !merging {
+        let outside = staged_outside_root(&repo, root)?;
+        if !outside.is_empty() {
+            return Err(Error::InvalidState(format!("changes outside the project root are staged: {}", outside.join(", "))));
+        }
+    }
     let sig = repo.signature()?;
     let mut idx = repo.index()?;
     let tree_id = idx.write_tree()?;
     let tree = repo.find_tree(tree_id)?;
-    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
-    let parents: Vec<&git2::Commit> = parent.as_ref().into_iter().collect();
-    let oid = if let Some(p) = parents.first() {
-        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[*p])?
-    } else {
-        // initial commit on orphan branch
-        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[])?
+    // initial commit on orphan branch has no parents
+    let mut parent_commits: Vec<git2::Commit> = repo.head().ok().and_then(|h| h.peel_to_commit().ok()).into_iter().collect();
+    for oid in merge_heads { parent_commits.push(repo.find_commit(oid)?); }
+    let parents: Vec<&git2::Commit> = parent_commits.iter().collect();
+    let Some(signing) = signing else {
+        let oid = repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)?;
+        if merging { repo.cleanup_state()?; }
+        return Ok(oid.to_string());
     };
+
+    // git2 can't sign itself: build the raw commit object, sign it externally,
+    // write it with the signature header, then move HEAD's branch by hand.
+    let buf = repo.commit_create_buffer(&sig, &sig, message, &tree, &parents)?;
+    let payload = buf.as_str().ok_or_else(|| Error::InvalidInput("commit buffer is not UTF-8".into()))?;
+    let signature = signing.sign(payload)?;
+    let oid = repo.commit_signed(payload, &signature, Some("gpgsig"))?;
+    let reflog = format!("commit: {}", message.lines().next().unwrap_or(""));
+    match repo.head() {
+        Ok(head) => { head.resolve()?.set_target(oid, &reflog)?; }
+        Err(_) => {
+            // unborn branch: HEAD is symbolic to a ref that doesn't exist yet
+            let head = repo.find_reference("HEAD")?;
+            let branch = head.symbolic_target().ok_or_else(|| Error::InvalidState("HEAD is not symbolic".into()))?;
+            repo.reference(branch, oid, false, &reflog)?;
+        }
+    }
+    if merging { repo.cleanup_state()?; }
     Ok(oid.to_string())
 }
 
+/// Commits [`log`] returns when the caller gives no `max`.
+pub const DEFAULT_LOG_MAX: usize = 50;
+
+/// One commit in a history listing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommitInfo {
+    pub id: String,
+    /// First line of the message.
+    pub summary: String,
+    pub author: String,
+    pub time: DateTime<Utc>,
+}
+
+impl CommitInfo {
+    fn from_commit(c: &git2::Commit) -> Self {
+        let author = c.author();
+        Self {
+            id: c.id().to_string(),
+            summary: c.summary().unwrap_or("").to_string(),
+            author: format!("{} <{}>", author.name().unwrap_or(""), author.email().unwrap_or("")),
+            time: commit_time(c),
+        }
+    }
+}
+
+fn commit_time(c: &git2::Commit) -> DateTime<Utc> {
+    DateTime::from_timestamp(c.time().seconds(), 0).unwrap_or_default()
+}
+
+/// Up to `max` commits reachable from HEAD, newest first; empty before the first
+/// commit. Commits whose time falls outside `since..=until` are skipped during the
+/// walk, so `max` counts only commits inside the window.
+pub fn log(root: &str, max: usize, since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> Result<Vec<CommitInfo>> {
+    let repo = open_repo(root)?;
+    if repo.head().is_err() { return Ok(Vec::new()); }
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TIME)?;
+    walk.push_head()?;
+    let mut out = Vec::new();
+    for oid in walk {
+        if out.len() >= max { break; }
+        let c = repo.find_commit(oid?)?;
+        let t = commit_time(&c);
+        if since.is_some_and(|s| t < s) || until.is_some_and(|u| t > u) { continue; }
+        out.push(CommitInfo::from_commit(&c));
+    }
+    Ok(out)
+}
+
+/// Up to `max` commits reachable from HEAD that changed `rel` (a file or directory
+/// relative to `root`), newest first. Each commit is diffed against its first
+/// parent, so a merge only counts when it changed the path relative to the branch
+/// it landed on. Paths that no longer exist are fine as long as they stay under `root`.
+pub fn file_history(root: &str, rel: &str, max: usize) -> Result<Vec<CommitInfo>> {
+    let root_abs = resolve_under_root(root, ".").ok_or_else(|| Error::NotFound(format!("root {}", root)))?;
+    let full = match resolve_under_root(root, rel) {
+        Some(p) => p,
+        // deleted along with its parent directory: accept plain relative paths only
+        None if Path::new(rel).components().all(|c| matches!(c, std::path::Component::Normal(_))) => root_abs.join(rel),
+        None => return Err(Error::PathOutsideRoot(rel.to_string())),
+    };
+    let repo = open_repo(root)?;
+    let workdir = repo.workdir().ok_or_else(|| Error::InvalidState("bare repository".into()))?.canonicalize()?;
+    let spec = full.strip_prefix(&workdir).map_err(|_| Error::InvalidInput(format!("{} is outside the repository", rel)))?
+        .components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<_>>().join("/");
+    if spec.is_empty() { return log(root, max, None, None); }
+    if repo.head().is_err() { return Ok(Vec::new()); }
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TIME)?;
+    walk.push_head()?;
+    let mut out = Vec::new();
+    for oid in walk {
+        if out.len() >= max { break; }
+        let c = repo.find_commit(oid?)?;
+        let tree = c.tree()?;
+        let parent_tree = match c.parent(0) {
+            Ok(p) => Some(p.tree()?),
+            Err(_) => None,
+        };
+        let mut opts = git2::DiffOptions::new();
+        opts.pathspec(&spec);
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))?;
+        if diff.deltas().len() > 0 {
+            out.push(CommitInfo::from_commit(&c));
+        }
+    }
+    Ok(out)
+}
+
+/// The remote rejected our credentials, or none we could offer were accepted.
+#[derive(Debug, thiserror::Error)]
+#[error("authentication failed for remote {remote}: {message}")]
+pub struct RemoteAuthFailed {
+    pub remote: String,
+    pub message: String,
+}
+
+/// Callbacks offering, in order: the ssh-agent, `ATC_GIT_TOKEN` over HTTPS, then
+/// git's default credentials. Each is tried once so a rejected credential doesn't
+/// loop forever; `exhausted` is set when nothing is left to offer.
+fn remote_callbacks(exhausted: &Cell<bool>) -> RemoteCallbacks<'_> {
+    let mut tried = CredentialType::empty();
+    let mut cb = RemoteCallbacks::new();
+    cb.credentials(move |_url, username, allowed| {
+        let user = username.unwrap_or("git");
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(user);
+        }
+        if allowed.contains(CredentialType::SSH_KEY) && !tried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            return Cred::ssh_key_from_agent(user);
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && !tried.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            if let Ok(token) = std::env::var("ATC_GIT_TOKEN") {
+                // hosts ignore the user for token auth but require one to be present
+                let token_user = std::env::var("ATC_GIT_USERNAME").ok().or(username.map(String::from)).unwrap_or_else(|| "x-access-token".into());
+                return Cred::userpass_plaintext(&token_user, &token);
+            }
+        }
+        if allowed.contains(CredentialType::DEFAULT) && !tried.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            return Cred::default();
+        }
+        exhausted.set(true);
+        Err(git2::Error::from_str("no usable credentials (tried ssh-agent and ATC_GIT_TOKEN)"))
+    });
+    cb
+}
+
+fn remote_error(remote: &str, exhausted: &Cell<bool>, e: git2::Error) -> Error {
+    if exhausted.get() || e.code() == git2::ErrorCode::Auth {
+        return RemoteAuthFailed { remote: remote.to_string(), message: e.message().to_string() }.into();
+    }
+    Error::Remote { remote: remote.to_string(), message: e.message().to_string() }
+}
+
+/// `refs/heads/<branch>:refs/heads/<branch>` for the checked-out branch.
+fn current_branch_refspec(repo: &Repository) -> Result<String> {
+    let head = repo.head().map_err(|_| Error::InvalidState("nothing to push: HEAD has no commits".into()))?;
+    if !head.is_branch() { return Err(Error::InvalidState("HEAD is detached; pass an explicit refspec".into())); }
+    let name = head.name().ok_or_else(|| Error::InvalidState("branch name is not UTF-8".into()))?;
+    Ok(format!("{name}:{name}"))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushPreview {
+    pub remote: String,
+    pub url: Option<String>,
+    pub refspec: String,
+    pub local: Option<String>,
+}
+
+/// What `push` would send, without contacting the remote.
+pub fn preview_push(root: &str, remote: &str, refspec: Option<&str>) -> Result<PushPreview> {
+    let repo = open_repo(root)?;
+    let r = repo.find_remote(remote)?;
+    let refspec = match refspec { Some(s) => s.to_string(), None => current_branch_refspec(&repo)? };
+    let src = refspec.trim_start_matches('+').split(':').next().unwrap_or("");
+    let local = repo.revparse_single(src).ok().map(|o| o.id().to_string());
+    Ok(PushPreview { remote: remote.to_string(), url: r.url().map(String::from), refspec, local })
+}
+
+/// Pushes `refspec` (default: the current branch to the same name) to `remote`.
+pub fn push(root: &str, remote: &str, refspec: Option<&str>) -> Result<String> {
+    let repo = open_repo(root)?;
+    let refspec = match refspec { Some(s) => s.to_string(), None => current_branch_refspec(&repo)? };
+    let mut r = repo.find_remote(remote)?;
+    let exhausted = Cell::new(false);
+    let rejected = std::cell::RefCell::new(None);
+    let mut cb = remote_callbacks(&exhausted);
+    // a rejected ref (e.g. non-fast-forward) is reported here, not as a push error
+    cb.push_update_reference(|name, status| {
+        if let Some(msg) = status { *rejected.borrow_mut() = Some(format!("{name}: {msg}")); }
+        Ok(())
+    });
+    let mut opts = PushOptions::new();
+    opts.remote_callbacks(cb);
+    r.push(&[refspec.as_str()], Some(&mut opts)).map_err(|e| remote_error(remote, &exhausted, e))?;
+    if let Some(msg) = rejected.borrow_mut().take() {
+        return Err(Error::Remote { remote: remote.to_string(), message: format!("push rejected: {}", msg) });
+    }
+    Ok(refspec)
+}
+
+/// Fetches `remote` with its configured refspecs; returns the number of objects received.
+pub fn fetch(root: &str, remote: &str) -> Result<usize> {
+    let repo = open_repo(root)?;
+    fetch_in(&repo, remote)
+}
+
+fn fetch_in(repo: &Repository, remote: &str) -> Result<usize> {
+    let mut r = repo.find_remote(remote)?;
+    let exhausted = Cell::new(false);
+    let mut opts = FetchOptions::new();
+    opts.remote_callbacks(remote_callbacks(&exhausted));
+    r.fetch(&[] as &[&str], Some(&mut opts), None).map_err(|e| remote_error(remote, &exhausted, e))?;
+    Ok(r.stats().received_objects())
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum PullOutcome {
+    UpToDate,
+    FastForward { to: String },
+    /// Local and remote both have new commits; a merge is needed.
+    Diverged { upstream: String },
+}
+
+/// Fetches `remote` and fast-forwards the current branch to its counterpart there.
+/// Never merges: a diverged branch is reported and left untouched.
+pub fn pull(root: &str, remote: &str) -> Result<PullOutcome> {
+    let repo = open_repo(root)?;
+    let head = repo.find_reference("HEAD")?;
+    let branch_ref = head.symbolic_target().ok_or_else(|| Error::InvalidState("HEAD is detached".into()))?.to_string();
+    let branch = branch_ref.strip_prefix("refs/heads/").unwrap_or(&branch_ref).to_string();
+    fetch_in(&repo, remote)?;
+    let upstream_name = format!("refs/remotes/{remote}/{branch}");
+    let upstream = repo.find_reference(&upstream_name).map_err(|_| Error::NotFound(format!("{remote} has no branch {branch}")))?;
+    let theirs = repo.reference_to_annotated_commit(&upstream)?;
+    let (analysis, _) = repo.merge_analysis(&[&theirs])?;
+    if analysis.is_up_to_date() { return Ok(PullOutcome::UpToDate); }
+    if !(analysis.is_fast_forward() || analysis.is_unborn()) {
+        return Ok(PullOutcome::Diverged { upstream: upstream_name });
+    }
+    // update the worktree before moving the branch so a safe checkout can refuse to clobber local edits
+    let target = repo.find_object(theirs.id(), None)?;
+    repo.checkout_tree(&target, Some(git2::build::CheckoutBuilder::new().safe()))?;
+    let reflog = format!("pull: fast-forward to {upstream_name}");
+    match repo.find_reference(&branch_ref) {
+        Ok(mut r) => { r.set_target(theirs.id(), &reflog)?; }
+        Err(_) => { repo.reference(&branch_ref, theirs.id(), false, &reflog)?; }
+    }
+    Ok(PullOutcome::FastForward { to: theirs.id().to_string() })
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConflictedFile {
+    pub path: String,
+    /// Working-tree content with `<<<<<<<`/`>>>>>>>` markers, for resolving with the file tools.
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum MergeOutcome {
+    UpToDate,
+    FastForward { to: String },
+    Merged { commit: String },
+    /// The merge is left in progress: fix the files, then `add_all` and `commit`
+    /// to record the merge commit.
+    Conflicts { files: Vec<ConflictedFile> },
+}
+
+/// Merges `branch` (a local branch, remote-tracking branch like `origin/main`, or any
+/// revision) into the current branch.
+pub fn merge(root: &str, branch: &str) -> Result<MergeOutcome> {
+    let repo = open_repo(root)?;
+    if repo.state() != git2::RepositoryState::Clean {
+        return Err(Error::InvalidState(format!("repository is in the middle of a {:?}; commit or abort it first", repo.state())));
+    }
+    let target = repo.revparse_single(branch).map_err(|_| Error::NotFound(format!("branch or revision {}", branch)))?.peel_to_commit()?;
+    let theirs = repo.find_annotated_commit(target.id())?;
+    let (analysis, _) = repo.merge_analysis(&[&theirs])?;
+    if analysis.is_up_to_date() { return Ok(MergeOutcome::UpToDate); }
+    if analysis.is_fast_forward() || analysis.is_unborn() {
+        let head = repo.find_reference("HEAD")?;
+        let branch_ref = head.symbolic_target().ok_or_else(|| Error::InvalidState("HEAD is detached".into()))?.to_string();
+        repo.checkout_tree(target.as_object(), Some(git2::build::CheckoutBuilder::new().safe()))?;
+        let reflog = format!("merge {branch}: Fast-forward");
+        match repo.find_reference(&branch_ref) {
+            Ok(mut r) => { r.set_target(target.id(), &reflog)?; }
+            Err(_) => { repo.reference(&branch_ref, target.id(), false, &reflog)?; }
+        }
+        return Ok(MergeOutcome::FastForward { to: target.id().to_string() });
+    }
+
+    // writes the merged index and worktree, with markers in conflicted files, and sets MERGE_HEAD
+    repo.merge(&[&theirs], None, Some(git2::build::CheckoutBuilder::new().safe().allow_conflicts(true).conflict_style_merge(true)))?;
+    let mut idx = repo.index()?;
+    if idx.has_conflicts() {
+        let workdir = repo.workdir().ok_or_else(|| Error::InvalidState("bare repository".into()))?.to_path_buf();
+        let mut files = Vec::new();
+        for c in idx.conflicts()? {
+            let c = c?;
+            let Some(entry) = c.our.or(c.their).or(c.ancestor) else { continue };
+            let path = String::from_utf8_lossy(&entry.path).to_string();
+            let content = std::fs::read(workdir.join(&path)).map(|b| String::from_utf8_lossy(&b).to_string()).unwrap_or_default();
+            files.push(ConflictedFile { path, content });
+        }
+        return Ok(MergeOutcome::Conflicts { files });
+    }
+    let tree = repo.find_tree(idx.write_tree()?)?;
+    let sig = repo.signature()?;
+    let ours = repo.head()?.peel_to_commit()?;
+    let oid = repo.commit(Some("HEAD"), &sig, &sig, &format!("Merge {branch}"), &tree, &[&ours, &target])?;
+    repo.cleanup_state()?;
+    Ok(MergeOutcome::Merged { commit: oid.to_string() })
+}
+
+/// Where session worktrees of a repository are checked out: a `<repo>.worktrees`
+/// sibling of its working directory, so they stay out of the shared checkout's
+/// listings and status.
+fn worktrees_dir(repo: &Repository) -> Result<PathBuf> {
+    let workdir = repo.workdir().ok_or_else(|| Error::InvalidState("bare repository".into()))?;
+    let name = workdir.file_name().ok_or_else(|| Error::InvalidInput(format!("worktree base {} has no directory name", workdir.display())))?;
+    let parent = workdir.parent().ok_or_else(|| Error::InvalidInput(format!("worktree base {} has no parent directory", workdir.display())))?;
+    Ok(parent.join(format!("{}.worktrees", name.to_string_lossy())))
+}
+
+fn worktree_branch(name: &str) -> String { format!("atc/{name}") }
+
+/// Checks out a new worktree of the repository at `base` named `name`, on branch
+/// `atc/<name>` (created from HEAD unless it is left over from an earlier worktree),
+/// and returns its path.
+pub fn add_worktree(base: &str, name: &str) -> Result<PathBuf> {
+    let repo = open_repo(base)?;
+    if repo.find_worktree(name).is_ok() {
+        return Err(Error::AlreadyExists(format!("worktree {}", name)));
+    }
+    let dir = worktrees_dir(&repo)?;
+    let path = dir.join(name);
+    if path.exists() {
+        return Err(Error::AlreadyExists(format!("worktree path {}", path.display())));
+    }
+    let branch = match repo.find_branch(&worktree_branch(name), git2::BranchType::Local) {
+        Ok(b) => b,
+        Err(_) => {
+            let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok())
+                .ok_or_else(|| Error::InvalidState("worktree base has no commits".into()))?;
+            repo.branch(&worktree_branch(name), &head, false)?
+        }
+    };
+    std::fs::create_dir_all(&dir)?;
+    let mut opts = git2::WorktreeAddOptions::new();
+    opts.reference(Some(branch.get()));
+    repo.worktree(name, &path, Some(&opts))?;
+    Ok(path)
+}
+
+/// Removes the worktree `name` of the repository at `base`, including its checkout.
+/// The `atc/<name>` branch is kept so committed work survives. Returns `false` when
+/// no such worktree exists.
+pub fn remove_worktree(base: &str, name: &str) -> Result<bool> {
+    let repo = open_repo(base)?;
+    let Ok(wt) = repo.find_worktree(name) else { return Ok(false) };
+    wt.prune(Some(git2::WorktreePruneOptions::new().valid(true).working_tree(true)))?;
+    Ok(true)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,6 +715,397 @@ mod tests {
         let diff = diff_porcelain(&root).unwrap();
         assert!(diff.is_empty());
     }
-}
 
+    #[test]
+    fn plain_directory_is_not_a_repository() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        // a repo above the temp dir would otherwise be discovered
+        if Repository::discover(dir.path()).is_ok() { return; }
+        assert!(matches!(status(&root).unwrap_err(), Error::NotARepository(_)));
+        assert!(matches!(add_all(&root).unwrap_err(), Error::NotARepository(_)));
+    }
+
+    #[test]
+    fn head_oid_none_before_first_commit() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let _repo = Repository::init(dir.path()).unwrap();
+        assert_eq!(head_oid(&root).unwrap(), None);
+        fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+        add_all(&root).unwrap();
+        let oid = commit(&root, "first").unwrap();
+        assert_eq!(head_oid(&root).unwrap(), Some(oid));
+    }
+
+    #[test]
+    fn previews_do_not_stage_or_commit() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let _repo = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+
+        assert_eq!(preview_add_all(&root).unwrap(), vec!["a.txt".to_string()]);
+        assert!(preview_commit(&root, "msg").unwrap().staged.is_empty());
+
+        add_all(&root).unwrap();
+        let p = preview_commit(&root, "msg").unwrap();
+        assert_eq!(p.staged, vec!["a.txt".to_string()]);
+        assert_eq!(p.parent, None);
+        assert_eq!(head_oid(&root).unwrap(), None);
+    }
+
+    #[test]
+    fn soft_deleted_files_are_not_reported_or_staged() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let _repo = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+        fs::create_dir_all(dir.path().join(crate::file_ops::TRASH_DIR).join("1")).unwrap();
+        fs::write(dir.path().join(crate::file_ops::TRASH_DIR).join("1").join("old.txt"), b"gone").unwrap();
+
+        assert_eq!(status(&root).unwrap().iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), vec!["a.txt"]);
+        assert_eq!(preview_add_all(&root).unwrap(), vec!["a.txt".to_string()]);
+        add_all(&root).unwrap();
+        assert_eq!(preview_commit(&root, "msg").unwrap().staged, vec!["a.txt".to_string()]);
+    }
+
+    #[test]
+    fn trailers_follow_a_blank_line_or_join_existing_block() {
+        let t = vec![("Signed-off-by".to_string(), "Ann <ann@example.com>".to_string())];
+        assert_eq!(with_trailers("Fix: typo\n", &t).unwrap(), "Fix: typo\n\nSigned-off-by: Ann <ann@example.com>\n");
+        assert_eq!(with_trailers("Subject\n\nBody text", &t).unwrap(), "Subject\n\nBody text\n\nSigned-off-by: Ann <ann@example.com>\n");
+        assert_eq!(with_trailers("Subject\n\nCo-authored-by: Bo <bo@example.com>", &t).unwrap(), "Subject\n\nCo-authored-by: Bo <bo@example.com>\nSigned-off-by: Ann <ann@example.com>\n");
+        assert!(with_trailers("s", &[("Bad key".to_string(), "v".to_string())]).is_err());
+        assert_eq!(with_trailers("as is\n", &[]).unwrap(), "as is\n");
+    }
+
+    #[test]
+    fn commit_with_trailers_records_them() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+        add_all(&root).unwrap();
+        let t = vec![("Co-authored-by".to_string(), "Bo <bo@example.com>".to_string())];
+        let oid = commit_with_trailers(&root, "add a", &t).unwrap();
+        let c = repo.find_commit(git2::Oid::from_str(&oid).unwrap()).unwrap();
+        assert_eq!(c.message(), Some("add a\n\nCo-authored-by: Bo <bo@example.com>\n"));
+    }
+
+    #[test]
+    fn ssh_signed_commit_carries_signature_and_moves_head() {
+        let dir = tempdir().unwrap();
+        let key = dir.path().join("key");
+        // ssh-keygen is not available everywhere; nothing to test without it
+        let Ok(out) = Command::new("ssh-keygen").args(["-q", "-t", "ed25519", "-N", "", "-f"]).arg(&key).output() else { return };
+        if !out.status.success() { return; }
+        let work = dir.path().join("work");
+        let repo = Repository::init(&work).unwrap();
+        let root = work.to_string_lossy().to_string();
+        fs::write(work.join("a.txt"), b"hello").unwrap();
+        add_all(&root).unwrap();
+        let signing = CommitSigning { format: SigningFormat::Ssh, key: key.to_string_lossy().to_string() };
+        let first = commit_signed(&root, "first", &[], Some(&signing)).unwrap();
+        assert_eq!(head_oid(&root).unwrap(), Some(first.clone()));
+        fs::write(work.join("b.txt"), b"again").unwrap();
+        add_all(&root).unwrap();
+        let second = commit_signed(&root, "second", &[], Some(&signing)).unwrap();
+        assert_eq!(head_oid(&root).unwrap(), Some(second.clone()));
+        let (sig, _) = repo.extract_signature(&git2::Oid::from_str(&second).unwrap(), None).unwrap();
+        assert!(sig.as_str().unwrap().contains("BEGIN SSH SIGNATURE"));
+        let c = repo.find_commit(git2::Oid::from_str(&second).unwrap()).unwrap();
+        assert_eq!(c.parent_id(0).unwrap().to_string(), first);
+    }
+
+    #[test]
+    fn signing_requires_a_key_when_enabled() {
+        assert!(CommitSigning::from_settings(&SessionSettings::default()).unwrap().is_none());
+        let on = SessionSettings { sign_commits: Some(true), ..Default::default() };
+        assert!(CommitSigning::from_settings(&on).is_err());
+    }
+
+    #[test]
+    fn push_then_pull_fast_forwards_through_a_bare_remote() {
+        let dir = tempdir().unwrap();
+        let bare = dir.path().join("remote.git");
+        Repository::init_bare(&bare).unwrap();
+        let url = bare.to_string_lossy().to_string();
+
+        let a = dir.path().join("a");
+        let repo_a = Repository::init(&a).unwrap();
+        repo_a.remote("origin", &url).unwrap();
+        let root_a = a.to_string_lossy().to_string();
+        fs::write(a.join("f.txt"), b"one").unwrap();
+        add_all(&root_a).unwrap();
+        commit(&root_a, "one").unwrap();
+        let preview = preview_push(&root_a, "origin", None).unwrap();
+        assert_eq!(preview.local, head_oid(&root_a).unwrap());
+        let branch = repo_a.head().unwrap().shorthand().unwrap().to_string();
+        assert_eq!(push(&root_a, "origin", None).unwrap(), format!("refs/heads/{branch}:refs/heads/{branch}"));
+
+        let b = dir.path().join("b");
+        let repo_b = Repository::init(&b).unwrap();
+        repo_b.remote("origin", &url).unwrap();
+        repo_b.set_head(&format!("refs/heads/{branch}")).unwrap();
+        let root_b = b.to_string_lossy().to_string();
+        assert!(matches!(pull(&root_b, "origin").unwrap(), PullOutcome::FastForward { .. }));
+        assert_eq!(fs::read_to_string(b.join("f.txt")).unwrap(), "one");
+        assert_eq!(pull(&root_b, "origin").unwrap(), PullOutcome::UpToDate);
+
+        fs::write(a.join("f.txt"), b"two").unwrap();
+        add_all(&root_a).unwrap();
+        let two = commit(&root_a, "two").unwrap();
+        push(&root_a, "origin", None).unwrap();
+        assert!(fetch(&root_b, "origin").unwrap() > 0);
+        assert_eq!(pull(&root_b, "origin").unwrap(), PullOutcome::FastForward { to: two });
+    }
 
+    #[test]
+    fn auth_errors_are_typed() {
+        let exhausted = Cell::new(true);
+        let e = remote_error("origin", &exhausted, git2::Error::from_str("denied"));
+        assert!(matches!(e, Error::RemoteAuth(_)));
+        let e = remote_error("origin", &Cell::new(false), git2::Error::from_str("unreachable"));
+        assert!(matches!(e, Error::Remote { .. }));
+    }
+
+    fn commit_file(root: &str, name: &str, body: &str, message: &str) -> String {
+        fs::write(std::path::Path::new(root).join(name), body).unwrap();
+        add_all(root).unwrap();
+        commit(root, message).unwrap()
+    }
+
+    // checkout before moving HEAD so files only on the old branch are removed
+    fn switch(repo: &Repository, branch_ref: &str) {
+        let target = repo.revparse_single(branch_ref).unwrap();
+        repo.checkout_tree(&target, Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+        repo.set_head(branch_ref).unwrap();
+    }
+
+    #[test]
+    fn merge_reports_fast_forward_clean_merge_and_conflicts() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let repo = Repository::init(dir.path()).unwrap();
+        let base = commit_file(&root, "a.txt", "base\n", "base");
+        let main = repo.head().unwrap().name().unwrap().to_string();
+        let base_commit = repo.find_commit(git2::Oid::from_str(&base).unwrap()).unwrap();
+        repo.branch("topic", &base_commit, false).unwrap();
+
+        // topic ahead of main: fast-forward
+        switch(&repo, "refs/heads/topic");
+        let topic1 = commit_file(&root, "b.txt", "topic\n", "topic 1");
+        switch(&repo, &main);
+        assert!(matches!(merge(&root, "topic").unwrap(), MergeOutcome::FastForward { to } if to == topic1));
+        assert!(matches!(merge(&root, "topic").unwrap(), MergeOutcome::UpToDate));
+
+        // both sides touch different files: merge commit with two parents
+        commit_file(&root, "c.txt", "main\n", "main 1");
+        switch(&repo, "refs/heads/topic");
+        commit_file(&root, "d.txt", "topic\n", "topic 2");
+        switch(&repo, &main);
+        let MergeOutcome::Merged { commit: merged } = merge(&root, "topic").unwrap() else { panic!("expected a merge commit") };
+        assert_eq!(repo.find_commit(git2::Oid::from_str(&merged).unwrap()).unwrap().parent_count(), 2);
+
+        // both sides edit a.txt: conflicts with markers, resolved through add_all + commit
+        commit_file(&root, "a.txt", "ours\n", "main 2");
+        switch(&repo, "refs/heads/topic");
+        commit_file(&root, "a.txt", "theirs\n", "topic 3");
+        switch(&repo, &main);
+        let MergeOutcome::Conflicts { files } = merge(&root, "topic").unwrap() else { panic!("expected conflicts") };
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "a.txt");
+        assert!(files[0].content.contains("<<<<<<<") && files[0].content.contains(">>>>>>>"));
+        let resolved = commit_file(&root, "a.txt", "resolved\n", "resolve");
+        assert_eq!(repo.find_commit(git2::Oid::from_str(&resolved).unwrap()).unwrap().parent_count(), 2);
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+    }
+
+    #[test]
+    fn worktree_is_added_beside_base_and_pruned() {
+        let parent = tempdir().unwrap();
+        let base_dir = parent.path().join("repo");
+        fs::create_dir(&base_dir).unwrap();
+        let base = base_dir.to_string_lossy().to_string();
+        let _repo = Repository::init(&base_dir).unwrap();
+        fs::write(base_dir.join("a.txt"), b"hello").unwrap();
+        add_all(&base).unwrap();
+        commit(&base, "first").unwrap();
+
+        let path = add_worktree(&base, "s1").unwrap();
+        assert!(path.starts_with(parent.path().join("repo.worktrees")));
+        assert_eq!(fs::read(path.join("a.txt")).unwrap(), b"hello");
+        assert!(matches!(add_worktree(&base, "s1"), Err(Error::AlreadyExists(_))));
+
+        // edits in the worktree don't touch the shared checkout
+        fs::write(path.join("b.txt"), b"session").unwrap();
+        assert!(status(&base).unwrap().is_empty());
+
+        assert!(remove_worktree(&base, "s1").unwrap());
+        assert!(!path.exists());
+        assert!(!remove_worktree(&base, "s1").unwrap());
+        let repo = Repository::open(&base_dir).unwrap();
+        assert!(repo.find_branch("atc/s1", git2::BranchType::Local).is_ok());
+
+        // re-adding picks the kept branch back up
+        let again = add_worktree(&base, "s1").unwrap();
+        assert!(again.join("a.txt").exists());
+    }
+
+    #[test]
+    fn worktree_needs_a_commit_to_branch_from() {
+        let parent = tempdir().unwrap();
+        let base_dir = parent.path().join("repo");
+        fs::create_dir(&base_dir).unwrap();
+        let _repo = Repository::init(&base_dir).unwrap();
+        let err = add_worktree(&base_dir.to_string_lossy(), "s1").unwrap_err();
+        assert!(matches!(err, Error::InvalidState(_)));
+    }
+
+    #[test]
+    fn status_and_diff_paths_relative_to_subdirectory_root() {
+        let dir = tempdir().unwrap();
+        let top = dir.path().to_string_lossy().to_string();
+        let _repo = Repository::init(dir.path()).unwrap();
+        fs::create_dir_all(dir.path().join("crates/core")).unwrap();
+        fs::write(dir.path().join("crates/core/lib.rs"), b"one\n").unwrap();
+        fs::write(dir.path().join("README.md"), b"readme\n").unwrap();
+        add_all(&top).unwrap();
+        commit(&top, "first").unwrap();
+        fs::write(dir.path().join("crates/core/lib.rs"), b"two\n").unwrap();
+        fs::write(dir.path().join("README.md"), b"changed\n").unwrap();
+
+        let sub = dir.path().join("crates/core").to_string_lossy().to_string();
+        let mut repo_paths: Vec<String> = status(&sub).unwrap().into_iter().map(|e| e.path).collect();
+        repo_paths.sort();
+        assert_eq!(repo_paths, vec!["README.md", "crates/core/lib.rs"]);
+        let mut rel_paths: Vec<String> = status_with(&sub, PathBase::ProjectRoot).unwrap().into_iter().map(|e| e.path).collect();
+        rel_paths.sort();
+        assert_eq!(rel_paths, vec!["lib.rs"]);
+
+        let d = diff_with(&sub, PathBase::ProjectRoot).unwrap();
+        assert!(d.contains("diff --git a/lib.rs b/lib.rs"), "{d}");
+        assert!(d.contains("+two"));
+        assert!(!d.contains("README"));
+        assert!(diff_porcelain(&sub).unwrap().contains("a/crates/core/lib.rs"));
+        // at the top level both bases agree
+        assert_eq!(diff_with(&top, PathBase::ProjectRoot).unwrap(), diff_porcelain(&top).unwrap());
+    }
+
+    #[test]
+    fn add_and_commit_are_scoped_to_subdirectory_root() {
+        let dir = tempdir().unwrap();
+        let top = dir.path().to_string_lossy().to_string();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::create_dir_all(dir.path().join("crates/core")).unwrap();
+        fs::create_dir_all(dir.path().join("crates/core-extra")).unwrap();
+        fs::write(dir.path().join("crates/core/lib.rs"), b"one\n").unwrap();
+        fs::write(dir.path().join("crates/core-extra/lib.rs"), b"one\n").unwrap();
+        fs::write(dir.path().join("README.md"), b"readme\n").unwrap();
+
+        let sub = dir.path().join("crates/core").to_string_lossy().to_string();
+        assert_eq!(preview_add_all(&sub).unwrap(), vec!["crates/core/lib.rs".to_string()]);
+        add_all(&sub).unwrap();
+        let staged: Vec<String> = repo.index().unwrap().iter().map(|e| String::from_utf8(e.path).unwrap()).collect();
+        assert_eq!(staged, vec!["crates/core/lib.rs".to_string()]);
+        commit(&sub, "core only").unwrap();
+
+        // staging from the top level pulls in files outside the subtree
+        add_all(&top).unwrap();
+        let err = commit(&sub, "sweeps in README").unwrap_err();
+        assert!(matches!(err, Error::InvalidState(ref m) if m.contains("README.md")), "{err:?}");
+        commit(&top, "everything").unwrap();
+    }
+
+    #[test]
+    fn commit_paths_records_only_the_given_files() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/old.rs"), b"old\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"v1\n").unwrap();
+        add_all(&root).unwrap();
+        commit(&root, "first").unwrap();
+
+        fs::write(dir.path().join("src/new.rs"), b"new\n").unwrap();
+        fs::remove_file(dir.path().join("src/old.rs")).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"human edit\n").unwrap();
+        let paths = vec!["src/new.rs".to_string(), "src/old.rs".to_string()];
+        commit_paths(&root, &paths, "agent change", &[], None).unwrap();
+
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(tree.get_path(std::path::Path::new("src/new.rs")).is_ok());
+        assert!(tree.get_path(std::path::Path::new("src/old.rs")).is_err());
+        // the human's edit is neither committed nor staged
+        assert!(preview_commit(&root, "m").unwrap().staged.is_empty());
+        assert_eq!(preview_add_all(&root).unwrap(), vec!["notes.txt".to_string()]);
+
+        add_all(&root).unwrap();
+        fs::write(dir.path().join("src/new.rs"), b"newer\n").unwrap();
+        let err = commit_paths(&root, &["src/new.rs".to_string()], "m", &[], None).unwrap_err();
+        assert!(matches!(err, Error::InvalidState(ref m) if m.contains("notes.txt")), "{err:?}");
+        // the refusal leaves the index untouched: src/new.rs's edit is still unstaged
+        assert_eq!(preview_commit(&root, "m").unwrap().staged, vec!["notes.txt".to_string()]);
+        assert_eq!(preview_add_all(&root).unwrap(), vec!["src/new.rs".to_string()]);
+    }
+
+    // commits `name` with author and committer time fixed at `secs`
+    fn commit_at(repo: &Repository, root: &str, name: &str, secs: i64) -> String {
+        fs::write(std::path::Path::new(root).join(name), secs.to_string()).unwrap();
+        add_all(root).unwrap();
+        let sig = git2::Signature::new("Ann", "ann@example.com", &git2::Time::new(secs, 0)).unwrap();
+        // add_all staged through its own handle; reload so the tree isn't the cached one
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, &format!("at {secs}"), &tree, &parents).unwrap().to_string()
+    }
+
+    #[test]
+    fn log_filters_by_commit_time_during_walk() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert!(log(&root, 10, None, None).unwrap().is_empty());
+        let day = 86_400;
+        let ids: Vec<String> = (1..=5).map(|d| commit_at(&repo, &root, "a.txt", d * day)).collect();
+
+        let all = log(&root, 10, None, None).unwrap();
+        assert_eq!(all.iter().map(|c| c.id.clone()).collect::<Vec<_>>(), ids.iter().rev().cloned().collect::<Vec<_>>());
+        assert_eq!(all[0].summary, format!("at {}", 5 * day));
+        assert_eq!(all[0].author, "Ann <ann@example.com>");
+
+        let at = |d: i64| DateTime::from_timestamp(d * day, 0);
+        let window = log(&root, 10, at(2), at(4)).unwrap();
+        assert_eq!(window.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec![ids[3].as_str(), ids[2].as_str(), ids[1].as_str()]);
+        // max counts in-window commits, not commits walked
+        let capped = log(&root, 2, None, at(3)).unwrap();
+        assert_eq!(capped.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec![ids[2].as_str(), ids[1].as_str()]);
+        assert!(log(&root, 10, at(6), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_history_lists_commits_touching_the_path() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let repo = Repository::init(dir.path()).unwrap();
+        let day = 86_400;
+        let a1 = commit_at(&repo, &root, "a.txt", day);
+        commit_at(&repo, &root, "b.txt", 2 * day);
+        let a2 = commit_at(&repo, &root, "a.txt", 3 * day);
+        fs::remove_file(dir.path().join("a.txt")).unwrap();
+        add_all(&root).unwrap();
+        let gone = commit(&root, "remove a").unwrap();
+
+        let ids = |v: Vec<CommitInfo>| v.into_iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids(file_history(&root, "a.txt", 10).unwrap()), vec![gone.clone(), a2.clone(), a1]);
+        assert_eq!(ids(file_history(&root, "a.txt", 2).unwrap()), vec![gone, a2]);
+        assert_eq!(file_history(&root, "b.txt", 10).unwrap().len(), 1);
+        assert!(file_history(&root, "never.txt", 10).unwrap().is_empty());
+        assert!(matches!(file_history(&root, "../etc/passwd", 10).unwrap_err(), Error::PathOutsideRoot(_)));
+    }
+}
@@ -0,0 +1,19 @@
+//! Headless AI coding agent: session storage, agent tools, model backends and the
+//! HTTP server. The `air_traffic_control` binary is a CLI over this library.
+
+pub mod agent;
+pub mod discovery;
+#[cfg(feature = "embeddings")]
+pub mod embeddings;
+pub mod error;
+pub mod file_ops;
+pub mod git_ops;
+pub mod models;
+pub mod net;
+pub mod pagination;
+pub mod server;
+pub mod session;
+pub mod settings;
+pub mod storage;
+pub mod templates;
+pub mod tokens;
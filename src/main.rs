@@ -1,16 +1,7 @@
-use clap::{Args, Parser, Subcommand};
-use std::net::SocketAddr;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use tracing_subscriber::{fmt, EnvFilter};
 
-mod server;
-mod session;
-mod settings;
-mod discovery;
-mod file_ops;
-mod git_ops;
-mod models;
-mod storage;
-mod agent;
+use air_traffic_control::{agent, discovery, file_ops, git_ops, models, server, settings, storage};
 use serde_json::json;
 
 #[derive(Debug, Parser)]
@@ -24,8 +15,15 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Commands {
     Start {
+        /// `host:port`, or `unix:/path/to.sock` to bind a Unix domain socket
         #[arg(long, default_value = "127.0.0.1:7171")]
         listen: String,
+        /// PEM certificate chain; serves HTTPS together with --tls-key
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<std::path::PathBuf>,
+        /// PEM private key for --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<std::path::PathBuf>,
     },
     Session { #[command(subcommand)] cmd: SessionCmd },
     Git { #[command(subcommand)] cmd: GitCmd },
@@ -37,7 +35,7 @@ enum Commands {
 #[derive(Debug, Subcommand)]
 enum GitCmd {
     Status(RootArg),
-    Diff(RootArg),
+    Diff(DiffArgs),
     AddAll(RootArg),
     Commit(CommitArgs),
 }
@@ -50,14 +48,23 @@ enum SessionCmd {
     SettingsSet(SessionSettingsSetArgs),
     Send(SessionSendArgs),
     Url(SessionUrlArgs),
+    Export(SessionExportArgs),
     Close(SessionIdArg),
 }
 
 #[derive(Debug, Subcommand)]
 enum DiscoveryCmd {
-    List { #[command(flatten)] root: RootArg, #[arg(long, default_value_t = 500)] max: usize },
-    Search { #[command(flatten)] root: RootArg, #[arg(long)] pattern: String, #[arg(long, default_value_t = 500)] max: usize },
-    Read { #[command(flatten)] root: RootArg, #[arg(long)] path: String, #[arg(long, default_value_t = 65536)] max_bytes: usize },
+    /// --max defaults to ATC_DISCOVERY_MAX_ENTRIES, else 500
+    /// .git/.hg/.svn are skipped unless --include-vcs
+    /// --classify adds each file's kind (text/binary/image/unknown)
+    List { #[command(flatten)] root: RootArg, #[arg(long)] max: Option<usize>, #[arg(long)] include_vcs: bool, #[arg(long)] classify: bool },
+    /// Prints the indented tree; --depth defaults to 3
+    Tree { #[command(flatten)] root: RootArg, #[arg(long)] depth: Option<usize>, #[arg(long)] max: Option<usize>, #[arg(long)] include_vcs: bool },
+    Search { #[command(flatten)] root: RootArg, #[arg(long)] pattern: String, #[arg(long)] max: Option<usize>, #[arg(long)] include_vcs: bool, #[command(flatten)] matching: PatternArgs },
+    /// --max-file-bytes defaults to ATC_GREP_MAX_FILE_BYTES, else 1 MiB
+    Grep { #[command(flatten)] root: RootArg, #[arg(long)] pattern: String, #[arg(long)] max: Option<usize>, #[arg(long)] max_file_bytes: Option<u64>, #[arg(long)] include_vcs: bool, #[command(flatten)] matching: PatternArgs },
+    /// --max-bytes defaults to ATC_DISCOVERY_READ_BYTES, else 65536
+    Read { #[command(flatten)] root: RootArg, #[arg(long)] path: String, #[arg(long)] max_bytes: Option<usize> },
 }
 
 #[derive(Debug, Subcommand)]
@@ -65,6 +72,7 @@ enum FilesCmd {
     Write(WriteArgs),
     Move(MoveArgs),
     Delete(DeleteArgs),
+    Touch(TouchArgs),
 }
 
 #[derive(Debug, Subcommand)]
@@ -72,6 +80,7 @@ enum AgentCmd {
     IncludeFile(AgentIncludeFileArgs),
     IncludeUrl(AgentIncludeUrlArgs),
     AddRule(AgentAddRuleArgs),
+    Run(AgentRunArgs),
 }
 
 #[derive(Debug, Args)]
@@ -80,12 +89,84 @@ struct RootArg {
     root: String,
 }
 
+#[derive(Debug, Args)]
+struct PatternArgs {
+    #[arg(long)]
+    ignore_case: bool,
+    /// Only match whole words
+    #[arg(long)]
+    whole_word: bool,
+}
+
+impl From<PatternArgs> for discovery::PatternOptions {
+    fn from(a: PatternArgs) -> Self {
+        Self { ignore_case: a.ignore_case, whole_word: a.whole_word }
+    }
+}
+
 #[derive(Debug, Args)]
 struct CommitArgs {
     #[command(flatten)]
     root: RootArg,
     #[arg(short, long)]
     message: String,
+    /// Trailer appended to the message, e.g. --trailer "Signed-off-by: Name <email>"
+    #[arg(long = "trailer", value_parser = parse_trailer)]
+    trailers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    #[command(flatten)]
+    root: RootArg,
+    /// auto colors only when stdout is a terminal and NO_COLOR is unset
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        use std::io::IsTerminal;
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+/// Colors a `git_ops::diff_porcelain` patch: file headers bold, hunk headers cyan,
+/// additions green, removals red. Each line still starts with its origin char.
+fn colorize_diff(diff: &str) -> String {
+    use owo_colors::OwoColorize;
+    let mut out = String::with_capacity(diff.len());
+    // a file header spans several lines but only its first carries the `F` origin
+    let mut in_header = false;
+    for line in diff.split_inclusive('\n') {
+        let (text, eol) = line.strip_suffix('\n').map_or((line, ""), |t| (t, "\n"));
+        match text.chars().next() {
+            Some('F') => { in_header = true; out.push_str(&text.bold().to_string()); }
+            Some('H') => { in_header = false; out.push_str(&text.cyan().to_string()); }
+            _ if in_header => out.push_str(&text.bold().to_string()),
+            Some('+') => out.push_str(&text.green().to_string()),
+            Some('-') => out.push_str(&text.red().to_string()),
+            _ => out.push_str(text),
+        }
+        out.push_str(eol);
+    }
+    out
+}
+
+fn parse_trailer(s: &str) -> Result<(String, String), String> {
+    let (k, v) = s.split_once(':').ok_or_else(|| format!("expected KEY: VALUE, got {s:?}"))?;
+    Ok((k.trim().to_string(), v.trim().to_string()))
 }
 
 #[derive(Debug, Args)]
@@ -142,6 +223,15 @@ struct SessionUrlArgs {
     max_bytes: usize,
 }
 
+#[derive(Debug, Args)]
+struct SessionExportArgs {
+    #[command(flatten)]
+    id: SessionIdArg,
+    /// openai | markdown
+    #[arg(long, default_value = "markdown")]
+    format: String,
+}
+
 #[derive(Debug, Args)]
 struct WriteArgs {
     #[command(flatten)]
@@ -170,6 +260,8 @@ struct MoveArgs {
     to: String,
     #[arg(long, default_value_t = true)]
     dry_run: bool,
+    #[arg(long, default_value_t = 1024)]
+    preview_bytes: usize,
 }
 
 #[derive(Debug, Args)]
@@ -178,6 +270,27 @@ struct DeleteArgs {
     root: RootArg,
     #[arg(long)]
     path: String,
+    /// Required to delete a non-empty directory
+    #[arg(long)]
+    recursive: bool,
+    /// Move into <root>/.atc-trash instead of removing
+    #[arg(long)]
+    trash: bool,
+    #[arg(long, default_value_t = true)]
+    dry_run: bool,
+    #[arg(long, default_value_t = 1024)]
+    preview_bytes: usize,
+}
+
+#[derive(Debug, Args)]
+struct TouchArgs {
+    #[command(flatten)]
+    root: RootArg,
+    #[arg(long)]
+    path: String,
+    /// Succeed without changes when the file already exists
+    #[arg(long)]
+    ok_if_exists: bool,
     #[arg(long, default_value_t = true)]
     dry_run: bool,
 }
@@ -188,8 +301,9 @@ struct AgentIncludeFileArgs {
     id: SessionIdArg,
     #[arg(long)]
     path: String,
-    #[arg(long, default_value_t = 65536)]
-    max_bytes: usize,
+    /// Defaults to the server's discovery read cap
+    #[arg(long)]
+    max_bytes: Option<usize>,
 }
 
 #[derive(Debug, Args)]
@@ -218,6 +332,18 @@ struct AgentAddRuleArgs {
     repo_dir: Option<String>,
 }
 
+#[derive(Debug, Args)]
+struct AgentRunArgs {
+    #[command(flatten)]
+    id: SessionIdArg,
+    #[arg(long)]
+    task: String,
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long, default_value_t = 10)]
+    max_iterations: usize,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     fmt()
@@ -226,12 +352,14 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
     match cli.command {
-        Commands::Start { listen } => {
-            let addr: SocketAddr = listen.parse()?;
+        Commands::Start { listen, tls_cert, tls_key } => {
+            let addr: server::ListenAddr = listen.parse()?;
             // Initialize SQLite repository (DATABASE_URL or default path)
             let repo = storage::SqliteSessionRepository::initialize(std::env::var("DATABASE_URL").ok()).await?;
-            let state = server::AppState { repo: std::sync::Arc::new(repo), model: None };
-            server::serve(addr, state).await?;
+            let model = models::FallbackChain::from_env()?.map(|c| std::sync::Arc::new(c) as std::sync::Arc<dyn models::LanguageModel>);
+            let state = server::AppState { repo: std::sync::Arc::new(repo), model, model_catalog: std::sync::Arc::new(models::ModelCatalog::from_env()), response_cache: std::sync::Arc::new(models::ResponseCache::from_env()), inflight: Default::default(), admin_key: std::env::var("ATC_ADMIN_KEY").ok() };
+            let tls = tls_cert.zip(tls_key).map(|(cert, key)| server::TlsFiles { cert, key });
+            server::serve(addr, state, tls).await?;
         }
         Commands::Session { cmd } => match cmd {
             SessionCmd::Create(args) => {
@@ -311,6 +439,22 @@ async fn main() -> anyhow::Result<()> {
                 let v: serde_json::Value = resp.json().await?;
                 println!("{}", serde_json::to_string_pretty(&v)?);
             }
+            SessionCmd::Export(args) => {
+                let client = reqwest::Client::new();
+                let resp = client.get(format!("{}/v1/sessions/{}/export", args.id.server.server, args.id.id))
+                    .query(&[("format", &args.format)])
+                    .send()
+                    .await?;
+                if resp.status() == reqwest::StatusCode::BAD_REQUEST { anyhow::bail!("unknown export format: {}", args.format); }
+                if !resp.status().is_success() { anyhow::bail!("server error: {}", resp.status()); }
+                // markdown is printed as-is; JSON formats are pretty-printed like other commands
+                if args.format == "markdown" {
+                    print!("{}", resp.text().await?);
+                } else {
+                    let v: serde_json::Value = resp.json().await?;
+                    println!("{}", serde_json::to_string_pretty(&v)?);
+                }
+            }
             SessionCmd::Close(arg) => {
                 let client = reqwest::Client::new();
                 let resp = client.delete(format!("{}/v1/sessions/{}", arg.server.server, arg.id)).send().await?;
@@ -328,30 +472,47 @@ async fn main() -> anyhow::Result<()> {
                 let st = git_ops::status(&root)?;
                 println!("{}", serde_json::to_string_pretty(&st)?);
             }
-            GitCmd::Diff(RootArg { root }) => {
+            GitCmd::Diff(DiffArgs { root: RootArg { root }, color }) => {
                 let diff = git_ops::diff_porcelain(&root)?;
-                println!("{}", diff);
+                println!("{}", if color.enabled() { colorize_diff(&diff) } else { diff });
             }
             GitCmd::AddAll(RootArg { root }) => {
                 git_ops::add_all(&root)?;
                 println!("{}", serde_json::json!({"ok": true}));
             }
-            GitCmd::Commit(CommitArgs { root: RootArg { root }, message }) => {
-                let oid = git_ops::commit(&root, &message)?;
+            GitCmd::Commit(CommitArgs { root: RootArg { root }, message, trailers }) => {
+                let oid = git_ops::commit_with_trailers(&root, &message, &trailers)?;
                 println!("{}", serde_json::json!({"commit": oid}));
             }
         },
         Commands::Discovery { cmd } => match cmd {
-            DiscoveryCmd::List { root: RootArg { root }, max } => {
-                let items = discovery::list_files(&root, max);
+            DiscoveryCmd::List { root: RootArg { root }, max, include_vcs, classify } => {
+                let opts = discovery::WalkOptions { include_vcs, classify, ..Default::default() };
+                let items = discovery::list_files_with(&root, max.unwrap_or(settings::global_defaults().discovery().max_entries), opts);
                 println!("{}", serde_json::to_string_pretty(&items)?);
             }
-            DiscoveryCmd::Search { root: RootArg { root }, pattern, max } => {
-                let items = discovery::search_files(&root, &pattern, max);
+            DiscoveryCmd::Tree { root: RootArg { root }, depth, max, include_vcs } => {
+                let opts = discovery::WalkOptions { include_vcs, ..Default::default() };
+                let tree = discovery::tree(&root, depth.unwrap_or(discovery::DEFAULT_TREE_DEPTH), max.unwrap_or(settings::global_defaults().discovery().max_entries), opts);
+                print!("{}", tree.text);
+            }
+            DiscoveryCmd::Search { root: RootArg { root }, pattern, max, include_vcs, matching } => {
+                let opts = discovery::WalkOptions { include_vcs, ..Default::default() };
+                let items = discovery::search_files_with_progress(&root, &pattern, matching.into(), max.unwrap_or(settings::global_defaults().discovery().max_entries), opts, usize::MAX, |_| {})?;
                 println!("{}", serde_json::to_string_pretty(&items)?);
             }
+            DiscoveryCmd::Grep { root: RootArg { root }, pattern, max, max_file_bytes, include_vcs, matching } => {
+                let defaults = settings::global_defaults().discovery();
+                let limits = discovery::GrepLimits {
+                    max_matches: max.unwrap_or(defaults.max_entries),
+                    max_file_bytes: max_file_bytes.unwrap_or(defaults.grep_max_file_bytes),
+                    max_total_bytes: defaults.grep_max_total_bytes,
+                };
+                let report = discovery::grep_files(&root, &pattern, matching.into(), discovery::WalkOptions { include_vcs, ..Default::default() }, limits)?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
             DiscoveryCmd::Read { root: RootArg { root }, path, max_bytes } => {
-                let content = discovery::read_file_under_root(&root, &path, max_bytes)?;
+                let content = discovery::read_file_under_root(&root, &path, max_bytes.unwrap_or(settings::global_defaults().discovery().read_bytes))?;
                 println!("{}", serde_json::json!({"path": path, "content": content}));
             }
         },
@@ -366,11 +527,15 @@ async fn main() -> anyhow::Result<()> {
                 println!("{}", serde_json::to_string_pretty(&res)?);
             }
             FilesCmd::Move(args) => {
-                let res = file_ops::move_file_under_root(&args.root.root, &args.from, &args.to, args.dry_run)?;
+                let res = file_ops::move_file_under_root(&args.root.root, &args.from, &args.to, args.dry_run, args.preview_bytes)?;
                 println!("{}", serde_json::to_string_pretty(&res)?);
             }
             FilesCmd::Delete(args) => {
-                let res = file_ops::delete_file_under_root(&args.root.root, &args.path, args.dry_run)?;
+                let res = file_ops::delete_file_under_root(&args.root.root, &args.path, args.recursive, args.trash, args.dry_run, args.preview_bytes)?;
+                println!("{}", serde_json::to_string_pretty(&res)?);
+            }
+            FilesCmd::Touch(args) => {
+                let res = file_ops::touch_file_under_root(&args.root.root, &args.path, args.ok_if_exists, args.dry_run)?;
                 println!("{}", serde_json::to_string_pretty(&res)?);
             }
         },
@@ -404,6 +569,36 @@ async fn main() -> anyhow::Result<()> {
                 let v: serde_json::Value = resp.json().await?;
                 println!("{}", serde_json::to_string_pretty(&v)?);
             }
+            AgentCmd::Run(args) => {
+                let client = reqwest::Client::new();
+                let base = format!("{}/v1/sessions/{}", args.id.server.server, args.id.id);
+                let mut content = args.task.clone();
+                let mut done = false;
+                for i in 0..args.max_iterations {
+                    let body = json!({ "role": "user", "content": content, "model": args.model });
+                    let resp = client.post(format!("{}/messages", base)).json(&body).send().await?;
+                    if !resp.status().is_success() { anyhow::bail!("server error: {}", resp.status()); }
+                    let v: serde_json::Value = resp.json().await?;
+                    let Some(reply) = v.get("assistant").and_then(|a| a.as_str()) else {
+                        anyhow::bail!("no assistant reply; is a model configured for this session?");
+                    };
+                    println!("{}", json!({ "iteration": i, "assistant": reply }));
+                    let Some((tool, tool_args)) = agent::engine::parse_tool_call(reply) else {
+                        done = true;
+                        break;
+                    };
+                    let resp = client.post(format!("{}/agent/tool/{}", base, tool)).json(&json!({ "args": tool_args })).send().await?;
+                    // tool failures are fed back to the model rather than aborting the run
+                    let result = if resp.status().is_success() {
+                        resp.json::<serde_json::Value>().await?
+                    } else {
+                        json!({ "error": resp.status().to_string() })
+                    };
+                    println!("{}", json!({ "iteration": i, "tool": tool, "result": result }));
+                    content = format!("Tool {} returned:\n{}", tool, result);
+                }
+                if !done { anyhow::bail!("stopped after {} iterations without completion", args.max_iterations); }
+            }
         },
     }
     Ok(())
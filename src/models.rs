@@ -1,15 +1,34 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
+use crate::storage::SessionRepository;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModelRequest {
     pub model: String,
+    pub system: Option<String>,
     pub prompt: String,
     pub temperature: Option<f32>,
     pub max_tokens: Option<u32>,
     pub top_p: Option<f32>,
 }
 
+impl ModelRequest {
+    /// Size of the assembled prompt as the model receives it: system text (repo
+    /// rules) plus the user turn. History and context are not sent separately.
+    pub fn prompt_bytes(&self) -> usize {
+        self.system.as_deref().map_or(0, str::len) + self.prompt.len()
+    }
+
+    /// Estimated tokens for the same text as [`Self::prompt_bytes`].
+    pub fn prompt_tokens(&self) -> usize {
+        self.system.as_deref().map_or(0, crate::tokens::estimate_tokens) + crate::tokens::estimate_tokens(&self.prompt)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModelResponse {
     pub content: String,
@@ -19,8 +38,15 @@ pub struct ModelResponse {
 #[async_trait]
 pub trait LanguageModel: Send + Sync {
     async fn generate(&self, req: ModelRequest) -> anyhow::Result<ModelResponse>;
+    /// Model ids the backend serves, or `None` if it can't say.
+    async fn list_models(&self) -> anyhow::Result<Option<Vec<String>>> { Ok(None) }
 }
 
+/// Connection failure, timeout, or 5xx from a backend; a [`FallbackChain`] moves on to the next backend.
+#[derive(Debug, thiserror::Error)]
+#[error("model backend unavailable: {0}")]
+pub struct BackendUnavailable(pub String);
+
 #[derive(Clone)]
 pub struct OpenAICompatible {
     pub base_url: String,
@@ -57,6 +83,12 @@ struct OaiChatResponse {
 #[derive(Debug, Deserialize)]
 struct OaiChoice { message: OaiMessage }
 
+#[derive(Debug, Deserialize)]
+struct OaiModelList { data: Vec<OaiModel> }
+
+#[derive(Debug, Deserialize)]
+struct OaiModel { id: String }
+
 #[derive(Debug, Deserialize)]
 struct OaiMessage { content: String }
 
@@ -66,17 +98,21 @@ impl LanguageModel for OpenAICompatible {
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let body = OaiChatRequest {
             model: &req.model,
-            messages: vec![serde_json::json!({"role":"user","content": req.prompt})],
+            messages: req.system.iter().map(|sys| serde_json::json!({"role":"system","content": sys}))
+                .chain(std::iter::once(serde_json::json!({"role":"user","content": req.prompt})))
+                .collect(),
             temperature: req.temperature,
             max_tokens: req.max_tokens,
             top_p: req.top_p,
         };
-        let client = reqwest::Client::new();
-        let mut rb = client.post(url).json(&body);
+        let mut rb = crate::net::http_client().post(url).json(&body);
         if let Some(key) = &self.api_key {
             rb = rb.bearer_auth(key);
         }
-        let resp = rb.send().await?;
+        let resp = rb.send().await.map_err(|e| BackendUnavailable(e.to_string()))?;
+        if resp.status().is_server_error() {
+            return Err(BackendUnavailable(format!("model call failed: {}", resp.status())).into());
+        }
         if !resp.status().is_success() {
             anyhow::bail!("model call failed: {}", resp.status());
         }
@@ -84,6 +120,170 @@ impl LanguageModel for OpenAICompatible {
         let content = v.choices.get(0).map(|c| c.message.content.clone()).unwrap_or_default();
         Ok(ModelResponse { content, model: v.model })
     }
+
+    async fn list_models(&self) -> anyhow::Result<Option<Vec<String>>> {
+        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        let mut rb = crate::net::http_client().get(url);
+        if let Some(key) = &self.api_key {
+            rb = rb.bearer_auth(key);
+        }
+        let resp = rb.send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("model list failed: {}", resp.status());
+        }
+        let v: OaiModelList = resp.json().await?;
+        Ok(Some(v.data.into_iter().map(|m| m.id).collect()))
+    }
+}
+
+pub struct ModelBackend {
+    pub name: String,
+    pub model: Arc<dyn LanguageModel>,
+    pub timeout: Duration,
+}
+
+const DEFAULT_BACKEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ordered model backends tried in turn; only [`BackendUnavailable`] errors and timeouts fall
+/// through, anything else (e.g. a 4xx for a bad request) is returned as-is. The served response's
+/// `model` is prefixed with the backend name, e.g. `fallback/gpt-4o-mini`.
+pub struct FallbackChain {
+    pub backends: Vec<ModelBackend>,
+}
+
+impl FallbackChain {
+    /// Parses `ATC_MODEL_BACKENDS`, a comma-separated list of `name=base_url[@timeout_secs]`.
+    /// Each backend's key comes from `ATC_MODEL_<NAME>_API_KEY`, falling back to `OPENAI_API_KEY`.
+    /// Returns `None` when unset or empty.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(spec) = std::env::var("ATC_MODEL_BACKENDS") else { return Ok(None) };
+        let specs = parse_backend_specs(&spec)?;
+        if specs.is_empty() { return Ok(None); }
+        let backends = specs.into_iter().map(|(name, base_url, timeout)| {
+            let api_key = std::env::var(format!("ATC_MODEL_{}_API_KEY", name.to_uppercase()))
+                .or_else(|_| std::env::var("OPENAI_API_KEY"))
+                .ok();
+            ModelBackend { name, model: Arc::new(OpenAICompatible { base_url, api_key }), timeout }
+        }).collect();
+        Ok(Some(Self { backends }))
+    }
+}
+
+fn parse_backend_specs(spec: &str) -> anyhow::Result<Vec<(String, String, Duration)>> {
+    let mut out = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, rest) = part.split_once('=').ok_or_else(|| anyhow::anyhow!("invalid backend spec: {}", part))?;
+        let (url, timeout) = match rest.rsplit_once('@') {
+            Some((url, secs)) => (url, Duration::from_secs(secs.parse().map_err(|_| anyhow::anyhow!("invalid backend timeout: {}", part))?)),
+            None => (rest, DEFAULT_BACKEND_TIMEOUT),
+        };
+        out.push((name.trim().to_string(), url.trim().to_string(), timeout));
+    }
+    Ok(out)
+}
+
+#[async_trait]
+impl LanguageModel for FallbackChain {
+    async fn generate(&self, req: ModelRequest) -> anyhow::Result<ModelResponse> {
+        let mut last_err = anyhow::anyhow!("no model backends configured");
+        for b in &self.backends {
+            match tokio::time::timeout(b.timeout, b.model.generate(req.clone())).await {
+                Ok(Ok(mut r)) => {
+                    r.model = format!("{}/{}", b.name, r.model);
+                    return Ok(r);
+                }
+                Ok(Err(e)) if e.downcast_ref::<BackendUnavailable>().is_some() => {
+                    tracing::warn!(backend = %b.name, error = %e, "model backend unavailable, trying next");
+                    last_err = e;
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    tracing::warn!(backend = %b.name, "model backend timed out, trying next");
+                    last_err = BackendUnavailable(format!("{} timed out after {:?}", b.name, b.timeout)).into();
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Union of what each reachable backend serves, since any of them may end up serving the turn.
+    async fn list_models(&self) -> anyhow::Result<Option<Vec<String>>> {
+        let mut all: Option<Vec<String>> = None;
+        for b in &self.backends {
+            if let Ok(Ok(Some(models))) = tokio::time::timeout(b.timeout, b.model.list_models()).await {
+                let acc = all.get_or_insert_with(Vec::new);
+                for m in models {
+                    if !acc.contains(&m) { acc.push(m); }
+                }
+            }
+        }
+        Ok(all)
+    }
+}
+
+/// Caches a backend's model list for `ttl` so per-message validation doesn't cost a round-trip.
+pub struct ModelCatalog {
+    ttl: Duration,
+    cached: std::sync::Mutex<Option<(std::time::Instant, Vec<String>)>>,
+}
+
+impl ModelCatalog {
+    pub fn new(ttl: Duration) -> Self { Self { ttl, cached: std::sync::Mutex::new(None) } }
+
+    /// TTL from `ATC_MODEL_LIST_TTL_SECS` (default 300).
+    pub fn from_env() -> Self {
+        let secs = std::env::var("ATC_MODEL_LIST_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(300);
+        Self::new(Duration::from_secs(secs))
+    }
+
+    /// Whether `name` is served by `model`; `None` when the backend can't list its models.
+    /// The lock isn't held across the fetch, so concurrent misses may each call
+    /// `list_models`; the last one to finish fills the cache.
+    pub async fn contains(&self, model: &dyn LanguageModel, name: &str) -> anyhow::Result<Option<bool>> {
+        if let Some((at, list)) = self.cached.lock().unwrap().as_ref()
+            && at.elapsed() < self.ttl
+        {
+            return Ok(Some(list.iter().any(|m| m == name)));
+        }
+        let Some(list) = model.list_models().await? else { return Ok(None) };
+        let found = list.iter().any(|m| m == name);
+        *self.cached.lock().unwrap() = Some((std::time::Instant::now(), list));
+        Ok(Some(found))
+    }
+}
+
+/// Reuses responses to deterministic (temperature 0) requests for `ttl`. Entries live
+/// in the session database, keyed by the whole serialized [`ModelRequest`].
+pub struct ResponseCache {
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self { Self { ttl } }
+
+    /// TTL from `ATC_RESPONSE_CACHE_TTL_SECS` (default 86400).
+    pub fn from_env() -> Self {
+        let secs = std::env::var("ATC_RESPONSE_CACHE_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(86400);
+        Self::new(Duration::from_secs(secs))
+    }
+
+    /// Serves `req` from the cache when it is deterministic, otherwise calls `model` (and
+    /// caches the reply if it could have been served). The flag is `true` on a hit. Cache
+    /// failures are logged and fall through to the backend rather than failing the turn.
+    pub async fn generate(&self, model: &dyn LanguageModel, repo: &dyn SessionRepository, req: ModelRequest) -> anyhow::Result<(ModelResponse, bool)> {
+        if req.temperature != Some(0.0) { return Ok((model.generate(req).await?, false)); }
+        let key = serde_json::to_string(&req)?;
+        match repo.get_cached_response(&key).await {
+            Ok(Some((model_used, content))) => return Ok((ModelResponse { content, model: model_used }, true)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(error = %e, "response cache lookup failed"),
+        }
+        let r = model.generate(req).await?;
+        if let Err(e) = repo.put_cached_response(&key, &r.model, &r.content, self.ttl).await {
+            tracing::warn!(error = %e, "failed to cache model response");
+        }
+        Ok((r, false))
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -95,4 +295,163 @@ impl ModelSelector {
     }
 }
 
+/// Deterministic model for exercising the agent loop: replays `responses` in order, one per
+/// `generate` call, and records each prompt it was given.
+#[cfg(test)]
+pub struct ScriptedModel {
+    responses: std::sync::Mutex<std::collections::VecDeque<String>>,
+    pub prompts: std::sync::Mutex<Vec<String>>,
+}
 
+#[cfg(test)]
+impl ScriptedModel {
+    pub fn new<S: Into<String>>(responses: Vec<S>) -> Self {
+        Self { responses: std::sync::Mutex::new(responses.into_iter().map(Into::into).collect()), prompts: std::sync::Mutex::new(Vec::new()) }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl LanguageModel for ScriptedModel {
+    async fn generate(&self, req: ModelRequest) -> anyhow::Result<ModelResponse> {
+        self.prompts.lock().unwrap().push(req.prompt);
+        let content = self.responses.lock().unwrap().pop_front().ok_or_else(|| anyhow::anyhow!("scripted model exhausted"))?;
+        Ok(ModelResponse { content, model: req.model })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Unavailable;
+
+    #[async_trait]
+    impl LanguageModel for Unavailable {
+        async fn generate(&self, _req: ModelRequest) -> anyhow::Result<ModelResponse> {
+            Err(BackendUnavailable("connection refused".into()).into())
+        }
+    }
+
+    struct Slow;
+
+    #[async_trait]
+    impl LanguageModel for Slow {
+        async fn generate(&self, req: ModelRequest) -> anyhow::Result<ModelResponse> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(ModelResponse { content: "late".into(), model: req.model })
+        }
+    }
+
+    fn backend(name: &str, model: Arc<dyn LanguageModel>) -> ModelBackend {
+        ModelBackend { name: name.into(), model, timeout: Duration::from_millis(50) }
+    }
+
+    #[tokio::test]
+    async fn fallback_chain_skips_unavailable_and_slow_backends() {
+        let chain = FallbackChain { backends: vec![
+            backend("down", Arc::new(Unavailable)),
+            backend("slow", Arc::new(Slow)),
+            backend("ok", Arc::new(ScriptedModel::new(vec!["hi"]))),
+        ] };
+        let r = chain.generate(ModelRequest { model: "m".into(), ..Default::default() }).await.unwrap();
+        assert_eq!(r.content, "hi");
+        assert_eq!(r.model, "ok/m");
+    }
+
+    #[tokio::test]
+    async fn fallback_chain_returns_non_retryable_errors() {
+        let never = Arc::new(ScriptedModel::new(vec!["unused"]));
+        let chain = FallbackChain { backends: vec![
+            backend("exhausted", Arc::new(ScriptedModel::new(Vec::<String>::new()))),
+            backend("never", never.clone()),
+        ] };
+        assert!(chain.generate(ModelRequest::default()).await.is_err());
+        assert!(never.prompts.lock().unwrap().is_empty());
+    }
+
+    struct Listing(std::sync::atomic::AtomicUsize);
+
+    #[async_trait]
+    impl LanguageModel for Listing {
+        async fn generate(&self, _req: ModelRequest) -> anyhow::Result<ModelResponse> { Ok(ModelResponse::default()) }
+        async fn list_models(&self) -> anyhow::Result<Option<Vec<String>>> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some(vec!["gpt-a".into()]))
+        }
+    }
+
+    #[tokio::test]
+    async fn model_catalog_caches_within_ttl() {
+        let backend = Listing(std::sync::atomic::AtomicUsize::new(0));
+        let catalog = ModelCatalog::new(Duration::from_secs(60));
+        assert_eq!(catalog.contains(&backend, "gpt-a").await.unwrap(), Some(true));
+        assert_eq!(catalog.contains(&backend, "gpt-z").await.unwrap(), Some(false));
+        assert_eq!(backend.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let expired = ModelCatalog::new(Duration::ZERO);
+        expired.contains(&backend, "gpt-a").await.unwrap();
+        expired.contains(&backend, "gpt-a").await.unwrap();
+        assert_eq!(backend.0.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        // backends that can't list models are not validated
+        assert_eq!(ModelCatalog::new(Duration::from_secs(60)).contains(&Unavailable, "x").await.unwrap(), None);
+    }
+
+    #[test]
+    fn prompt_bytes_counts_system_and_user_turn() {
+        let mut req = ModelRequest { model: "m".into(), system: None, prompt: "héllo".into(), temperature: None, max_tokens: None, top_p: None };
+        assert_eq!(req.prompt_bytes(), 6);
+        req.system = Some("rules".into());
+        assert_eq!(req.prompt_bytes(), 11);
+        assert_eq!(req.prompt_tokens(), crate::tokens::estimate_tokens("rules") + crate::tokens::estimate_tokens("héllo"));
+    }
+
+    #[test]
+    fn parse_backend_specs_with_timeouts() {
+        let specs = parse_backend_specs("primary=https://a.example/v1@5, local=http://127.0.0.1:8000/v1").unwrap();
+        assert_eq!(specs, vec![
+            ("primary".to_string(), "https://a.example/v1".to_string(), Duration::from_secs(5)),
+            ("local".to_string(), "http://127.0.0.1:8000/v1".to_string(), DEFAULT_BACKEND_TIMEOUT),
+        ]);
+        assert!(parse_backend_specs("nourl").is_err());
+        assert!(parse_backend_specs("a=http://x@soon").is_err());
+    }
+
+    #[tokio::test]
+    async fn response_cache_serves_repeat_deterministic_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = crate::storage::SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let model = ScriptedModel::new(vec!["first", "second", "third"]);
+        let req = ModelRequest { model: "m".into(), prompt: "explain".into(), temperature: Some(0.0), ..Default::default() };
+
+        let (r, cached) = cache.generate(&model, &repo, req.clone()).await.unwrap();
+        assert_eq!((r.content.as_str(), cached), ("first", false));
+        let (r, cached) = cache.generate(&model, &repo, req.clone()).await.unwrap();
+        assert_eq!((r.content.as_str(), r.model.as_str(), cached), ("first", "m", true));
+        assert_eq!(model.prompts.lock().unwrap().len(), 1);
+
+        // a different prompt or a non-zero temperature goes to the backend
+        let other = ModelRequest { prompt: "other".into(), ..req.clone() };
+        assert_eq!(cache.generate(&model, &repo, other).await.unwrap().0.content, "second");
+        let warm = ModelRequest { temperature: Some(0.7), ..req };
+        let (r, cached) = cache.generate(&model, &repo, warm.clone()).await.unwrap();
+        assert_eq!((r.content.as_str(), cached), ("third", false));
+        assert!(cache.generate(&model, &repo, warm).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn response_cache_entries_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = crate::storage::SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let cache = ResponseCache::new(Duration::ZERO);
+        let model = ScriptedModel::new(vec!["first", "second"]);
+        let req = ModelRequest { model: "m".into(), temperature: Some(0.0), ..Default::default() };
+        cache.generate(&model, &repo, req.clone()).await.unwrap();
+        let (r, cached) = cache.generate(&model, &repo, req).await.unwrap();
+        assert_eq!((r.content.as_str(), cached), ("second", false));
+    }
+}
@@ -0,0 +1,304 @@
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Duration;
+use url::Url;
+
+/// Exact-match check against a session's `network_allowlist`; no allowlist means no fetches.
+pub fn is_allowed_host(allowlist: &Option<Vec<String>>, host: &str) -> bool {
+    match allowlist {
+        None => false,
+        Some(list) => list.iter().any(|h| h == host),
+    }
+}
+
+/// Rejects caller-supplied fetch headers that could misroute a request or leak
+/// credentials: `Host` is never overridable, and credential headers are only
+/// sent toward allowlisted hosts. [`ReqwestFetcher`] drops them on cross-host redirects.
+pub fn check_fetch_headers(allowlist: &Option<Vec<String>>, host: &str, headers: &[(String, String)]) -> anyhow::Result<()> {
+    for (name, _) in headers {
+        let name = name.to_ascii_lowercase();
+        if name == "host" { anyhow::bail!("overriding Host is not allowed"); }
+        let credential = matches!(name.as_str(), "authorization" | "proxy-authorization" | "cookie");
+        if credential && !is_allowed_host(allowlist, host) {
+            anyhow::bail!("credential headers are only sent to allowlisted hosts");
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a response body using the charset from `Content-Type`, else a
+/// `<meta charset>` in the document head, else lossy UTF-8.
+fn decode_body(bytes: &[u8], content_type: Option<&str>) -> String {
+    let from_header = content_type.and_then(|ct| {
+        ct.split(';').map(|p| p.trim()).find_map(|p| {
+            let (k, v) = p.split_once('=')?;
+            k.trim().eq_ignore_ascii_case("charset").then(|| v.trim().trim_matches('"').to_string())
+        })
+    });
+    let label = from_header.or_else(|| {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_string();
+        let re = regex::Regex::new(r#"(?i)<meta[^>]+charset=["']?([A-Za-z0-9_\-]+)"#).ok()?;
+        re.captures(&head).map(|c| c[1].to_string())
+    });
+    match label.and_then(|l| encoding_rs::Encoding::for_label(l.as_bytes())) {
+        Some(enc) => enc.decode(bytes).0.into_owned(),
+        None => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+fn user_agent() -> String {
+    std::env::var("ATC_HTTP_USER_AGENT").unwrap_or_else(|_| concat!("air_traffic_control/", env!("CARGO_PKG_VERSION")).to_string())
+}
+
+const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(120);
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 10;
+
+fn build_client(redirect: reqwest::redirect::Policy) -> reqwest::Client {
+    let timeout = std::env::var("ATC_HTTP_TIMEOUT_SECS").ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_HTTP_TIMEOUT);
+    reqwest::Client::builder()
+        .user_agent(user_agent())
+        .timeout(timeout)
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .redirect(redirect)
+        .build()
+        .expect("http client configuration is static")
+}
+
+/// Process-wide HTTP client shared by model and embedding calls, so connections
+/// and TLS sessions are pooled across requests. The overall timeout defaults to
+/// 120s (`ATC_HTTP_TIMEOUT_SECS` overrides); callers with a tighter budget set it
+/// per request.
+pub fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build_client(reqwest::redirect::Policy::limited(MAX_REDIRECTS)))
+}
+
+/// Like [`http_client`] but never follows redirects: URL fetches follow them by
+/// hand so every hop is checked against the session allowlist.
+fn page_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build_client(reqwest::redirect::Policy::none()))
+}
+
+/// Resolves a `Location` header against the URL that sent it, refusing hosts
+/// outside the allowlist.
+fn redirect_target(from: &Url, location: &str, allowlist: &Option<Vec<String>>) -> anyhow::Result<Url> {
+    let to = from.join(location)?;
+    if !matches!(to.scheme(), "http" | "https") { anyhow::bail!("redirect to unsupported scheme {}", to.scheme()); }
+    let host = to.host_str().unwrap_or_default();
+    if !is_allowed_host(allowlist, host) { anyhow::bail!("redirect to non-allowlisted host {}", host); }
+    Ok(to)
+}
+
+/// Raw HTML plus the URL it was finally served from (after redirects).
+#[derive(Debug)]
+pub struct FetchedPage {
+    pub final_url: String,
+    pub html: String,
+}
+
+/// Result of URL ingestion: extracted text along with citation details.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedPage {
+    pub title: Option<String>,
+    pub final_url: String,
+    pub content: String,
+}
+
+/// Retrieves raw page HTML for URL ingestion. Callers enforce the host allowlist before fetching.
+#[async_trait::async_trait]
+pub trait Fetcher: Send + Sync {
+    async fn fetch(&self, url: &str, max_bytes: usize, headers: &[(String, String)]) -> anyhow::Result<FetchedPage>;
+}
+
+/// Plain HTTP GET; the default fetcher. Redirects are followed only to hosts in
+/// `allowlist`.
+pub struct ReqwestFetcher {
+    pub allowlist: Option<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl Fetcher for ReqwestFetcher {
+    async fn fetch(&self, url: &str, max_bytes: usize, headers: &[(String, String)]) -> anyhow::Result<FetchedPage> {
+        let origin = Url::parse(url)?;
+        let mut current = origin.clone();
+        let mut hops = 0;
+        let resp = loop {
+            let same_host = current.host_str() == origin.host_str();
+            let mut rb = page_client().get(current.clone());
+            for (k, v) in headers {
+                let credential = matches!(k.to_ascii_lowercase().as_str(), "authorization" | "proxy-authorization" | "cookie");
+                if credential && !same_host { continue; }
+                rb = rb.header(k.as_str(), v.as_str());
+            }
+            let resp = rb.send().await?;
+            if !resp.status().is_redirection() { break resp; }
+            let Some(location) = resp.headers().get(reqwest::header::LOCATION).and_then(|v| v.to_str().ok()).map(String::from) else { break resp; };
+            hops += 1;
+            if hops > MAX_REDIRECTS { anyhow::bail!("too many redirects"); }
+            current = redirect_target(&current, &location, &self.allowlist)?;
+        };
+        let status = resp.status();
+        if !status.is_success() { anyhow::bail!("fetch failed: {}", status); }
+        let final_url = resp.url().to_string();
+        let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+        // reqwest has already undone gzip/brotli content-encoding here
+        let bytes = resp.bytes().await?;
+        let slice = if bytes.len() > max_bytes { &bytes[..max_bytes] } else { &bytes };
+        Ok(FetchedPage { final_url, html: decode_body(slice, content_type.as_deref()) })
+    }
+}
+
+/// Runs an external headless-browser command (`<program> <url>`) and reads rendered HTML from stdout.
+#[cfg(feature = "render")]
+pub struct CommandFetcher {
+    pub program: String,
+}
+
+#[cfg(feature = "render")]
+#[async_trait::async_trait]
+impl Fetcher for CommandFetcher {
+    async fn fetch(&self, url: &str, max_bytes: usize, _headers: &[(String, String)]) -> anyhow::Result<FetchedPage> {
+        let out = tokio::process::Command::new(&self.program).arg(url).output().await?;
+        if !out.status.success() { anyhow::bail!("render command failed: {}", out.status); }
+        let slice = if out.stdout.len() > max_bytes { &out.stdout[..max_bytes] } else { &out.stdout[..] };
+        Ok(FetchedPage { final_url: url.to_string(), html: String::from_utf8_lossy(slice).to_string() })
+    }
+}
+
+/// Picks the fetcher for a host: hosts listed in `ATC_RENDER_HOSTS` go through
+/// `ATC_RENDER_COMMAND` when the `render` feature is enabled.
+pub fn fetcher_for(host: &str, allowlist: &Option<Vec<String>>) -> Box<dyn Fetcher> {
+    #[cfg(feature = "render")]
+    {
+        let flagged = std::env::var("ATC_RENDER_HOSTS").ok()
+            .map(|v| v.split(',').any(|h| h.trim() == host))
+            .unwrap_or(false);
+        if let (true, Ok(program)) = (flagged, std::env::var("ATC_RENDER_COMMAND")) {
+            return Box::new(CommandFetcher { program });
+        }
+    }
+    let _ = host;
+    Box::new(ReqwestFetcher { allowlist: allowlist.clone() })
+}
+
+fn extract_page(page: FetchedPage) -> ExtractedPage {
+    let doc = scraper::Html::parse_document(&page.html);
+    let title_sel = scraper::Selector::parse("title").unwrap();
+    let title = doc.select(&title_sel).next()
+        .map(|t| t.text().collect::<String>().trim().to_string())
+        .filter(|t| !t.is_empty());
+    let selector = scraper::Selector::parse("body").unwrap();
+    let mut text = String::new();
+    for el in doc.select(&selector) {
+        text.push_str(&el.text().collect::<Vec<_>>().join(" "));
+        text.push('\n');
+    }
+    let content = if text.is_empty() { page.html } else { text };
+    ExtractedPage { title, final_url: page.final_url, content }
+}
+
+pub async fn fetch_and_extract(url: &str, max_bytes: usize, headers: &[(String, String)], allowlist: &Option<Vec<String>>) -> anyhow::Result<ExtractedPage> {
+    let host = Url::parse(url)?.host_str().unwrap_or_default().to_string();
+    fetch_and_extract_with(fetcher_for(&host, allowlist).as_ref(), url, max_bytes, headers).await
+}
+
+pub async fn fetch_and_extract_with(fetcher: &dyn Fetcher, url: &str, max_bytes: usize, headers: &[(String, String)]) -> anyhow::Result<ExtractedPage> {
+    let page = fetcher.fetch(url, max_bytes, headers).await?;
+    Ok(extract_page(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist_is_exact_and_closed_by_default() {
+        assert!(!is_allowed_host(&None, "docs.example.com"));
+        let allow = Some(vec!["docs.example.com".to_string()]);
+        assert!(is_allowed_host(&allow, "docs.example.com"));
+        assert!(!is_allowed_host(&allow, "evil.docs.example.com"));
+    }
+
+    #[test]
+    fn redirects_stay_on_allowlisted_hosts() {
+        let allow = Some(vec!["docs.example.com".to_string()]);
+        let from = Url::parse("https://docs.example.com/a/b").unwrap();
+        assert_eq!(redirect_target(&from, "../c", &allow).unwrap().as_str(), "https://docs.example.com/c");
+        assert!(redirect_target(&from, "https://evil.example.com/", &allow).is_err());
+        assert!(redirect_target(&from, "//evil.example.com/x", &allow).is_err());
+        assert!(redirect_target(&from, "file:///etc/passwd", &allow).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetcher_refuses_redirect_off_the_allowlist() {
+        use axum::{response::Redirect, routing::get, Router};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/", get(|| async { "landed" }))
+            .route("/same", get(|| async { Redirect::temporary("/") }))
+            .route("/away", get(move || async move { Redirect::temporary(&format!("http://localhost:{}/", addr.port())) }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap(); });
+
+        let f = ReqwestFetcher { allowlist: Some(vec!["127.0.0.1".to_string()]) };
+        let page = f.fetch(&format!("http://{addr}/same"), 1024, &[]).await.unwrap();
+        assert_eq!(page.html, "landed");
+        let err = f.fetch(&format!("http://{addr}/away"), 1024, &[]).await.unwrap_err();
+        assert!(err.to_string().contains("non-allowlisted"), "{err}");
+    }
+
+    #[test]
+    fn http_client_is_built_once() {
+        assert!(std::ptr::eq(http_client(), http_client()));
+    }
+
+    struct StaticFetcher(&'static str);
+
+    #[async_trait::async_trait]
+    impl Fetcher for StaticFetcher {
+        async fn fetch(&self, url: &str, _max_bytes: usize, _headers: &[(String, String)]) -> anyhow::Result<FetchedPage> {
+            Ok(FetchedPage { final_url: format!("{}final", url), html: self.0.to_string() })
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_and_extract_uses_pluggable_fetcher() {
+        let f = StaticFetcher("<html><body><div id=app>rendered docs</div></body></html>");
+        let page = fetch_and_extract_with(&f, "https://docs.example.com/", 1024, &[]).await.unwrap();
+        assert!(page.content.contains("rendered docs"));
+    }
+
+    #[tokio::test]
+    async fn fetch_and_extract_returns_title_and_final_url() {
+        let f = StaticFetcher("<html><head><title> Guide </title></head><body>text</body></html>");
+        let page = fetch_and_extract_with(&f, "https://docs.example.com/", 1024, &[]).await.unwrap();
+        assert_eq!(page.title.as_deref(), Some("Guide"));
+        assert_eq!(page.final_url, "https://docs.example.com/final");
+    }
+
+    #[test]
+    fn fetch_headers_block_host_and_unlisted_credentials() {
+        let allow = Some(vec!["docs.example.com".to_string()]);
+        let h = |k: &str| vec![(k.to_string(), "x".to_string())];
+        assert!(check_fetch_headers(&allow, "docs.example.com", &h("Accept")).is_ok());
+        assert!(check_fetch_headers(&allow, "docs.example.com", &h("Authorization")).is_ok());
+        assert!(check_fetch_headers(&allow, "docs.example.com", &h("Host")).is_err());
+        assert!(check_fetch_headers(&allow, "other.example.com", &h("authorization")).is_err());
+    }
+
+    #[test]
+    fn decode_body_honors_header_and_meta_charset() {
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("日本語");
+        assert_eq!(decode_body(&sjis, Some("text/html; charset=Shift_JIS")), "日本語");
+
+        let latin1 = b"<html><head><meta charset=\"iso-8859-1\"></head><body>caf\xe9</body></html>";
+        assert!(decode_body(latin1, Some("text/html")).contains("café"));
+
+        assert_eq!(decode_body("plain ✓".as_bytes(), None), "plain ✓");
+    }
+}
@@ -0,0 +1,64 @@
+/// One page of `data` starting at offset `cursor` (default 0), plus the offset of the
+/// next page, or `None` when this page reaches the end. A cursor at or past the end
+/// yields an empty page.
+pub fn paginate<T: Clone>(data: &[T], cursor: Option<usize>, limit: usize) -> (Vec<T>, Option<usize>) {
+    let start = cursor.unwrap_or(0);
+    if start >= data.len() { return (Vec::new(), None); }
+    let end = start.saturating_add(limit).min(data.len());
+    let page = data[start..end].to_vec();
+    let next = if end < data.len() { Some(end) } else { None };
+    (page, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input_has_no_pages() {
+        let data: Vec<u32> = Vec::new();
+        assert_eq!(paginate(&data, None, 10), (vec![], None));
+        assert_eq!(paginate(&data, Some(3), 10), (vec![], None));
+    }
+
+    #[test]
+    fn cursor_past_end_is_empty() {
+        let data = [1, 2, 3];
+        assert_eq!(paginate(&data, Some(3), 2), (vec![], None));
+        assert_eq!(paginate(&data, Some(99), 2), (vec![], None));
+    }
+
+    #[test]
+    fn exact_page_boundary_ends_without_next_cursor() {
+        let data = [1, 2, 3, 4];
+        assert_eq!(paginate(&data, None, 2), (vec![1, 2], Some(2)));
+        assert_eq!(paginate(&data, Some(2), 2), (vec![3, 4], None));
+    }
+
+    #[test]
+    fn last_partial_page_is_clamped() {
+        let data = [1, 2, 3, 4, 5];
+        assert_eq!(paginate(&data, Some(4), 2), (vec![5], None));
+        assert_eq!(paginate(&data, Some(3), 2), (vec![4, 5], None));
+    }
+
+    #[test]
+    fn limit_larger_than_data_returns_everything() {
+        let data = [1, 2, 3];
+        assert_eq!(paginate(&data, None, 100), (vec![1, 2, 3], None));
+        assert_eq!(paginate(&data, Some(1), usize::MAX), (vec![2, 3], None));
+    }
+
+    #[test]
+    fn following_next_cursor_visits_each_item_once() {
+        let data: Vec<u32> = (0..7).collect();
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (page, next) = paginate(&data, cursor, 3);
+            seen.extend(page);
+            match next { Some(n) => cursor = Some(n), None => break }
+        }
+        assert_eq!(seen, data);
+    }
+}
@@ -1,190 +1,980 @@
 use axum::{routing::{get, post, delete}, Json, Router};
 use axum::extract::Query;
-use axum::http::StatusCode;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::response::sse::{Event, KeepAlive, Sse};
 use serde::{Deserialize, Serialize};
 use std::{net::SocketAddr, sync::Arc};
 use uuid::Uuid;
 
-use crate::models::{LanguageModel, ModelRequest, OpenAICompatible, ModelSelector};
-use crate::discovery::{list_files, search_files, read_file_under_root};
-use crate::file_ops::{write_file_under_root, move_file_under_root, delete_file_under_root};
-use crate::git_ops::{status as git_status, diff_porcelain as git_diff, add_all as git_add_all, commit as git_commit};
-use crate::settings::{SessionSettings, SessionSettingsPatch};
+use crate::models::{LanguageModel, ModelCatalog, ModelRequest, ModelSelector, ResponseCache};
+use crate::discovery::{list_files_with, search_files_with_progress, read_file_under_root_with, grep_files, tree as file_tree, GrepLimits, PatternOptions, WalkOptions, DEFAULT_TREE_DEPTH};
+use crate::file_ops::{Confirm, write_file_under_root_with_mode, move_file_under_root, delete_file_under_root, touch_file_under_root};
+use crate::git_ops::{status as git_status, status_with as git_status_with, diff_with as git_diff_with, add_all as git_add_all, commit_signed as git_commit, head_oid as git_head_oid, log as git_log, file_history as git_file_history, with_trailers, CommitSigning, PathBase, DEFAULT_LOG_MAX};
+use crate::settings::{RequestOverrides, SessionSettings, SessionSettingsPatch, DEFAULT_SUMMARY_CHARS, global_defaults, resolve_effective_settings, resolve_commit_trailers, resolve_dry_run, resolve_git_dry_run, resolve_git_remote_dry_run, resolve_max_prompt_bytes, resolve_max_prompt_tokens};
 use url::Url;
 use metrics::Unit;
-use crate::storage::{SqliteSessionRepository, SessionRepository};
-use chrono::Utc;
+use crate::storage::{SqliteSessionRepository, SessionRepository, SessionDeleteFilter, SessionPreview, SessionSummary, ToolEventFilter};
+use crate::session::{MessageStatus, Role};
+use chrono::{DateTime, Utc};
 // use std::fs; // no longer used here; file writes handled by agent engine
 use crate::agent::engine::{AgentContext, EngineCommand, execute};
+use crate::agent::tools::ToolStatus;
+use crate::net::{check_fetch_headers, fetch_and_extract, is_allowed_host};
+use crate::pagination::paginate;
 
 #[derive(Clone)]
 pub struct AppState {
     pub repo: Arc<SqliteSessionRepository>,
-    pub model: Option<OpenAICompatible>,
+    pub model: Option<Arc<dyn LanguageModel>>,
+    /// Cached `/v1/models` list used when a session sets `validate_model`.
+    pub model_catalog: Arc<ModelCatalog>,
+    /// Replies to deterministic requests, for sessions with `cache_responses`.
+    pub response_cache: Arc<ResponseCache>,
+    /// Cancellation handles for in-flight `POST /messages` turns.
+    pub inflight: Arc<InflightTurns>,
+    /// Shared secret for `/v1/admin/*`; admin routes are disabled when unset.
+    pub admin_key: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// One cancellable turn per session; a newer turn replaces (but does not cancel) an older handle.
+#[derive(Default)]
+pub struct InflightTurns {
+    turns: std::sync::Mutex<std::collections::HashMap<Uuid, (Uuid, tokio_util::sync::CancellationToken)>>,
+}
+
+impl InflightTurns {
+    /// Registers a turn, returning its id and token.
+    fn begin(&self, session_id: Uuid) -> (Uuid, tokio_util::sync::CancellationToken) {
+        let turn = (Uuid::new_v4(), tokio_util::sync::CancellationToken::new());
+        self.turns.lock().unwrap().insert(session_id, turn.clone());
+        turn
+    }
+
+    /// Removes the handle if it still belongs to `turn_id`.
+    fn finish(&self, session_id: Uuid, turn_id: Uuid) {
+        let mut turns = self.turns.lock().unwrap();
+        if turns.get(&session_id).is_some_and(|(t, _)| *t == turn_id) {
+            turns.remove(&session_id);
+        }
+    }
+
+    /// Cancels the session's in-flight turn; false if none was running.
+    fn cancel(&self, session_id: Uuid) -> bool {
+        match self.turns.lock().unwrap().remove(&session_id) {
+            Some((_, token)) => { token.cancel(); true }
+            None => false,
+        }
+    }
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Reuses a sane client-supplied `X-Request-Id`, otherwise mints a UUID.
+fn request_id_from(headers: &HeaderMap) -> String {
+    headers.get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Runs the request inside a span carrying its id and echoes the id back in the response.
+async fn propagate_request_id(req: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    use tracing::Instrument;
+    let id = request_id_from(req.headers());
+    let span = tracing::info_span!("request", request_id = %id, method = %req.method(), path = %req.uri().path());
+    let mut resp = next.run(req).instrument(span).await;
+    if let Ok(v) = header::HeaderValue::from_str(&id) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, v);
+    }
+    resp
+}
+
+/// Query params whose names contain any of these are masked in access logs.
+const SENSITIVE_PARAM_MARKERS: &[&str] = &["key", "token", "secret", "password", "auth"];
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "x-admin-key", "x-api-key"];
+const REDACTED: &str = "[redacted]";
+
+fn redact_query(query: &str) -> String {
+    query.split('&').map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        let lower = k.to_ascii_lowercase();
+        if !v.is_empty() && SENSITIVE_PARAM_MARKERS.iter().any(|m| lower.contains(m)) {
+            format!("{}={}", k, REDACTED)
+        } else {
+            pair.to_string()
+        }
+    }).collect::<Vec<_>>().join("&")
+}
+
+fn redact_headers(headers: &HeaderMap) -> String {
+    headers.iter().map(|(name, value)| {
+        let v = if SENSITIVE_HEADERS.contains(&name.as_str()) { REDACTED } else { value.to_str().unwrap_or("<binary>") };
+        format!("{}: {}", name, v)
+    }).collect::<Vec<_>>().join(", ")
+}
+
+/// Logs one line per request (method, path, redacted query and headers, status, latency). Bodies are never read.
+async fn access_log(req: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let started = std::time::Instant::now();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().map(redact_query).unwrap_or_default();
+    let headers = redact_headers(req.headers());
+    let resp = next.run(req).await;
+    tracing::info!(target: "access", %method, %path, %query, %headers, status = resp.status().as_u16(), latency_ms = started.elapsed().as_millis() as u64, "request");
+    resp
+}
+
+fn access_log_enabled() -> bool {
+    matches!(std::env::var("ATC_ACCESS_LOG").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Non-standard "client closed request" status used for cancelled turns.
+fn status_cancelled() -> StatusCode { StatusCode::from_u16(499).unwrap_or(StatusCode::REQUEST_TIMEOUT) }
+
+/// Rejects mutating requests against a read-only session with `403`.
+fn ensure_writable(settings: &SessionSettings) -> Result<(), StatusCode> {
+    if settings.is_read_only() { Err(StatusCode::FORBIDDEN) } else { Ok(()) }
+}
+
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), StatusCode> {
+    check_admin_key(state.admin_key.as_deref(), headers)
+}
+
+fn check_admin_key(admin_key: Option<&str>, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(key) = admin_key else { return Err(StatusCode::FORBIDDEN) };
+    let given = headers.get("x-admin-key").and_then(|v| v.to_str().ok());
+    if given == Some(key) { Ok(()) } else { Err(StatusCode::UNAUTHORIZED) }
+}
+
+/// A read-only session accepts no settings changes except toggling `read_only`
+/// itself, and that only with the admin key; otherwise anyone could lift it.
+fn check_read_only_patch(settings: &SessionSettings, patch: &SessionSettingsPatch, admin_key: Option<&str>, headers: &HeaderMap) -> Result<(), StatusCode> {
+    if !settings.is_read_only() { return Ok(()); }
+    let only_read_only = *patch == SessionSettingsPatch { read_only: patch.read_only, ..Default::default() };
+    if !only_read_only { return Err(StatusCode::FORBIDDEN); }
+    check_admin_key(admin_key, headers)
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
 pub struct CreateSessionBody {
     pub client_id: Option<String>,
+    #[schema(value_type = Option<Object>)]
     pub settings: Option<SessionSettings>,
+    /// Caller-managed id; an existing session with this id is returned as-is.
+    pub id: Option<Uuid>,
+    /// Derive the id from `client_id` (UUIDv5), making creation idempotent per client.
+    #[serde(default)]
+    pub id_from_client_id: bool,
+}
+
+/// Namespace for ids derived from `client_id`; fixed so derivation is stable across servers.
+const CLIENT_SESSION_NAMESPACE: Uuid = Uuid::from_u128(0x6f1d_3c2a_9b4e_4d7a_8c55_2e0f_a1b3_c4d5);
+
+/// The id a get-or-create request targets, if it names one.
+fn requested_session_id(body: &CreateSessionBody) -> Result<Option<Uuid>, StatusCode> {
+    if let Some(id) = body.id { return Ok(Some(id)); }
+    if !body.id_from_client_id { return Ok(None); }
+    let client_id = body.client_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    Ok(Some(Uuid::new_v5(&CLIENT_SESSION_NAMESPACE, client_id.as_bytes())))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, utoipa::ToSchema)]
 pub struct CreateSessionResponse {
     pub id: Uuid,
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions",
+    request_body = CreateSessionBody,
+    responses((status = 201, description = "Session created; `Location` points at it", body = CreateSessionResponse), (status = 200, description = "A session with the requested id already exists", body = CreateSessionResponse), (status = 400, description = "id_from_client_id without a client_id, project_root is not a directory, or the worktree could not be checked out")),
+)]
 async fn create_session(
     axum::extract::State(state): axum::extract::State<AppState>,
     Json(body): Json<CreateSessionBody>,
-) -> Json<CreateSessionResponse> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions", "method" => "POST"); }
+    if let Some(root) = body.settings.as_ref().and_then(|s| s.project_root.as_deref())
+        && let Err(e) = crate::discovery::validate_root(root)
+    {
+        return Ok(discovery_failure(e));
+    }
+    if let Some(id) = requested_session_id(&body)? {
+        let created = state.repo.get_or_create_session(id, body.client_id.clone(), body.settings.unwrap_or_default()).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !created { return Ok(Json(CreateSessionResponse { id }).into_response()); }
+        if let Err(resp) = provision_worktree(&state, id).await { return Ok(resp); }
+        return Ok(session_created(id));
+    }
     let settings = body.settings.unwrap_or_default();
     let id = state.repo.create_session(body.client_id.clone(), settings).await.expect("create session");
-    Json(CreateSessionResponse { id })
+    if let Err(resp) = provision_worktree(&state, id).await { return Ok(resp); }
+    Ok(session_created(id))
+}
+
+/// Checks out the new session's own worktree of `worktree_base`, named after the
+/// session id, and points `project_root` at it. A session that already has a
+/// `project_root` keeps it. On failure the session is deleted again.
+async fn provision_worktree(state: &AppState, id: Uuid) -> Result<(), Response> {
+    let internal = |_| StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    let Some(mut session) = state.repo.get_session(id).await.map_err(internal)? else { return Ok(()) };
+    let Some(base) = session.settings.worktree_base.clone() else { return Ok(()) };
+    if session.settings.project_root.is_some() { return Ok(()); }
+    let name = id.to_string();
+    let added = tokio::task::spawn_blocking(move || crate::git_ops::add_worktree(&base, &name)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+    let path = match added {
+        Ok(path) => path,
+        Err(e) => {
+            let _ = state.repo.delete_session(id).await;
+            return Err((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": format!("worktree: {e}")}))).into_response());
+        }
+    };
+    session.settings.project_root = Some(path.to_string_lossy().to_string());
+    state.repo.update_settings(id, session.settings).await.map_err(internal)
+}
+
+/// `201 Created` with `Location: /v1/sessions/:id`.
+fn session_created(id: Uuid) -> Response {
+    (StatusCode::CREATED, [(header::LOCATION, format!("/v1/sessions/{}", id))], Json(CreateSessionResponse { id })).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 200, description = "Session metadata, settings and history counts", body = SessionSummary), (status = 404, description = "Unknown session")),
+)]
+async fn get_session_resource(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+) -> Result<Json<SessionSummary>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id", "method" => "GET"); }
+    let summary = state.repo.session_summary(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(summary))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/v1/sessions/{id}",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 204, description = "Session deleted"), (status = 404, description = "Unknown session")),
+)]
 async fn delete_session(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
 ) -> Result<axum::http::StatusCode, axum::http::StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id", "method" => "DELETE"); }
+    let worktree_base = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.and_then(|s| s.settings.worktree_base);
     let ok = state.repo.delete_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     if ok {
+        if let Some(base) = worktree_base {
+            let name = id.to_string();
+            match tokio::task::spawn_blocking(move || crate::git_ops::remove_worktree(&base, &name)).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::warn!(session = %id, error = %e, "failed to prune session worktree"),
+                Err(e) => tracing::warn!(session = %id, error = %e, "failed to prune session worktree"),
+            }
+        }
         Ok(axum::http::StatusCode::NO_CONTENT)
     } else {
         Err(axum::http::StatusCode::NOT_FOUND)
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BulkDeleteQuery { before: Option<DateTime<Utc>> }
+
+#[utoipa::path(
+    delete,
+    path = "/v1/sessions",
+    params(BulkDeleteQuery),
+    responses((status = 200, description = "Number of sessions deleted", body = serde_json::Value), (status = 400, description = "No filter given")),
+)]
+async fn delete_sessions(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Query(q): Query<BulkDeleteQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions", "method" => "DELETE"); }
+    let filter = SessionDeleteFilter { before: q.before };
+    if filter.is_empty() { return Err(StatusCode::BAD_REQUEST); }
+    let deleted = state.repo.delete_sessions_where(&filter).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(serde_json::json!({"deleted": deleted})))
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
 pub struct ListSessionsResponse {
     pub sessions: Vec<Uuid>,
 }
 
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListSessionsQuery {
+    /// Only sessions created with this `client_id`.
+    client_id: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions",
+    params(ListSessionsQuery),
+    responses((status = 200, description = "Session ids", body = ListSessionsResponse)),
+)]
 async fn list_sessions(
     axum::extract::State(state): axum::extract::State<AppState>,
+    Query(q): Query<ListSessionsQuery>,
 ) -> Json<ListSessionsResponse> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions", "method" => "GET"); }
-    let ids = state.repo.list_sessions().await.unwrap_or_default();
-    Json(ListSessionsResponse { sessions: ids })
+    let ids = match q.client_id.as_deref() {
+        Some(client_id) => state.repo.list_sessions_for_client(client_id).await,
+        None => state.repo.list_sessions().await,
+    };
+    Json(ListSessionsResponse { sessions: ids.unwrap_or_default() })
 }
 
-#[derive(Debug, Serialize)]
+/// Default and maximum `limit` for `GET /v1/sessions/recent`.
+const DEFAULT_RECENT_SESSIONS: usize = 20;
+const MAX_RECENT_SESSIONS: usize = 200;
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RecentSessionsQuery { limit: Option<usize> }
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct RecentSessionsResponse {
+    pub sessions: Vec<SessionPreview>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/recent",
+    params(RecentSessionsQuery),
+    responses((status = 200, description = "Most recently active sessions with previews", body = RecentSessionsResponse)),
+)]
+async fn list_recent_sessions(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Query(q): Query<RecentSessionsQuery>,
+) -> Result<Json<RecentSessionsResponse>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/recent", "method" => "GET"); }
+    let limit = q.limit.unwrap_or(DEFAULT_RECENT_SESSIONS).min(MAX_RECENT_SESSIONS);
+    let sessions = state.repo.recent_sessions(limit).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(RecentSessionsResponse { sessions }))
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
 struct SessionSettingsResponse {
+    #[schema(value_type = Object)]
     settings: SessionSettings,
+    /// Bumped on every settings write; send it back as `If-Match` to PATCH safely.
+    version: i64,
+    /// Non-fatal problems spotted in the settings, such as an unknown `default_model`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
 }
 
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(inm) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) else { return false };
+    // weak comparison: ignore the W/ prefix on either side
+    let wanted = etag.trim_start_matches("W/");
+    inm.split(',').map(|t| t.trim()).any(|t| t == "*" || t.trim_start_matches("W/") == wanted)
+}
+
+fn etag_response<T: Serialize>(headers: &HeaderMap, etag: String, body: T) -> Response {
+    if etag_matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    ([(header::ETAG, etag)], Json(body)).into_response()
+}
+
+fn hash_etag<T: Serialize>(body: &T) -> Result<String, StatusCode> {
+    use std::hash::{Hash, Hasher};
+    let bytes = serde_json::to_vec(body).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut h);
+    Ok(format!("W/\"{:016x}\"", h.finish()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/settings",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 200, description = "Current settings", body = SessionSettingsResponse), (status = 304, description = "Matches If-None-Match"), (status = 404, description = "Unknown session")),
+)]
 async fn get_session_settings(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
-) -> Result<Json<SessionSettingsResponse>, StatusCode> {
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/settings", "method" => "GET"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    match s { Some(sess) => Ok(Json(SessionSettingsResponse { settings: sess.settings })), None => Err(StatusCode::NOT_FOUND) }
+    match s {
+        Some(sess) => {
+            let etag = format!("W/\"settings-{}\"", sess.settings_version);
+            Ok(etag_response(&headers, etag, SessionSettingsResponse { settings: sess.settings, version: sess.settings_version, warnings: Vec::new() }))
+        }
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Page size for /history and /context when the caller gives none, and the most either returns.
+const DEFAULT_PAGE_LIMIT: usize = 50;
+const MAX_PAGE_LIMIT: usize = 200;
+
+fn page_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct HistoryQuery {
     kind: String,            // "messages" | "tools"
     cursor: Option<usize>,   // offset
     limit: Option<usize>,    // page size
+    status: Option<String>,  // tools only: "ok" | "error"
+    tool: Option<String>,    // tools only: tool name
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
 struct HistoryResponse {
     kind: String,
+    #[schema(value_type = Vec<Object>)]
     items: serde_json::Value,
     next_cursor: Option<usize>,
 }
 
-fn paginate<T: Clone>(data: &[T], cursor: Option<usize>, limit: usize) -> (Vec<T>, Option<usize>) {
-    let start = cursor.unwrap_or(0);
-    if start >= data.len() { return (Vec::new(), None); }
-    let end = (start + limit).min(data.len());
-    let page = data[start..end].to_vec();
-    let next = if end < data.len() { Some(end) } else { None };
-    (page, next)
-}
-
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/history",
+    params(("id" = Uuid, Path, description = "Session id"), HistoryQuery),
+    responses((status = 200, description = "One page of history", body = HistoryResponse), (status = 304, description = "Matches If-None-Match"), (status = 400, description = "Unknown kind"), (status = 404, description = "Unknown session")),
+)]
 async fn get_session_history(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Query(q): Query<HistoryQuery>,
-) -> Result<Json<HistoryResponse>, StatusCode> {
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/history", "method" => "GET"); }
-    let limit = q.limit.unwrap_or(50).min(200).max(1);
+    let limit = page_limit(q.limit);
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
 
-    match q.kind.as_str() {
+    let resp = match q.kind.as_str() {
         "messages" => {
             let (items, next) = paginate(&s.messages, q.cursor, limit);
             let items = serde_json::to_value(items).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            Ok(Json(HistoryResponse { kind: "messages".into(), items, next_cursor: next }))
+            HistoryResponse { kind: "messages".into(), items, next_cursor: next }
         }
         "tools" => {
-            let (items, next) = paginate(&s.tool_history, q.cursor, limit);
+            let filter = ToolEventFilter { status: q.status.clone(), tool: q.tool.clone() };
+            let events = state.repo.get_tool_events(id, &filter).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let (items, next) = paginate(&events, q.cursor, limit);
             let items = serde_json::to_value(items).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            Ok(Json(HistoryResponse { kind: "tools".into(), items, next_cursor: next }))
+            HistoryResponse { kind: "tools".into(), items, next_cursor: next }
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let etag = hash_etag(&resp)?;
+    Ok(etag_response(&headers, etag, resp))
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryCountQuery {
+    kind: String, // "messages" | "tools"
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+struct HistoryCountResponse {
+    kind: String,
+    count: u64,
+    /// Tool events per tool name; `kind=tools` only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by_tool: Option<std::collections::BTreeMap<String, u64>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/history/count",
+    params(("id" = Uuid, Path, description = "Session id"), HistoryCountQuery),
+    responses((status = 200, description = "Number of messages or tool events", body = HistoryCountResponse), (status = 400, description = "Unknown kind"), (status = 404, description = "Unknown session")),
+)]
+async fn count_session_history(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<HistoryCountQuery>,
+) -> Result<Json<HistoryCountResponse>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/history/count", "method" => "GET"); }
+    let counts = state.repo.history_counts(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let resp = match q.kind.as_str() {
+        "messages" => HistoryCountResponse { kind: "messages".into(), count: counts.messages, by_tool: None },
+        "tools" => HistoryCountResponse { kind: "tools".into(), count: counts.tool_events, by_tool: Some(counts.by_tool) },
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    Ok(Json(resp))
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PageQuery {
+    cursor: Option<usize>,   // offset
+    limit: Option<usize>,    // page size
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/context",
+    params(("id" = Uuid, Path, description = "Session id"), PageQuery),
+    responses((status = 200, description = "One page of context items", body = serde_json::Value), (status = 404, description = "Unknown session")),
+)]
+async fn list_session_context(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<PageQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/context", "method" => "GET"); }
+    // same offset cursor and limits as /history
+    let limit = page_limit(q.limit);
+    let start = q.cursor.unwrap_or(0);
+    state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    // fetch one extra row to learn whether another page exists
+    let mut items = state.repo.list_context_items_page(id, start, limit + 1).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let next = if items.len() > limit { Some(start + limit) } else { None };
+    items.truncate(limit);
+    Ok(Json(serde_json::json!({"items": items, "next_cursor": next})))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/context/budget",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 200, description = "used_bytes, max_bytes, used_tokens, max_tokens and item_count", body = serde_json::Value), (status = 404, description = "Unknown session")),
+)]
+async fn get_session_context_budget(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/context/budget", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let ctx = crate::agent::tools::ToolContext { repo: &*state.repo, session_id: id, settings: &s.settings, progress: None };
+    let budget = crate::agent::tools::context_tools::budget(&ctx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(budget))
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct TextContextBody {
+    /// Shown as the item's source, e.g. "stack trace" or "spec §4.2"
+    label: String,
+    text: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/context/text",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = TextContextBody,
+    responses((status = 200, description = "Text stored as context; includes the updated budget", body = serde_json::Value), (status = 400, description = "Empty label or text, or over the context budget"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session")),
+)]
+async fn add_session_context_text(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Json(b): Json<TextContextBody>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/context/text", "method" => "POST"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    let ctx = crate::agent::tools::ToolContext { repo: &*state.repo, session_id: id, settings: &s.settings, progress: None };
+    match crate::agent::tools::context_tools::add_text(&ctx, &b.label, &b.text).await {
+        Ok(v) => Ok(Json(v).into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e.to_string()}))).into_response()),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/sessions/{id}/context/{item_id}",
+    params(("id" = Uuid, Path, description = "Session id"), ("item_id" = Uuid, Path, description = "Context item id")),
+    responses((status = 204, description = "Item removed"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session or item")),
+)]
+async fn delete_session_context_item(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path((id, item_id)): axum::extract::Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/context/:item_id", "method" => "DELETE"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    let deleted = state.repo.delete_context_item(id, item_id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if deleted { Ok(StatusCode::NO_CONTENT) } else { Err(StatusCode::NOT_FOUND) }
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery { format: String }   // "openai" | "markdown"
+
+/// Role a model reply is stored under: `tool` when it asks for a tool call, else `assistant`.
+fn reply_role(content: &str) -> Role {
+    if crate::agent::engine::parse_tool_call(content).is_some() { Role::Tool } else { Role::Assistant }
+}
+
+/// Chat-completions roles; anything else is replayed as user input. A stored `tool`
+/// turn is the model's own tool request, so it goes back as `assistant`: the API's
+/// `tool` role is for results and needs a `tool_call_id` history doesn't keep.
+fn openai_role(role: &str) -> &'static str {
+    match Role::parse(role) {
+        Some(Role::System) => "system",
+        Some(Role::Assistant | Role::Tool) => "assistant",
+        _ => "user",
+    }
+}
+
+/// Conversation as OpenAI chat messages, led by the system prompt the model would see.
+fn export_openai(session: &crate::session::Session) -> serde_json::Value {
+    let mut messages = Vec::new();
+    if let Some(system) = repo_rules_system_prompt(&session.settings) {
+        messages.push(serde_json::json!({"role": "system", "content": system}));
+    }
+    for m in &session.messages {
+        // messages stored before full content was kept only have their summary
+        let content = m.content.as_deref().unwrap_or(&m.content_summary);
+        messages.push(serde_json::json!({"role": openai_role(&m.role), "content": content}));
+    }
+    serde_json::json!({"messages": messages})
+}
+
+/// Backtick fence longer than any backtick run in `text`, so the block can't be closed early.
+fn code_fence_for(text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        if c == '`' { run += 1; longest = longest.max(run); } else { run = 0; }
+    }
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// Closes a fenced block left open at the end of `text` so it doesn't swallow the following sections.
+fn close_open_fence(text: &str) -> String {
+    let mut open: Option<(char, usize)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if line.len() - trimmed.len() > 3 { continue; }
+        let Some(c) = trimmed.chars().next().filter(|c| *c == '`' || *c == '~') else { continue };
+        let n = trimmed.chars().take_while(|x| *x == c).count();
+        if n < 3 { continue; }
+        match open {
+            None => open = Some((c, n)),
+            Some((oc, on)) if oc == c && n >= on && trimmed[n..].trim().is_empty() => open = None,
+            Some(_) => {}
         }
+    }
+    match open {
+        Some((c, n)) => format!("{}\n{}", text.trim_end_matches('\n'), c.to_string().repeat(n)),
+        None => text.to_string(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Human-readable transcript: messages as `## role` sections and tool events as
+/// collapsible `<details>` blocks, interleaved in the order they happened.
+fn export_markdown(session: &crate::session::Session) -> String {
+    enum Entry<'a> { Message(&'a crate::session::Message), Tool(&'a crate::session::ToolEvent) }
+    let mut entries: Vec<(DateTime<Utc>, Entry)> = session.messages.iter().map(|m| (m.created_at, Entry::Message(m)))
+        .chain(session.tool_history.iter().map(|t| (t.created_at, Entry::Tool(t))))
+        .collect();
+    entries.sort_by_key(|(at, _)| *at);
+
+    let mut out = format!("# Session {}\n", session.id);
+    for (_, entry) in entries {
+        out.push('\n');
+        match entry {
+            Entry::Message(m) => {
+                let content = m.content.as_deref().unwrap_or(&m.content_summary);
+                out.push_str(&format!("## {}\n\n{}\n", m.role, close_open_fence(content).trim_end()));
+            }
+            Entry::Tool(t) => {
+                out.push_str(&format!("<details>\n<summary>{} ({})</summary>\n\n", escape_html(&t.tool), escape_html(&t.status)));
+                let mut body = t.summary.clone();
+                if let Some(err) = &t.error { body.push_str(&format!("\nerror: {}", err)); }
+                let fence = code_fence_for(&body);
+                out.push_str(&format!("{fence}\n{}\n{fence}\n\n</details>\n", body.trim_end()));
+            }
+        }
+    }
+    out
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/export",
+    params(("id" = Uuid, Path, description = "Session id"), ExportQuery),
+    responses((status = 200, description = "Session transcript: OpenAI chat JSON or text/markdown", body = serde_json::Value), (status = 400, description = "Unknown format"), (status = 404, description = "Unknown session")),
+)]
+async fn export_session(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<ExportQuery>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/export", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    match q.format.as_str() {
+        "openai" => Ok(Json(export_openai(&s)).into_response()),
+        "markdown" => Ok(([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], export_markdown(&s)).into_response()),
         _ => Err(StatusCode::BAD_REQUEST),
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct PostMessageBody { role: Option<String>, content: String, model: Option<String> }
+fn tail<T: Clone>(data: &[T], n: usize) -> Vec<T> {
+    data[data.len().saturating_sub(n)..].to_vec()
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/snapshot",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 200, description = "Settings, recent history, context and git state", body = serde_json::Value), (status = 404, description = "Unknown session")),
+)]
+async fn get_session_snapshot(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/snapshot", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let context_items = state.repo.list_context_items(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // git info is best-effort: the root may be unset or not a repository
+    let (head, status) = match s.settings.project_root.as_deref() {
+        Some(root) => (git_head_oid(root).ok().flatten(), git_status(root).ok()),
+        None => (None, None),
+    };
+    Ok(Json(serde_json::json!({
+        "id": s.id,
+        "client_id": s.client_id,
+        "created_at": s.created_at,
+        "settings": s.settings,
+        "git": { "head": head, "status": status },
+        "context_items": context_items,
+        "messages": tail(&s.messages, 20),
+        "tool_events": tail(&s.tool_history, 20),
+    })))
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct PostMessageBody {
+    role: Option<String>,
+    content: String,
+    model: Option<String>,
+    /// Named model-params profile from global config; the session's own params fill any gaps.
+    profile: Option<String>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+struct PostMessageResponse {
+    id: Uuid,
+    role: String,
+    content_summary: String,
+    model_used: Option<String>,
+    // full assistant reply, so clients driving the tool loop can parse tool calls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    assistant: Option<String>,
+    /// The reply came from the response cache rather than the model.
+    cached: bool,
+}
 
-#[derive(Debug, Serialize)]
-struct PostMessageResponse { id: Uuid, role: String, content_summary: String, model_used: Option<String> }
+const ELLIPSIS: char = '\u{2026}';
 
-fn summarize(content: &str, max: usize) -> String {
-    if content.len() <= max { content.to_string() } else { format!("{}…", &content[..max]) }
+fn summarize(content: &str, max_chars: usize) -> String {
+    // measured in chars, not bytes, so multibyte text is never split and CJK previews aren't cut short
+    match content.char_indices().nth(max_chars) {
+        None => content.to_string(),
+        Some((cut, _)) => format!("{}{}", &content[..cut], ELLIPSIS),
+    }
 }
 
+/// Repo rules to prepend to each turn when `auto_apply_repo_rules` is set; unreadable rules are skipped.
+fn repo_rules_system_prompt(settings: &SessionSettings) -> Option<String> {
+    use crate::agent::tools::rules::{load_repo_rules, repo_rules_prompt, DEFAULT_REPO_RULES_DIR, MAX_REPO_RULE_BYTES};
+    if !settings.auto_apply_repo_rules.unwrap_or(false) { return None; }
+    let root = settings.project_root.as_deref()?;
+    let rules = load_repo_rules(root, DEFAULT_REPO_RULES_DIR).ok()?;
+    repo_rules_prompt(&rules, MAX_REPO_RULE_BYTES)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/messages",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = PostMessageBody,
+    responses((status = 200, description = "Message recorded", body = PostMessageResponse), (status = 400, description = "Unknown model, role or profile"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 413, description = "Prompt over max_prompt_bytes or max_prompt_tokens"), (status = 499, description = "Turn cancelled")),
+)]
 async fn post_session_message(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Json(b): Json<PostMessageBody>,
-) -> Result<Json<PostMessageResponse>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/messages", "method" => "POST"); }
     // Resolve session and decide model
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let selected = ModelSelector::select(b.model.clone(), s.settings.default_model.clone(), None);
+    let summary_chars = s.settings.summary_chars.unwrap_or(DEFAULT_SUMMARY_CHARS);
+
+    // Optional pre-flight so an unsupported model fails fast instead of mid-turn
+    if s.settings.validate_model.unwrap_or(false)
+        && let (Some(model), Some(name)) = (&state.model, &selected)
+    {
+        match state.model_catalog.contains(model.as_ref(), name).await {
+            Ok(Some(false)) => return Ok((StatusCode::BAD_REQUEST, format!("unknown model: {}", name)).into_response()),
+            Ok(_) => {}
+            Err(e) => tracing::warn!(error = %e, "model list unavailable; skipping validation"),
+        }
+    }
+
+    let role = match b.role.as_deref().map(Role::parse) {
+        None => Role::User,
+        Some(Some(role)) => role,
+        Some(None) => return Ok((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": format!("unknown role: {}", b.role.as_deref().unwrap_or_default())}))).into_response()),
+    };
 
-    // Append user message summary
+    let overrides = match (RequestOverrides { profile: b.profile.clone(), ..Default::default() }).expand_profile(global_defaults()) {
+        Ok(o) => o,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e.to_string()}))).into_response()),
+    };
+    let params = resolve_effective_settings(global_defaults(), &s.settings, &overrides).model_params;
+    let req = selected.clone().filter(|_| state.model.is_some()).map(|model_name| {
+        ModelRequest { model: model_name, system: repo_rules_system_prompt(&s.settings), prompt: b.content.clone(), temperature: params.temperature, max_tokens: params.max_tokens, top_p: params.top_p }
+    });
+    // refuse oversized prompts before anything is stored or sent
+    if let Some(req) = &req {
+        let limit = resolve_max_prompt_bytes(global_defaults(), &s.settings);
+        if req.prompt_bytes() > limit {
+            let msg = format!("prompt is {} bytes, over the {}-byte max_prompt_bytes limit", req.prompt_bytes(), limit);
+            return Ok((StatusCode::PAYLOAD_TOO_LARGE, Json(serde_json::json!({"error": msg}))).into_response());
+        }
+        if let Some(limit) = resolve_max_prompt_tokens(global_defaults(), &s.settings) {
+            let tokens = req.prompt_tokens();
+            if tokens > limit {
+                let msg = format!("prompt is ~{} tokens, over the {}-token max_prompt_tokens limit", tokens, limit);
+                return Ok((StatusCode::PAYLOAD_TOO_LARGE, Json(serde_json::json!({"error": msg}))).into_response());
+            }
+        }
+    }
+
+    // Append user message summary; it stays pending until the reply (or failure) is recorded,
+    // so a crash mid-turn leaves a detectable dangling turn rather than a silent gap
+    let awaiting_reply = req.is_some();
     let user_msg = crate::session::Message {
         id: Uuid::new_v4(),
-        role: b.role.clone().unwrap_or_else(|| "user".into()),
-        content_summary: summarize(&b.content, 200),
+        role: role.as_str().into(),
+        content_summary: summarize(&b.content, summary_chars),
+        content: Some(b.content.clone()),
         model_used: selected.clone(),
         created_at: Utc::now(),
+        status: if awaiting_reply { MessageStatus::Pending } else { MessageStatus::Complete },
     };
     state.repo.append_message(id, user_msg.clone()).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Call model if configured
-    if let Some(model) = &state.model {
-        if let Some(model_name) = selected.clone() {
-            let req = ModelRequest { model: model_name.clone(), prompt: b.content.clone(), temperature: s.settings.model_params.as_ref().and_then(|p| p.temperature), max_tokens: s.settings.model_params.as_ref().and_then(|p| p.max_tokens), top_p: s.settings.model_params.as_ref().and_then(|p| p.top_p) };
-            match model.generate(req).await {
-                Ok(r) => {
-                    // store assistant message summary
-                    let as_msg = crate::session::Message { id: Uuid::new_v4(), role: "assistant".into(), content_summary: summarize(&r.content, 200), model_used: Some(r.model.clone()), created_at: Utc::now() };
-                    state.repo.append_message(id, as_msg).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                }
-                Err(e) => {
-                    state.repo.append_tool_event(id, crate::session::ToolEvent { id: Uuid::new_v4(), tool: "model".into(), summary: format!("error: {}", e), status: "error".into(), error: Some(e.to_string()), created_at: Utc::now() }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                }
+    let mut assistant = None;
+    let mut cached = false;
+    if let (Some(model), Some(req)) = (&state.model, req) {
+        let (turn_id, token) = state.inflight.begin(id);
+        let generate = async {
+            if s.settings.cache_responses.unwrap_or(false) {
+                state.response_cache.generate(model.as_ref(), state.repo.as_ref(), req).await
+            } else {
+                model.generate(req).await.map(|r| (r, false))
+            }
+        };
+        let generated = tokio::select! {
+            r = generate => Some(r),
+            _ = token.cancelled() => None,
+        };
+        state.inflight.finish(id, turn_id);
+        let Some(generated) = generated else {
+            state.repo.append_tool_event(id, crate::session::ToolEvent { id: Uuid::new_v4(), tool: "model".into(), summary: "cancelled".into(), status: ToolStatus::Cancelled.as_str().into(), error: None, args: None, created_at: Utc::now() }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            state.repo.complete_turn(id, user_msg.id, None).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            return Ok((status_cancelled(), Json(serde_json::json!({"cancelled": true, "id": user_msg.id}))).into_response());
+        };
+        match generated {
+            Ok((r, hit)) => {
+                cached = hit;
+                // store assistant message summary
+                let as_msg = crate::session::Message { id: Uuid::new_v4(), role: reply_role(&r.content).as_str().into(), content_summary: summarize(&r.content, summary_chars), content: Some(r.content.clone()), model_used: Some(r.model.clone()), created_at: Utc::now(), status: MessageStatus::Complete };
+                state.repo.complete_turn(id, user_msg.id, Some(as_msg)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                assistant = Some(r.content);
+            }
+            Err(e) => {
+                state.repo.append_tool_event(id, crate::session::ToolEvent { id: Uuid::new_v4(), tool: "model".into(), summary: format!("error: {}", e), status: ToolStatus::Error.as_str().into(), error: Some(e.to_string()), args: None, created_at: Utc::now() }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                state.repo.complete_turn(id, user_msg.id, None).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
             }
         }
     }
 
-    let resp = PostMessageResponse { id: user_msg.id, role: user_msg.role, content_summary: user_msg.content_summary, model_used: selected };
-    Ok(Json(resp))
+    let resp = PostMessageResponse { id: user_msg.id, role: user_msg.role, content_summary: user_msg.content_summary, model_used: selected, assistant, cached };
+    Ok(Json(resp).into_response())
 }
 
-#[derive(Debug, Deserialize)]
-struct ListQuery { max: Option<usize> }
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct TemplateMessageBody {
+    name: String,
+    #[serde(default)]
+    vars: std::collections::BTreeMap<String, String>,
+    role: Option<String>,
+    model: Option<String>,
+    profile: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/messages/from-template",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = TemplateMessageBody,
+    responses((status = 200, description = "Rendered message stored; includes the model reply when one is configured", body = PostMessageResponse), (status = 400, description = "Missing template variables"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session or template")),
+)]
+async fn post_session_message_from_template(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Json(b): Json<TemplateMessageBody>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/messages/from-template", "method" => "POST"); }
+    let Some(template) = state.repo.get_template(&b.name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? else {
+        return Ok((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("unknown template: {}", b.name)}))).into_response());
+    };
+    let content = match crate::templates::render(&template, &b.vars) {
+        Ok(content) => content,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e.to_string()}))).into_response()),
+    };
+    post_session_message(axum::extract::State(state), axum::extract::Path(id), Json(PostMessageBody { role: b.role, content, model: b.model, profile: b.profile })).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/messages/cancel",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 200, description = "Whether a turn was cancelled", body = serde_json::Value), (status = 404, description = "Unknown session")),
+)]
+async fn cancel_session_message(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/messages/cancel", "method" => "POST"); }
+    state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let cancelled = state.inflight.cancel(id);
+    Ok(Json(serde_json::json!({"cancelled": cancelled})))
+}
 
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListQuery { max: Option<usize>, include_vcs: Option<bool>, classify: Option<bool> }
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/discovery/list",
+    params(("id" = Uuid, Path, description = "Session id"), ListQuery),
+    responses((status = 200, description = "Files under the project root", body = serde_json::Value), (status = 400, description = "No project root"), (status = 404, description = "Unknown session")),
+)]
 async fn list_session_files(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
@@ -192,31 +982,124 @@ async fn list_session_files(
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/discovery/list", "method" => "GET"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
-    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let items = list_files(&root, q.max.unwrap_or(500));
+    let root = s.settings.read_root().ok_or(StatusCode::BAD_REQUEST)?;
+    let opts = WalkOptions { include_vcs: q.include_vcs.unwrap_or(false), classify: q.classify.unwrap_or(false), ..Default::default() };
+    let items = list_files_with(&root, q.max.unwrap_or(global_defaults().discovery().max_entries), opts);
     let v = serde_json::to_value(items).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(v))
 }
 
-#[derive(Debug, Deserialize)]
-struct SearchQuery { pattern: String, max: Option<usize> }
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TreeQuery { depth: Option<usize>, max: Option<usize>, include_vcs: Option<bool> }
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/discovery/tree",
+    params(("id" = Uuid, Path, description = "Session id"), TreeQuery),
+    responses((status = 200, description = "Indented tree text plus the entries it shows", body = serde_json::Value), (status = 400, description = "No project root"), (status = 404, description = "Unknown session")),
+)]
+async fn tree_session_files(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<TreeQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/discovery/tree", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let root = s.settings.read_root().ok_or(StatusCode::BAD_REQUEST)?;
+    let opts = WalkOptions { include_vcs: q.include_vcs.unwrap_or(false), ..Default::default() };
+    let tree = file_tree(&root, q.depth.unwrap_or(DEFAULT_TREE_DEPTH), q.max.unwrap_or(global_defaults().discovery().max_entries), opts);
+    let v = serde_json::to_value(tree).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(v))
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery { pattern: String, max: Option<usize>, include_vcs: Option<bool>, ignore_case: Option<bool>, whole_word: Option<bool> }
 
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/discovery/search",
+    params(("id" = Uuid, Path, description = "Session id"), SearchQuery),
+    responses((status = 200, description = "Paths matching the pattern", body = serde_json::Value), (status = 400, description = "No project root or invalid pattern"), (status = 404, description = "Unknown session")),
+)]
 async fn search_session_files(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Query(q): Query<SearchQuery>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/discovery/search", "method" => "GET"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
-    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let items = search_files(&root, &q.pattern, q.max.unwrap_or(500));
+    let root = s.settings.read_root().ok_or(StatusCode::BAD_REQUEST)?;
+    let opts = WalkOptions { include_vcs: q.include_vcs.unwrap_or(false), ..Default::default() };
+    let matching = PatternOptions { ignore_case: q.ignore_case.unwrap_or(false), whole_word: q.whole_word.unwrap_or(false) };
+    let items = match search_files_with_progress(&root, &q.pattern, matching, q.max.unwrap_or(global_defaults().discovery().max_entries), opts, usize::MAX, |_| {}) {
+        Ok(items) => items,
+        Err(e) => return Ok(discovery_failure(e)),
+    };
     let v = serde_json::to_value(items).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(v))
+    Ok(Json(v).into_response())
 }
 
-#[derive(Debug, Deserialize)]
-struct ReadQuery { path: String, max_bytes: Option<usize> }
+/// 400 with the reason for a bad search input or root (e.g. `invalid regex: ...`), else 500.
+fn discovery_failure(e: crate::error::Error) -> Response {
+    use crate::error::Error;
+    match e {
+        Error::InvalidInput(message) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": message}))).into_response(),
+        other => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": other.to_string()}))).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GrepQuery { pattern: String, max: Option<usize>, max_file_bytes: Option<u64>, include_vcs: Option<bool>, ignore_case: Option<bool>, whole_word: Option<bool> }
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/discovery/grep",
+    params(("id" = Uuid, Path, description = "Session id"), GrepQuery),
+    responses((status = 200, description = "Matching lines, skipped oversized files, and whether the scan was truncated", body = serde_json::Value), (status = 400, description = "No project root or invalid pattern"), (status = 404, description = "Unknown session")),
+)]
+async fn grep_session_files(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<GrepQuery>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/discovery/grep", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let root = s.settings.read_root().ok_or(StatusCode::BAD_REQUEST)?;
+    let defaults = global_defaults().discovery();
+    let limits = GrepLimits {
+        max_matches: q.max.unwrap_or(defaults.max_entries),
+        max_file_bytes: q.max_file_bytes.unwrap_or(defaults.grep_max_file_bytes),
+        max_total_bytes: defaults.grep_max_total_bytes,
+    };
+    let opts = WalkOptions { include_vcs: q.include_vcs.unwrap_or(false), ..Default::default() };
+    let matching = PatternOptions { ignore_case: q.ignore_case.unwrap_or(false), whole_word: q.whole_word.unwrap_or(false) };
+    let report = tokio::task::spawn_blocking(move || grep_files(&root, &q.pattern, matching, opts, limits))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match report {
+        Ok(report) => Ok(Json(serde_json::to_value(report).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response()),
+        Err(e) => Ok(discovery_failure(e)),
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReadQuery {
+    path: String,
+    max_bytes: Option<usize>,
+    /// Return CRLF line endings as LF; the file itself is unchanged.
+    normalize_newlines: Option<bool>,
+}
 
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/discovery/read",
+    params(("id" = Uuid, Path, description = "Session id"), ReadQuery),
+    responses((status = 200, description = "File content", body = serde_json::Value), (status = 400, description = "No project root or unreadable path"), (status = 404, description = "Unknown session")),
+)]
 async fn read_session_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
@@ -224,153 +1107,649 @@ async fn read_session_file(
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/discovery/read", "method" => "GET"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
-    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let content = read_file_under_root(&root, &q.path, q.max_bytes.unwrap_or(64 * 1024))
+    let root = s.settings.read_root().ok_or(StatusCode::BAD_REQUEST)?;
+    let content = read_file_under_root_with(&root, &q.path, q.max_bytes.unwrap_or(global_defaults().discovery().read_bytes), q.normalize_newlines.unwrap_or(false))
         .map_err(|_| StatusCode::BAD_REQUEST)?;
     Ok(Json(serde_json::json!({"path": q.path, "content": content})))
 }
 
-#[derive(Debug, Deserialize)]
-struct WriteBody { path: String, content: String, create: Option<bool>, dry_run: Option<bool>, preview_bytes: Option<usize> }
+/// 409 when the session has `require_confirm_token` and `token` is not from a current
+/// preview of `op`.
+fn confirm_conflict(settings: &SessionSettings, root: &str, dry_run: bool, op: Confirm<'_>, token: Option<&str>) -> Option<Response> {
+    if dry_run || !settings.require_confirm_token.unwrap_or(false) { return None; }
+    let e = crate::file_ops::check_confirm_token(root, op, token).err()?;
+    let status = match e {
+        crate::error::Error::ConfirmationRequired(_) => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    Some((status, Json(serde_json::json!({"error": e.to_string()}))).into_response())
+}
 
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct WriteBody { path: String, content: String, create: Option<bool>, dry_run: Option<bool>, preview_bytes: Option<usize>, atomic: Option<bool>, confirm_token: Option<String> }
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/files/write",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = WriteBody,
+    responses((status = 200, description = "Write result or dry-run preview", body = serde_json::Value), (status = 400, description = "Invalid path"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "confirm_token missing or not from a current preview of this write")),
+)]
 async fn write_session_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Json(b): Json<WriteBody>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/files/write", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let dry_run = b.dry_run.unwrap_or_else(|| s.settings.tool_policies.as_ref().and_then(|p| p.dry_run).unwrap_or(true));
-    let res = write_file_under_root(&root, &b.path, &b.content, b.create.unwrap_or(true), dry_run, b.preview_bytes.unwrap_or(1024))
+    let dry_run = resolve_dry_run(global_defaults(), &s.settings, b.dry_run);
+    if let Some(conflict) = confirm_conflict(&s.settings, &root, dry_run, Confirm::Write { rel: &b.path, content: &b.content }, b.confirm_token.as_deref()) {
+        return Ok(conflict);
+    }
+    let res = write_file_under_root_with_mode(&root, &b.path, &b.content, b.create.unwrap_or(true), dry_run, b.preview_bytes.unwrap_or(1024), b.atomic.unwrap_or(true))
         .map_err(|_| StatusCode::BAD_REQUEST)?;
-    Ok(Json(serde_json::to_value(res).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?))
+    Ok(Json(serde_json::to_value(res).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response())
 }
 
-#[derive(Debug, Deserialize)]
-struct MoveBody { from: String, to: String, dry_run: Option<bool> }
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct MoveBody { from: String, to: String, dry_run: Option<bool>, preview_bytes: Option<usize>, confirm_token: Option<String> }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/files/move",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = MoveBody,
+    responses((status = 200, description = "Move result or dry-run preview", body = serde_json::Value), (status = 400, description = "Invalid path"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "confirm_token missing or not from a current preview of this move")),
+)]
 async fn move_session_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Json(b): Json<MoveBody>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/files/move", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let dry_run = b.dry_run.unwrap_or_else(|| s.settings.tool_policies.as_ref().and_then(|p| p.dry_run).unwrap_or(true));
-    let res = move_file_under_root(&root, &b.from, &b.to, dry_run).map_err(|_| StatusCode::BAD_REQUEST)?;
-    Ok(Json(serde_json::to_value(res).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?))
+    let dry_run = resolve_dry_run(global_defaults(), &s.settings, b.dry_run);
+    if let Some(conflict) = confirm_conflict(&s.settings, &root, dry_run, Confirm::Move { from: &b.from, to: &b.to }, b.confirm_token.as_deref()) {
+        return Ok(conflict);
+    }
+    let res = move_file_under_root(&root, &b.from, &b.to, dry_run, b.preview_bytes.unwrap_or(1024)).map_err(|_| StatusCode::BAD_REQUEST)?;
+    Ok(Json(serde_json::to_value(res).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response())
 }
 
-#[derive(Debug, Deserialize)]
-struct DeleteBody { path: String, dry_run: Option<bool> }
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct DeleteBody { path: String, dry_run: Option<bool>, recursive: Option<bool>, preview_bytes: Option<usize>, confirm_token: Option<String> }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/files/delete",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = DeleteBody,
+    responses((status = 200, description = "Files removed, or that would be in a dry run", body = serde_json::Value), (status = 400, description = "Invalid path, project root, or non-empty directory without recursive"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "confirm_token missing or not from a current preview of this delete")),
+)]
 async fn delete_session_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Json(b): Json<DeleteBody>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/files/delete", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let dry_run = b.dry_run.unwrap_or_else(|| s.settings.tool_policies.as_ref().and_then(|p| p.dry_run).unwrap_or(true));
-    let res = delete_file_under_root(&root, &b.path, dry_run).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let dry_run = resolve_dry_run(global_defaults(), &s.settings, b.dry_run);
+    if let Some(conflict) = confirm_conflict(&s.settings, &root, dry_run, Confirm::Delete { rel: &b.path }, b.confirm_token.as_deref()) {
+        return Ok(conflict);
+    }
+    let res = delete_file_under_root(&root, &b.path, b.recursive.unwrap_or(false), s.settings.soft_delete(), dry_run, b.preview_bytes.unwrap_or(1024)).map_err(|_| StatusCode::BAD_REQUEST)?;
+    Ok(Json(serde_json::to_value(res).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response())
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct TouchBody { path: String, ok_if_exists: Option<bool>, dry_run: Option<bool> }
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/files/touch",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = TouchBody,
+    responses((status = 200, description = "Touch result or dry-run preview", body = serde_json::Value), (status = 400, description = "Invalid path"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "File exists and ok_if_exists is not set")),
+)]
+async fn touch_session_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Json(b): Json<TouchBody>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/files/touch", "method" => "POST"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
+    let dry_run = resolve_dry_run(global_defaults(), &s.settings, b.dry_run);
+    let res = touch_file_under_root(&root, &b.path, b.ok_if_exists.unwrap_or(false), dry_run).map_err(|e| match e {
+        crate::error::Error::AlreadyExists(_) => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    })?;
     Ok(Json(serde_json::to_value(res).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?))
 }
 
+/// 422 when the project root isn't inside a git repository, 409 for a repository
+/// state the operation can't run from, else 400; the body carries the reason.
+fn git_failure(e: crate::error::Error) -> Response {
+    use crate::error::Error;
+    let status = match e {
+        Error::NotARepository(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        Error::InvalidState(_) => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()}))).into_response()
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GitPathsQuery {
+    /// What paths are relative to; defaults to `project_root`.
+    #[param(value_type = Option<String>)]
+    paths: Option<PathBase>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/git/status",
+    params(("id" = Uuid, Path, description = "Session id"), GitPathsQuery),
+    responses((status = 200, description = "Working tree status", body = serde_json::Value), (status = 400, description = "No project root"), (status = 404, description = "Unknown session"), (status = 422, description = "Project root is not in a git repository")),
+)]
 async fn get_git_status(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Query(q): Query<GitPathsQuery>,
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/status", "method" => "GET"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let st = git_status(&root).map_err(|_| StatusCode::BAD_REQUEST)?;
-    Ok(Json(serde_json::to_value(st).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?))
+    let st = match git_status_with(&root, q.paths.unwrap_or(PathBase::ProjectRoot)) {
+        Ok(st) => st,
+        Err(e) => return Ok(git_failure(e)),
+    };
+    Ok(Json(serde_json::to_value(st).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/git/diff",
+    params(("id" = Uuid, Path, description = "Session id"), GitPathsQuery),
+    responses((status = 200, description = "Patch of uncommitted changes", body = serde_json::Value), (status = 400, description = "No project root"), (status = 404, description = "Unknown session"), (status = 422, description = "Project root is not in a git repository")),
+)]
 async fn get_git_diff(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Query(q): Query<GitPathsQuery>,
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/diff", "method" => "GET"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let d = git_diff(&root).map_err(|_| StatusCode::BAD_REQUEST)?;
-    Ok(Json(serde_json::json!({"diff": d})))
+    match git_diff_with(&root, q.paths.unwrap_or(PathBase::ProjectRoot)) {
+        Ok(d) => Ok(Json(serde_json::json!({"diff": d})).into_response()),
+        Err(e) => Ok(git_failure(e)),
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GitLogQuery {
+    /// Most commits to return; defaults to 50.
+    max: Option<usize>,
+    /// RFC3339; only commits at or after this time.
+    since: Option<DateTime<Utc>>,
+    /// RFC3339; only commits at or before this time.
+    until: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/git/log",
+    params(("id" = Uuid, Path, description = "Session id"), GitLogQuery),
+    responses((status = 200, description = "Commits reachable from HEAD, newest first", body = serde_json::Value), (status = 400, description = "No project root or bad since/until"), (status = 404, description = "Unknown session"), (status = 422, description = "Project root is not in a git repository")),
+)]
+async fn get_git_log(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<GitLogQuery>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/log", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
+    match git_log(&root, q.max.unwrap_or(DEFAULT_LOG_MAX), q.since, q.until) {
+        Ok(commits) => Ok(Json(serde_json::to_value(commits).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response()),
+        Err(e) => Ok(git_failure(e)),
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct FileHistoryQuery {
+    /// File or directory, relative to the project root.
+    path: String,
+    /// Most commits to return; defaults to 50.
+    max: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sessions/{id}/git/file-history",
+    params(("id" = Uuid, Path, description = "Session id"), FileHistoryQuery),
+    responses((status = 200, description = "Commits that changed the path, newest first", body = serde_json::Value), (status = 400, description = "No project root or path outside it"), (status = 404, description = "Unknown session"), (status = 422, description = "Project root is not in a git repository")),
+)]
+async fn get_git_file_history(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(q): Query<FileHistoryQuery>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/file-history", "method" => "GET"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
+    let max = q.max.unwrap_or(DEFAULT_LOG_MAX);
+    let history = tokio::task::spawn_blocking(move || git_file_history(&root, &q.path, max)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match history {
+        Ok(commits) => Ok(Json(serde_json::to_value(commits).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?).into_response()),
+        Err(e) => Ok(git_failure(e)),
+    }
 }
 
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DryRunQuery { dry_run: Option<bool> }
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/git/add_all",
+    params(("id" = Uuid, Path, description = "Session id"), DryRunQuery),
+    responses((status = 200, description = "Staged, or paths that would be staged", body = serde_json::Value), (status = 400, description = "No project root"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 422, description = "Project root is not in a git repository")),
+)]
 async fn post_git_add_all(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Query(q): Query<DryRunQuery>,
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/add_all", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    git_add_all(&root).map_err(|_| StatusCode::BAD_REQUEST)?;
-    Ok(Json(serde_json::json!({"ok": true})))
+    if resolve_git_dry_run(global_defaults(), &s.settings, q.dry_run) {
+        return Ok(match crate::git_ops::preview_add_all(&root) {
+            Ok(paths) => Json(serde_json::json!({"dry_run": true, "would_stage": paths})).into_response(),
+            Err(e) => git_failure(e),
+        });
+    }
+    match git_add_all(&root) {
+        Ok(()) => Ok(Json(serde_json::json!({"ok": true})).into_response()),
+        Err(e) => Ok(git_failure(e)),
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct CommitBody { message: String }
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct CommitBody { message: String, dry_run: Option<bool>, #[serde(default)] trailers: std::collections::BTreeMap<String, String> }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/git/commit",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = CommitBody,
+    responses((status = 200, description = "Commit id or dry-run preview", body = serde_json::Value), (status = 400, description = "No project root"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "Changes outside the project root are staged"), (status = 422, description = "Project root is not in a git repository")),
+)]
 async fn post_git_commit(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
     Json(b): Json<CommitBody>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/commit", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
+    let trailers = resolve_commit_trailers(&s.settings, &b.trailers);
+    let message = with_trailers(&b.message, &trailers).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if resolve_git_dry_run(global_defaults(), &s.settings, b.dry_run) {
+        return Ok(match crate::git_ops::preview_commit(&root, &message) {
+            Ok(preview) => Json(serde_json::json!({"dry_run": true, "preview": preview})).into_response(),
+            Err(e) => git_failure(e),
+        });
+    }
+    let signing = CommitSigning::from_settings(&s.settings).map_err(|_| StatusCode::BAD_REQUEST)?;
+    match git_commit(&root, &message, &[], signing.as_ref()) {
+        Ok(oid) => Ok(Json(serde_json::json!({"commit": oid})).into_response()),
+        Err(e) => Ok(git_failure(e)),
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct RemoteBody { remote: Option<String>, refspec: Option<String>, dry_run: Option<bool> }
+
+impl RemoteBody {
+    fn remote(&self) -> String { self.remote.clone().unwrap_or_else(|| "origin".into()) }
+}
+
+/// Remote failures carry their message so callers can tell a credential problem from a network one.
+fn git_remote_failure(e: crate::error::Error) -> Response {
+    use crate::error::Error;
+    let status = match e {
+        Error::RemoteAuth(_) => StatusCode::UNAUTHORIZED,
+        Error::InvalidState(_) => StatusCode::CONFLICT,
+        Error::NotARepository(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        Error::NotFound(_) | Error::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()}))).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/git/push",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = RemoteBody,
+    responses((status = 200, description = "Pushed refspec or dry-run preview", body = serde_json::Value), (status = 401, description = "Remote rejected credentials"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "Detached HEAD or no commits to push"), (status = 502, description = "Remote unreachable or push rejected")),
+)]
+async fn post_git_push(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Json(b): Json<RemoteBody>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/push", "method" => "POST"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-    let oid = git_commit(&root, &b.message).map_err(|_| StatusCode::BAD_REQUEST)?;
-    Ok(Json(serde_json::json!({"commit": oid})))
+    let remote = b.remote();
+    if resolve_git_remote_dry_run(&s.settings, b.dry_run) {
+        return Ok(match crate::git_ops::preview_push(&root, &remote, b.refspec.as_deref()) {
+            Ok(preview) => Json(serde_json::json!({"dry_run": true, "preview": preview})).into_response(),
+            Err(e) => git_remote_failure(e),
+        });
+    }
+    let r = remote.clone();
+    let res = tokio::task::spawn_blocking(move || crate::git_ops::push(&root, &r, b.refspec.as_deref())).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match res {
+        Ok(refspec) => Ok(Json(serde_json::json!({"remote": remote, "refspec": refspec})).into_response()),
+        Err(e) => Ok(git_remote_failure(e)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/git/fetch",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = RemoteBody,
+    responses((status = 200, description = "Objects received", body = serde_json::Value), (status = 401, description = "Remote rejected credentials"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 502, description = "Remote unreachable")),
+)]
+async fn post_git_fetch(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Json(b): Json<RemoteBody>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/fetch", "method" => "POST"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
+    let remote = b.remote();
+    if resolve_git_remote_dry_run(&s.settings, b.dry_run) {
+        return Ok(Json(serde_json::json!({"dry_run": true, "remote": remote})).into_response());
+    }
+    let r = remote.clone();
+    let res = tokio::task::spawn_blocking(move || crate::git_ops::fetch(&root, &r)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match res {
+        Ok(received) => Ok(Json(serde_json::json!({"remote": remote, "received_objects": received})).into_response()),
+        Err(e) => Ok(git_remote_failure(e)),
+    }
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/git/pull",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = RemoteBody,
+    responses((status = 200, description = "up_to_date, fast_forward, or diverged", body = serde_json::Value), (status = 401, description = "Remote rejected credentials"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "Detached HEAD"), (status = 502, description = "Remote unreachable")),
+)]
+async fn post_git_pull(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Json(b): Json<RemoteBody>,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/git/pull", "method" => "POST"); }
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
+    let remote = b.remote();
+    if resolve_git_remote_dry_run(&s.settings, b.dry_run) {
+        return Ok(Json(serde_json::json!({"dry_run": true, "remote": remote})).into_response());
+    }
+    let res = tokio::task::spawn_blocking(move || crate::git_ops::pull(&root, &remote)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match res {
+        Ok(outcome) => Ok(Json(outcome).into_response()),
+        Err(e) => Ok(git_remote_failure(e)),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/v1/sessions/{id}/settings",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = serde_json::Value,
+    responses((status = 200, description = "Updated settings, with warnings for an unknown default_model", body = SessionSettingsResponse), (status = 400, description = "project_root is missing or not a directory, an invalid setting such as max_tool_events 0 or a new project_root for a worktree session, or unreadable If-Match"), (status = 401, description = "Clearing read_only without the admin key"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "If-Match version is stale, or a concurrent write won")),
+)]
 async fn patch_session_settings(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
+    headers: HeaderMap,
     Json(patch): Json<SessionSettingsPatch>,
-) -> Result<Json<SessionSettingsResponse>, StatusCode> {
+) -> Result<Response, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/settings", "method" => "PATCH"); }
+    let expected = if_match_version(&headers)?;
     let mut s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(v) = expected.filter(|v| *v != s.settings_version) {
+        return Ok(settings_conflict(v, s.settings_version));
+    }
+    check_read_only_patch(&s.settings, &patch, state.admin_key.as_deref(), &headers)?;
+    if let Err(msg) = s.settings.check_patch(&patch) {
+        return Ok((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": msg}))).into_response());
+    }
+    if let Some(Some(root)) = &patch.project_root
+        && let Err(e) = crate::discovery::validate_root(root)
+    {
+        return Ok(discovery_failure(e));
+    }
+    let new_model = patch.default_model.clone().flatten();
     s.settings.apply_patch(patch);
-    state.repo.update_settings(id, s.settings.clone()).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(SessionSettingsResponse { settings: s.settings }))
+    // compare-and-set on the version read above, so a write racing this one isn't clobbered
+    let Some(version) = state.repo.update_settings_if_version(id, s.settings.clone(), s.settings_version).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? else {
+        let current = state.repo.session_summary(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?.settings_version;
+        return Ok(settings_conflict(s.settings_version, current));
+    };
+    let warnings = match (&new_model, &state.model) {
+        (Some(name), Some(model)) => unknown_model_warnings(&state.model_catalog, model.as_ref(), name).await,
+        _ => Vec::new(),
+    };
+    let etag = format!("W/\"settings-{}\"", version);
+    Ok(([(header::ETAG, etag)], Json(SessionSettingsResponse { settings: s.settings, version, warnings })).into_response())
 }
 
-async fn healthz() -> Json<serde_json::Value> {
-    { let _ = metrics::counter!("http.requests", "path" => "/v1/healthz", "method" => "GET"); }
-    Json(serde_json::json!({"ok": true}))
+/// Settings version from `If-Match`: a bare number, or the settings ETag
+/// (`W/"settings-N"`). `None` without the header or for `*`; 400 when it can't be read.
+fn if_match_version(headers: &HeaderMap) -> Result<Option<i64>, StatusCode> {
+    let Some(raw) = headers.get(header::IF_MATCH) else { return Ok(None) };
+    let tag = raw.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim().trim_start_matches("W/").trim_matches('"');
+    if tag == "*" { return Ok(None); }
+    tag.trim_start_matches("settings-").parse().map(Some).map_err(|_| StatusCode::BAD_REQUEST)
 }
 
-#[derive(Debug, Deserialize)]
-struct UrlIngestBody { url: String, max_bytes: Option<usize> }
+fn settings_conflict(expected: i64, current: i64) -> Response {
+    let msg = format!("settings changed: expected version {}, current is {}", expected, current);
+    (StatusCode::CONFLICT, Json(serde_json::json!({"error": msg, "version": current}))).into_response()
+}
 
-pub(crate) fn is_allowed_host(allowlist: &Option<Vec<String>>, host: &str) -> bool {
-    match allowlist {
-        None => false,
-        Some(list) => list.iter().any(|h| h == host),
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/settings/reset",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 200, description = "Settings reset to defaults; history is kept", body = SessionSettingsResponse), (status = 400, description = "Unreadable If-Match"), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown session"), (status = 409, description = "If-Match version is stale, or a concurrent write won")),
+)]
+async fn reset_session_settings(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/settings/reset", "method" => "POST"); }
+    let expected = if_match_version(&headers)?;
+    let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
+    if let Some(v) = expected.filter(|v| *v != s.settings_version) {
+        return Ok(settings_conflict(v, s.settings_version));
     }
+    let settings = s.settings.reset();
+    let Some(version) = state.repo.update_settings_if_version(id, settings.clone(), s.settings_version).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? else {
+        let current = state.repo.session_summary(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?.settings_version;
+        return Ok(settings_conflict(s.settings_version, current));
+    };
+    let etag = format!("W/\"settings-{}\"", version);
+    Ok(([(header::ETAG, etag)], Json(SessionSettingsResponse { settings, version, warnings: Vec::new() })).into_response())
 }
 
-pub(crate) async fn fetch_and_extract(url: &str, max_bytes: usize) -> anyhow::Result<String> {
-    let resp = reqwest::Client::new().get(url).send().await?;
-    let status = resp.status();
-    if !status.is_success() { anyhow::bail!("fetch failed: {}", status); }
-    let bytes = resp.bytes().await?;
-    let slice = if bytes.len() > max_bytes { &bytes[..max_bytes] } else { &bytes };
-    let html = String::from_utf8_lossy(slice).to_string();
-    let doc = scraper::Html::parse_document(&html);
-    let selector = scraper::Selector::parse("body").unwrap();
-    let mut text = String::new();
-    for el in doc.select(&selector) {
-        text.push_str(&el.text().collect::<Vec<_>>().join(" "));
-        text.push('\n');
+/// Best-effort check of a newly set `default_model` against the cached model list.
+/// Backends that can't list their models, or fail to, produce no warning.
+async fn unknown_model_warnings(catalog: &ModelCatalog, model: &dyn LanguageModel, name: &str) -> Vec<String> {
+    match catalog.contains(model, name).await {
+        Ok(Some(false)) => vec![format!("default_model {} is not served by the model backend", name)],
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            tracing::warn!(error = %e, "model list unavailable; skipping default_model check");
+            Vec::new()
+        }
     }
-    if text.is_empty() { Ok(html) } else { Ok(text) }
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/admin/vacuum",
+    responses((status = 200, description = "Bytes reclaimed", body = serde_json::Value), (status = 401, description = "Missing or wrong x-admin-key"), (status = 404, description = "Admin routes disabled")),
+)]
+async fn admin_vacuum(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/admin/vacuum", "method" => "POST"); }
+    require_admin(&state, &headers)?;
+    let freed = state.repo.maintenance().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(serde_json::json!({"freed_bytes": freed})))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/rules",
+    responses((status = 200, description = "Stored system rules", body = serde_json::Value)),
+)]
+async fn list_rules(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/rules", "method" => "GET"); }
+    let rules = state.repo.list_rules().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let items: Vec<serde_json::Value> = rules.into_iter().map(|(name, content)| serde_json::json!({"name": name, "content": content})).collect();
+    Ok(Json(serde_json::json!({"rules": items})))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/rules/{name}",
+    params(("name" = String, Path, description = "Rule name")),
+    responses((status = 204, description = "Rule deleted"), (status = 404, description = "Unknown rule")),
+)]
+async fn delete_rule(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/rules/:name", "method" => "DELETE"); }
+    let deleted = state.repo.delete_rule(&name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if deleted { Ok(StatusCode::NO_CONTENT) } else { Err(StatusCode::NOT_FOUND) }
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct TemplateBody { name: String, body: String }
+
+#[utoipa::path(
+    post,
+    path = "/v1/templates",
+    request_body = TemplateBody,
+    responses((status = 204, description = "Template stored, replacing any of the same name"), (status = 400, description = "Empty name")),
+)]
+async fn put_template(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(b): Json<TemplateBody>,
+) -> Result<StatusCode, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/templates", "method" => "POST"); }
+    if b.name.trim().is_empty() { return Err(StatusCode::BAD_REQUEST); }
+    state.repo.upsert_template(&b.name, &b.body).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/templates",
+    responses((status = 200, description = "Stored prompt templates and their placeholders", body = serde_json::Value)),
+)]
+async fn list_templates(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/templates", "method" => "GET"); }
+    let templates = state.repo.list_templates().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let items: Vec<serde_json::Value> = templates.into_iter()
+        .map(|(name, body)| serde_json::json!({"name": name, "placeholders": crate::templates::placeholders(&body), "body": body}))
+        .collect();
+    Ok(Json(serde_json::json!({"templates": items})))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/templates/{name}",
+    params(("name" = String, Path, description = "Template name")),
+    responses((status = 204, description = "Template deleted"), (status = 404, description = "Unknown template")),
+)]
+async fn delete_template(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/templates/:name", "method" => "DELETE"); }
+    let deleted = state.repo.delete_template(&name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if deleted { Ok(StatusCode::NO_CONTENT) } else { Err(StatusCode::NOT_FOUND) }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/settings/schema",
+    responses((status = 200, description = "JSON Schema for session settings", body = serde_json::Value)),
+)]
+async fn get_settings_schema() -> Json<serde_json::Value> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/settings/schema", "method" => "GET"); }
+    let schema = schemars::schema_for!(SessionSettings);
+    Json(serde_json::to_value(schema).unwrap_or_default())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/healthz",
+    responses((status = 200, description = "Server is up", body = serde_json::Value)),
+)]
+async fn healthz() -> Json<serde_json::Value> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/healthz", "method" => "GET"); }
+    Json(serde_json::json!({"ok": true}))
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct UrlIngestBody { url: String, max_bytes: Option<usize>, headers: Option<std::collections::BTreeMap<String, String>> }
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/context/url",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = UrlIngestBody,
+    responses((status = 200, description = "Page included as context", body = serde_json::Value), (status = 403, description = "Host not allowlisted or session read-only"), (status = 404, description = "Unknown session")),
+)]
 async fn ingest_url(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
@@ -378,25 +1757,68 @@ async fn ingest_url(
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/context/url", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let parsed = Url::parse(&b.url).map_err(|_| StatusCode::BAD_REQUEST)?;
     let host = parsed.host_str().ok_or(StatusCode::BAD_REQUEST)?;
     if !is_allowed_host(&s.settings.network_allowlist, host) {
         return Err(StatusCode::FORBIDDEN);
     }
     let max_bytes = b.max_bytes.unwrap_or(256 * 1024).min(2 * 1024 * 1024);
-    let content = fetch_and_extract(&b.url, max_bytes).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    let headers: Vec<(String, String)> = b.headers.clone().unwrap_or_default().into_iter().collect();
+    check_fetch_headers(&s.settings.network_allowlist, host, &headers).map_err(|_| StatusCode::FORBIDDEN)?;
+    let page = fetch_and_extract(&b.url, max_bytes, &headers, &s.settings.network_allowlist).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    let content = page.content;
     state.repo.append_tool_event(id, crate::session::ToolEvent {
         id: Uuid::new_v4(),
         tool: "url".into(),
         summary: format!("fetched {} ({} chars)", b.url, content.len()),
-        status: "ok".into(),
+        status: ToolStatus::Ok.as_str().into(),
         error: None,
+        args: Some(serde_json::json!({"url": b.url, "max_bytes": max_bytes})),
         created_at: Utc::now(),
     }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(serde_json::json!({"url": b.url, "content": content})))
+    Ok(Json(serde_json::json!({"url": b.url, "title": page.title, "final_url": page.final_url, "content": content})))
 }
 
-pub async fn serve(addr: SocketAddr, state: AppState) -> anyhow::Result<()> {
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    info(title = "Air Traffic Control", description = "Headless AI coding agent API"),
+    paths(create_session, get_session_resource, delete_session, delete_sessions, list_sessions, list_recent_sessions, get_session_settings, get_session_history, count_session_history, list_session_context, get_session_context_budget, add_session_context_text, delete_session_context_item, get_session_snapshot, export_session, post_session_message, post_session_message_from_template, cancel_session_message, list_session_files, tree_session_files, search_session_files, grep_session_files, read_session_file, write_session_file, move_session_file, delete_session_file, touch_session_file, get_git_status, get_git_diff, get_git_log, get_git_file_history, post_git_add_all, post_git_commit, post_git_push, post_git_fetch, post_git_pull, patch_session_settings, reset_session_settings, admin_vacuum, list_rules, delete_rule, put_template, list_templates, delete_template, get_settings_schema, healthz, ingest_url, agent_command, agent_tool, agent_tool_stream, retry_tool_event),
+    components(schemas(CreateSessionBody, CreateSessionResponse, ListSessionsResponse, RecentSessionsResponse, SessionPreview, SessionSummary, SessionSettingsResponse, HistoryResponse, HistoryCountResponse, PostMessageBody, PostMessageResponse, TemplateMessageBody, TemplateBody, WriteBody, MoveBody, DeleteBody, TouchBody, CommitBody, RemoteBody, UrlIngestBody, TextContextBody, AgentCommandResponse, ToolBody)),
+)]
+struct ApiDoc;
+
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    { let _ = metrics::counter!("http.requests", "path" => "/openapi.json", "method" => "GET"); }
+    Json(<ApiDoc as utoipa::OpenApi>::openapi())
+}
+
+/// Where `serve` listens: `host:port`, or `unix:/path/to.sock` for a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(std::path::PathBuf),
+}
+
+impl std::str::FromStr for ListenAddr {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.strip_prefix("unix:") {
+            Some("") => anyhow::bail!("empty unix socket path"),
+            Some(path) => Ok(Self::Unix(path.into())),
+            None => Ok(Self::Tcp(s.parse()?)),
+        }
+    }
+}
+
+/// PEM certificate chain and private key for HTTPS.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: std::path::PathBuf,
+    pub key: std::path::PathBuf,
+}
+
+pub async fn serve(addr: ListenAddr, state: AppState, tls: Option<TlsFiles>) -> anyhow::Result<()> {
     // Metrics setup
     metrics::describe_counter!("http.requests", Unit::Count, "HTTP requests by path and method");
     let recorder = metrics_exporter_prometheus::PrometheusBuilder::new()
@@ -405,31 +1827,124 @@ pub async fn serve(addr: SocketAddr, state: AppState) -> anyhow::Result<()> {
 
     let app = Router::new()
         .route("/v1/healthz", get(healthz))
+        .route("/openapi.json", get(openapi_json))
         .route("/metrics", get(move || async move { recorder.render() }))
-        .route("/v1/sessions", post(create_session).get(list_sessions))
+        .route("/v1/admin/vacuum", post(admin_vacuum))
+        .route("/v1/settings/schema", get(get_settings_schema))
+        .route("/v1/rules", get(list_rules))
+        .route("/v1/rules/:name", delete(delete_rule))
+        .route("/v1/templates", post(put_template).get(list_templates))
+        .route("/v1/templates/:name", delete(delete_template))
+        .route("/v1/sessions", post(create_session).get(list_sessions).delete(delete_sessions))
         .route("/v1/sessions/:id/settings", get(get_session_settings).patch(patch_session_settings))
-        .route("/v1/sessions/:id", delete(delete_session))
+        .route("/v1/sessions/:id/settings/reset", post(reset_session_settings))
+        .route("/v1/sessions/recent", get(list_recent_sessions))
+        .route("/v1/sessions/:id", get(get_session_resource).delete(delete_session))
         .route("/v1/sessions/:id/messages", post(post_session_message))
+        .route("/v1/sessions/:id/messages/cancel", post(cancel_session_message))
+        .route("/v1/sessions/:id/messages/from-template", post(post_session_message_from_template))
         .route("/v1/sessions/:id/history", get(get_session_history))
+        .route("/v1/sessions/:id/history/count", get(count_session_history))
+        .route("/v1/sessions/:id/snapshot", get(get_session_snapshot))
+        .route("/v1/sessions/:id/export", get(export_session))
         .route("/v1/sessions/:id/discovery/list", get(list_session_files))
+        .route("/v1/sessions/:id/discovery/tree", get(tree_session_files))
         .route("/v1/sessions/:id/discovery/search", get(search_session_files))
+        .route("/v1/sessions/:id/discovery/grep", get(grep_session_files))
         .route("/v1/sessions/:id/discovery/read", get(read_session_file))
         .route("/v1/sessions/:id/files/write", post(write_session_file))
         .route("/v1/sessions/:id/files/move", post(move_session_file))
         .route("/v1/sessions/:id/files/delete", post(delete_session_file))
+        .route("/v1/sessions/:id/files/touch", post(touch_session_file))
         .route("/v1/sessions/:id/git/status", get(get_git_status))
         .route("/v1/sessions/:id/git/diff", get(get_git_diff))
+        .route("/v1/sessions/:id/git/log", get(get_git_log))
+        .route("/v1/sessions/:id/git/file-history", get(get_git_file_history))
         .route("/v1/sessions/:id/git/add_all", post(post_git_add_all))
         .route("/v1/sessions/:id/git/commit", post(post_git_commit))
+        .route("/v1/sessions/:id/git/push", post(post_git_push))
+        .route("/v1/sessions/:id/git/fetch", post(post_git_fetch))
+        .route("/v1/sessions/:id/git/pull", post(post_git_pull))
+        .route("/v1/sessions/:id/context", get(list_session_context))
         .route("/v1/sessions/:id/context/url", post(ingest_url))
+        .route("/v1/sessions/:id/context/budget", get(get_session_context_budget))
+        .route("/v1/sessions/:id/context/text", post(add_session_context_text))
+        .route("/v1/sessions/:id/context/:item_id", delete(delete_session_context_item))
         .route("/v1/sessions/:id/agent/command", post(agent_command))
         .route("/v1/sessions/:id/agent/tool/:name", post(agent_tool))
+        .route("/v1/sessions/:id/agent/tool/:name/stream", post(agent_tool_stream))
+        .route("/v1/sessions/:id/tools/events/:event_id/retry", post(retry_tool_event))
+        .layer(axum::middleware::from_fn(propagate_request_id))
         .with_state(state);
+    // off by default: even redacted, request lines can be sensitive
+    let app = if access_log_enabled() { app.layer(axum::middleware::from_fn(access_log)) } else { app };
+
+    match (addr, tls) {
+        (ListenAddr::Tcp(addr), Some(tls)) => serve_tls(addr, app, tls).await?,
+        (ListenAddr::Tcp(addr), None) => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, app).await?;
+        }
+        (ListenAddr::Unix(_), Some(_)) => anyhow::bail!("TLS is only supported on TCP listeners"),
+        (ListenAddr::Unix(path), None) => serve_unix(&path, app).await?,
+    }
+    Ok(())
+}
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+/// HTTPS via rustls; on unix the certificate and key are re-read on SIGHUP so they can be rotated in place.
+async fn serve_tls(addr: SocketAddr, app: Router, tls: TlsFiles) -> anyhow::Result<()> {
+    let config = axum_server::tls_rustls::RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?;
+    #[cfg(unix)]
+    {
+        let config = config.clone();
+        let mut hup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hup.recv().await.is_some() {
+                // keep serving the old certificate if the new one is unreadable
+                match config.reload_from_pem_file(&tls.cert, &tls.key).await {
+                    Ok(()) => tracing::info!("reloaded TLS certificate"),
+                    Err(e) => tracing::error!(error = %e, "failed to reload TLS certificate"),
+                }
+            }
+        });
+    }
+    axum_server::bind_rustls(addr, config).serve(app.into_make_service()).await?;
     Ok(())
 }
+
+/// `axum::serve` only takes TCP listeners, so Unix sockets drive hyper directly.
+#[cfg(unix)]
+async fn serve_unix(path: &std::path::Path, app: Router) -> anyhow::Result<()> {
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use hyper_util::server::conn::auto::Builder;
+    use hyper_util::service::TowerToHyperService;
+    use std::os::unix::fs::FileTypeExt;
+
+    // a socket left behind by a previous run would make bind fail; anything else at
+    // the path is not ours to remove
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    tracing::info!(path = %path.display(), "listening on unix socket");
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(e) = Builder::new(TokioExecutor::new()).serve_connection_with_upgrades(TokioIo::new(stream), service).await {
+                tracing::debug!(error = %e, "unix socket connection error");
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: &std::path::Path, _app: Router) -> anyhow::Result<()> {
+    anyhow::bail!("unix sockets are not supported on this platform")
+}
 #[derive(Debug, Deserialize)]
 #[serde(tag = "kind", content = "args")]
 enum AgentCommandBody {
@@ -438,9 +1953,16 @@ enum AgentCommandBody {
     #[serde(rename = "add_rule")] AddRule { system: bool, name: String, content: String, repo_dir: Option<String> },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, utoipa::ToSchema)]
 struct AgentCommandResponse { ok: bool, summary: String }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/agent/command",
+    params(("id" = Uuid, Path, description = "Session id")),
+    request_body = serde_json::Value,
+    responses((status = 200, description = "Command result", body = AgentCommandResponse), (status = 400, description = "Command failed"), (status = 404, description = "Unknown session")),
+)]
 async fn agent_command(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<Uuid>,
@@ -448,11 +1970,12 @@ async fn agent_command(
 ) -> Result<Json<AgentCommandResponse>, StatusCode> {
     { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/agent/command", "method" => "POST"); }
     let s = state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    ensure_writable(&s.settings)?;
     let ctx = AgentContext { repo: &*state.repo };
     let res = match cmd {
         AgentCommandBody::IncludeFile { path, max_bytes } => {
-            let root = s.settings.project_root.clone().ok_or(StatusCode::BAD_REQUEST)?;
-            execute(ctx, EngineCommand::IncludeFile { session_id: id, project_root: &root, path: &path, max_bytes: max_bytes.unwrap_or(65536).min(2 * 1024 * 1024) }).await
+            let root = s.settings.read_root().ok_or(StatusCode::BAD_REQUEST)?;
+            execute(ctx, EngineCommand::IncludeFile { session_id: id, project_root: &root, path: &path, max_bytes: max_bytes.unwrap_or(global_defaults().discovery().read_bytes).min(2 * 1024 * 1024) }).await
         }
         AgentCommandBody::IncludeUrl { url, max_bytes } => {
             execute(ctx, EngineCommand::IncludeUrl { session_id: id, allowlist: s.settings.network_allowlist.as_ref(), url: &url, max_bytes: max_bytes.unwrap_or(262144).min(2 * 1024 * 1024) }).await
@@ -473,9 +1996,16 @@ async fn agent_command(
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct ToolBody { args: serde_json::Value }
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+struct ToolBody { #[schema(value_type = Object)] args: serde_json::Value }
 
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/agent/tool/{name}",
+    params(("id" = Uuid, Path, description = "Session id"), ("name" = String, Path, description = "Tool name")),
+    request_body = ToolBody,
+    responses((status = 200, description = "Tool summary and data", body = serde_json::Value), (status = 400, description = "Unknown tool or tool failed"), (status = 403, description = "Mutating tool on a read-only session")),
+)]
 async fn agent_tool(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path((id, name)): axum::extract::Path<(Uuid, String)>,
@@ -485,6 +2015,72 @@ async fn agent_tool(
     let ctx = crate::agent::engine::AgentContext { repo: &*state.repo };
     match crate::agent::engine::dispatch_tool(ctx, id, &name, b.args).await {
         Ok(v) => Ok(Json(v)),
+        Err(e) if e.downcast_ref::<crate::agent::engine::ReadOnlySession>().is_some() => Err(StatusCode::FORBIDDEN),
+        Err(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/agent/tool/{name}/stream",
+    params(("id" = Uuid, Path, description = "Session id"), ("name" = String, Path, description = "Tool name")),
+    request_body = ToolBody,
+    responses((status = 200, description = "SSE stream of progress events ending in a result or error event", content_type = "text/event-stream"), (status = 404, description = "Unknown session")),
+)]
+/// Runs a tool and streams its progress as SSE `progress` events, ending with one `result` or `error` event.
+async fn agent_tool_stream(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path((id, name)): axum::extract::Path<(Uuid, String)>,
+    Json(b): Json<ToolBody>,
+) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/agent/tool/:name/stream", "method" => "POST"); }
+    state.repo.get_session(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(64);
+    let (ptx, mut prx) = tokio::sync::mpsc::channel::<crate::agent::tools::ProgressEvent>(64);
+    let forward_tx = tx.clone();
+    let forward = tokio::spawn(async move {
+        while let Some(p) = prx.recv().await {
+            if let Ok(ev) = Event::default().event("progress").json_data(&p)
+                && forward_tx.send(ev).await.is_err()
+            {
+                break;
+            }
+        }
+    });
+    let repo = state.repo.clone();
+    tokio::spawn(async move {
+        let ctx = crate::agent::engine::AgentContext { repo: &*repo };
+        let res = crate::agent::engine::dispatch_tool_with_progress(ctx, id, &name, b.args, Some(ptx)).await;
+        // the progress sender is gone once the tool returns; drain it so the result comes last
+        let _ = forward.await;
+        let ev = match res {
+            Ok(v) => Event::default().event("result").json_data(&v),
+            Err(e) => Event::default().event("error").json_data(serde_json::json!({"error": e.to_string()})),
+        };
+        if let Ok(ev) = ev { let _ = tx.send(ev).await; }
+    });
+    let stream = tokio_stream::StreamExt::map(tokio_stream::wrappers::ReceiverStream::new(rx), Ok);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/{id}/tools/events/{event_id}/retry",
+    params(("id" = Uuid, Path, description = "Session id"), ("event_id" = Uuid, Path, description = "Tool event id")),
+    responses((status = 200, description = "Result of the replayed tool call", body = serde_json::Value), (status = 403, description = "Session is read-only"), (status = 404, description = "Unknown event"), (status = 422, description = "Event has no stored args")),
+)]
+async fn retry_tool_event(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path((id, event_id)): axum::extract::Path<(Uuid, Uuid)>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    { let _ = metrics::counter!("http.requests", "path" => "/v1/sessions/:id/tools/events/:event_id/retry", "method" => "POST"); }
+    let ev = state.repo.get_tool_event(id, event_id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    // events recorded before args were persisted cannot be replayed
+    let args = ev.args.ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+    let ctx = crate::agent::engine::AgentContext { repo: &*state.repo };
+    match crate::agent::engine::dispatch_tool(ctx, id, &ev.tool, args).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) if e.downcast_ref::<crate::agent::engine::ReadOnlySession>().is_some() => Err(StatusCode::FORBIDDEN),
         Err(_) => Err(StatusCode::BAD_REQUEST),
     }
 }
@@ -496,4 +2092,264 @@ fn slugify(name: &str) -> String { // kept for backward-compat in server if need
     s.trim_matches('-').to_string()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_truncates_on_char_boundary() {
+        // 4-byte emoji: a byte-based cut would land mid-character
+        let content = "a".repeat(199) + &"😀".repeat(10);
+        let out = summarize(&content, 201);
+        assert!(!out.contains('\u{FFFD}'));
+        assert_eq!(out.trim_end_matches('…'), "a".repeat(199) + "😀😀");
+
+        let emoji = "😀".repeat(60);
+        for max in 0..12 {
+            let out = summarize(&emoji, max);
+            assert_eq!(out.trim_end_matches('…').chars().count(), max);
+        }
+    }
+
+    #[test]
+    fn summarize_counts_chars_not_bytes() {
+        let cjk = "漢字".repeat(10);
+        assert_eq!(summarize(&cjk, 20), cjk);
+        assert_eq!(summarize(&cjk, 3), "漢字漢\u{2026}");
+    }
+
+    #[test]
+    fn summarize_appends_unicode_ellipsis() {
+        let out = summarize("hello world", 5);
+        assert_eq!(out, "hello\u{2026}");
+        assert!(out.ends_with('\u{2026}'));
+        assert!(!out.contains("â€"));
+        // short content is returned untouched
+        assert_eq!(summarize("hi", 5), "hi");
+    }
+
+    #[test]
+    fn session_created_sets_location() {
+        let id = Uuid::new_v4();
+        let resp = session_created(id);
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers()[header::LOCATION], format!("/v1/sessions/{}", id).as_str());
+    }
+
+    #[tokio::test]
+    async fn git_pull_tool_and_endpoint_agree_on_dry_run() {
+        use crate::agent::engine::{dispatch_tool, AgentContext};
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        git2::Repository::init(&project).unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let settings = SessionSettings { project_root: Some(project.to_string_lossy().to_string()), ..Default::default() };
+        let sid = repo.create_session(None, settings).await.unwrap();
+        let state = AppState {
+            repo: Arc::new(repo),
+            model: None,
+            model_catalog: Arc::new(ModelCatalog::from_env()),
+            response_cache: Arc::new(ResponseCache::from_env()),
+            inflight: Default::default(),
+            admin_key: None,
+        };
+        let pull = |dry_run: Option<bool>| {
+            let state = state.clone();
+            async move {
+                let body = RemoteBody { remote: None, refspec: None, dry_run };
+                post_git_pull(axum::extract::State(state), axum::extract::Path(sid), Json(body)).await.unwrap()
+            }
+        };
+
+        // neither pulls by default
+        let tool = dispatch_tool(AgentContext { repo: state.repo.as_ref() }, sid, "git.pull", serde_json::json!({})).await.unwrap();
+        assert_eq!(tool["data"]["dry_run"], true);
+        let resp = pull(None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["dry_run"], true);
+
+        // both try for real when the call opts in; there is no origin to pull from
+        assert!(dispatch_tool(AgentContext { repo: state.repo.as_ref() }, sid, "git.pull", serde_json::json!({"dry_run": false})).await.is_err());
+        assert_ne!(pull(Some(false)).await.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn requested_session_id_prefers_explicit_then_derives_from_client() {
+        let body = |id: Option<Uuid>, client: Option<&str>, derive: bool| CreateSessionBody { client_id: client.map(String::from), settings: None, id, id_from_client_id: derive };
+        let explicit = Uuid::new_v4();
+        assert_eq!(requested_session_id(&body(Some(explicit), Some("c"), true)), Ok(Some(explicit)));
+        assert_eq!(requested_session_id(&body(None, Some("c"), false)), Ok(None));
+        let a = requested_session_id(&body(None, Some("c"), true)).unwrap();
+        assert_eq!(a, requested_session_id(&body(None, Some("c"), true)).unwrap());
+        assert_ne!(a, requested_session_id(&body(None, Some("d"), true)).unwrap());
+        assert_eq!(requested_session_id(&body(None, None, true)), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn discovery_failure_reports_invalid_regex_as_bad_request() {
+        let err = crate::discovery::search_files(".", "(unclosed", 10).unwrap_err();
+        let resp = discovery_failure(err);
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(v["error"].as_str().unwrap().starts_with("invalid regex:"));
+    }
+
+    #[tokio::test]
+    async fn git_failure_distinguishes_missing_repository() {
+        let resp = git_failure(crate::error::Error::NotARepository("/tmp/x".into()));
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["error"], "not a git repository: /tmp/x");
+        assert_eq!(git_failure(crate::error::Error::InvalidInput("bad".into())).status(), StatusCode::BAD_REQUEST);
+    }
 
+    #[tokio::test]
+    async fn unknown_default_model_is_a_warning() {
+        struct Listing;
+        #[async_trait::async_trait]
+        impl LanguageModel for Listing {
+            async fn generate(&self, _req: ModelRequest) -> anyhow::Result<crate::models::ModelResponse> { anyhow::bail!("unused") }
+            async fn list_models(&self) -> anyhow::Result<Option<Vec<String>>> { Ok(Some(vec!["gpt-4o".into()])) }
+        }
+        let catalog = ModelCatalog::new(std::time::Duration::from_secs(60));
+        assert!(unknown_model_warnings(&catalog, &Listing, "gpt-4o").await.is_empty());
+        let warnings = unknown_model_warnings(&catalog, &Listing, "gpt-9").await;
+        assert_eq!(warnings, vec!["default_model gpt-9 is not served by the model backend".to_string()]);
+
+        let resp = SessionSettingsResponse { settings: SessionSettings::default(), version: 0, warnings: Vec::new() };
+        assert!(serde_json::to_value(resp).unwrap().get("warnings").is_none());
+    }
+
+    #[test]
+    fn read_only_can_only_be_lifted_by_an_admin() {
+        let ro = SessionSettings { read_only: Some(true), ..Default::default() };
+        let lift = SessionSettingsPatch { read_only: Some(Some(false)), ..Default::default() };
+        let other = SessionSettingsPatch { summary_chars: Some(Some(10)), ..Default::default() };
+        let mut admin = HeaderMap::new();
+        admin.insert("x-admin-key", "k".parse().unwrap());
+        assert_eq!(check_read_only_patch(&ro, &lift, Some("k"), &HeaderMap::new()), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(check_read_only_patch(&ro, &lift, None, &admin), Err(StatusCode::FORBIDDEN));
+        assert_eq!(check_read_only_patch(&ro, &lift, Some("k"), &admin), Ok(()));
+        assert_eq!(check_read_only_patch(&ro, &other, Some("k"), &admin), Err(StatusCode::FORBIDDEN));
+        assert_eq!(check_read_only_patch(&SessionSettings::default(), &other, None, &HeaderMap::new()), Ok(()));
+    }
+
+    #[test]
+    fn if_match_accepts_version_or_settings_etag() {
+        let with = |v: &str| {
+            let mut h = HeaderMap::new();
+            h.insert(header::IF_MATCH, v.parse().unwrap());
+            if_match_version(&h)
+        };
+        assert_eq!(if_match_version(&HeaderMap::new()), Ok(None));
+        assert_eq!(with("3"), Ok(Some(3)));
+        assert_eq!(with("W/\"settings-7\""), Ok(Some(7)));
+        assert_eq!(with("\"12\""), Ok(Some(12)));
+        assert_eq!(with("*"), Ok(None));
+        assert_eq!(with("latest"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(settings_conflict(1, 2).status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn inflight_turns_cancel_only_current_turn() {
+        let turns = InflightTurns::default();
+        let sid = Uuid::new_v4();
+        assert!(!turns.cancel(sid));
+
+        let (old_id, old_token) = turns.begin(sid);
+        let (_new_id, new_token) = turns.begin(sid);
+        // a stale turn finishing must not drop the newer handle
+        turns.finish(sid, old_id);
+        assert!(turns.cancel(sid));
+        assert!(new_token.is_cancelled());
+        assert!(!old_token.is_cancelled());
+        assert!(!turns.cancel(sid));
+    }
+
+    #[test]
+    fn openapi_documents_every_v1_route() {
+        let doc = <ApiDoc as utoipa::OpenApi>::openapi();
+        for path in ["/v1/sessions", "/v1/sessions/{id}/messages", "/v1/sessions/{id}/agent/tool/{name}", "/v1/rules/{name}"] {
+            assert!(doc.paths.paths.contains_key(path), "missing {}", path);
+        }
+    }
+
+    #[test]
+    fn request_id_reused_or_generated() {
+        let mut headers = HeaderMap::new();
+        assert!(Uuid::parse_str(&request_id_from(&headers)).is_ok());
+        headers.insert(REQUEST_ID_HEADER, "abc-123".parse().unwrap());
+        assert_eq!(request_id_from(&headers), "abc-123");
+        headers.insert(REQUEST_ID_HEADER, "x".repeat(500).parse().unwrap());
+        assert_ne!(request_id_from(&headers), "x".repeat(500));
+    }
+
+    #[test]
+    fn listen_addr_parses_tcp_and_unix() {
+        assert_eq!("127.0.0.1:7171".parse::<ListenAddr>().unwrap(), ListenAddr::Tcp("127.0.0.1:7171".parse().unwrap()));
+        assert_eq!("unix:/run/atc.sock".parse::<ListenAddr>().unwrap(), ListenAddr::Unix("/run/atc.sock".into()));
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("localhost".parse::<ListenAddr>().is_err());
+    }
+
+    #[test]
+    fn access_log_redacts_secrets() {
+        assert_eq!(redact_query("api_key=abc&max=5&Token=t&q="), "api_key=[redacted]&max=5&Token=[redacted]&q=");
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        headers.insert(header::USER_AGENT, "curl".parse().unwrap());
+        let line = redact_headers(&headers);
+        assert!(line.contains("authorization: [redacted]"));
+        assert!(line.contains("user-agent: curl"));
+        assert!(!line.contains("secret"));
+    }
+
+    #[test]
+    fn replies_requesting_tools_are_stored_with_tool_role() {
+        assert_eq!(reply_role(r#"{"tool": "discovery.read", "args": {"path": "a.txt"}}"#), Role::Tool);
+        assert_eq!(reply_role("Done: a.txt updated."), Role::Assistant);
+        assert_eq!(Role::parse("tool"), Some(Role::Tool));
+        assert_eq!(Role::parse("function"), None);
+    }
+
+    #[test]
+    fn export_openai_prefers_full_content() {
+        let mut session = crate::session::Session::new(None, SessionSettings::default());
+        let msg = |role: &str, summary: &str, content: Option<&str>| crate::session::Message { id: Uuid::new_v4(), role: role.into(), content_summary: summary.into(), content: content.map(String::from), model_used: None, created_at: Utc::now(), status: Default::default() };
+        session.messages.push(msg("user", "fix the…", Some("fix the bug")));
+        session.messages.push(msg("assistant", "done", None));
+        session.messages.push(msg("tool", "ran", Some("ran")));
+        let v = export_openai(&session);
+        assert_eq!(v, serde_json::json!({"messages": [
+            {"role": "user", "content": "fix the bug"},
+            {"role": "assistant", "content": "done"},
+            {"role": "assistant", "content": "ran"},
+        ]}));
+    }
+
+    #[test]
+    fn markdown_fences_outlast_backticks_in_content() {
+        assert_eq!(code_fence_for("plain"), "```");
+        assert_eq!(code_fence_for("has ``` inside"), "````");
+        assert_eq!(close_open_fence("```rust\nfn main() {}\n```"), "```rust\nfn main() {}\n```");
+        assert_eq!(close_open_fence("see:\n~~~~\nunterminated\n"), "see:\n~~~~\nunterminated\n~~~~");
+    }
+
+    #[test]
+    fn export_markdown_interleaves_messages_and_tool_events() {
+        let mut session = crate::session::Session::new(None, SessionSettings::default());
+        let t0 = Utc::now();
+        session.messages.push(crate::session::Message { id: Uuid::new_v4(), role: "user".into(), content_summary: "x".into(), content: Some("run it\n```sh\nls".into()), model_used: None, created_at: t0, status: Default::default() });
+        session.tool_history.push(crate::session::ToolEvent { id: Uuid::new_v4(), tool: "git.diff".into(), summary: "```diff\n+a\n```".into(), status: "ok".into(), error: None, args: None, created_at: t0 + chrono::Duration::seconds(1) });
+        session.messages.push(crate::session::Message { id: Uuid::new_v4(), role: "assistant".into(), content_summary: "done".into(), content: None, model_used: None, created_at: t0 + chrono::Duration::seconds(2), status: Default::default() });
+        let md = export_markdown(&session);
+        let user = md.find("## user\n\nrun it\n```sh\nls\n```\n").unwrap();
+        let tool = md.find("<details>\n<summary>git.diff (ok)</summary>\n\n````\n```diff\n+a\n```\n````\n\n</details>").unwrap();
+        let assistant = md.find("## assistant\n\ndone\n").unwrap();
+        assert!(user < tool && tool < assistant);
+    }
+}
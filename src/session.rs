@@ -8,8 +8,71 @@ pub struct Message {
     pub id: Uuid,
     pub role: String,
     pub content_summary: String,
+    /// Full text, kept out of history responses and surfaced through `/export`.
+    #[serde(default, skip_serializing)]
+    pub content: Option<String>,
     pub model_used: Option<String>,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub status: MessageStatus,
+}
+
+/// A user message is `Pending` from the moment it's stored until its reply (or the
+/// failure/cancellation event) is recorded; one left pending means the turn was cut short.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageStatus {
+    Pending,
+    #[default]
+    Complete,
+}
+
+impl MessageStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageStatus::Pending => "pending",
+            MessageStatus::Complete => "complete",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        if s == "pending" { MessageStatus::Pending } else { MessageStatus::Complete }
+    }
+}
+
+/// Message roles, following the chat-completions taxonomy. `Message::role` stores
+/// [`Role::as_str`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    System,
+    #[default]
+    User,
+    Assistant,
+    /// A model turn that requested a tool call.
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+
+    /// `None` for anything outside the known set.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "system" => Some(Role::System),
+            "user" => Some(Role::User),
+            "assistant" => Some(Role::Assistant),
+            "tool" => Some(Role::Tool),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +82,18 @@ pub struct ToolEvent {
     pub summary: String,
     pub status: String,
     pub error: Option<String>,
+    pub args: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextItem {
+    pub id: Uuid,
+    pub kind: String,
+    pub key: String,
+    pub content_excerpt: String,
+    pub byte_len: i64,
+    pub metadata: Option<serde_json::Value>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -30,6 +105,7 @@ pub struct Session {
     pub messages: Vec<Message>,
     pub tool_history: Vec<ToolEvent>,
     pub settings: SessionSettings,
+    pub settings_version: i64,
 }
 
 impl Session {
@@ -41,6 +117,7 @@ impl Session {
             messages: Vec::new(),
             tool_history: Vec::new(),
             settings,
+            settings_version: 0,
         }
     }
 }
@@ -1,6 +1,37 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+/// Default length, in characters, of stored message summaries.
+pub const DEFAULT_SUMMARY_CHARS: usize = 200;
+
+/// Default cap on context items stored per session.
+pub const DEFAULT_MAX_CONTEXT_ITEMS: usize = 200;
+pub const DEFAULT_CONTEXT_BUDGET_BYTES: u64 = 1024 * 1024;
+/// Largest assembled prompt (system + user) sent to the model when neither the
+/// session nor `ATC_MAX_PROMPT_BYTES` sets one.
+pub const DEFAULT_MAX_PROMPT_BYTES: usize = 1024 * 1024;
+
+/// What to do when adding a context item would exceed `max_context_items`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextOverflowPolicy {
+    #[default]
+    Reject,
+    EvictOldest,
+}
+
+/// External program used to sign commits.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningFormat {
+    #[default]
+    Gpg,
+    Ssh,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, JsonSchema)]
 pub struct ModelParams {
     pub temperature: Option<f32>,
     pub max_tokens: Option<u32>,
@@ -14,7 +45,7 @@ pub struct ModelParamsPatch {
     pub top_p: Option<Option<f32>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default, JsonSchema)]
 pub struct ToolPolicies {
     pub dry_run: Option<bool>,
     pub max_read_bytes: Option<u64>,
@@ -26,13 +57,46 @@ pub struct ToolPoliciesPatch {
     pub max_read_bytes: Option<Option<u64>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, JsonSchema)]
 pub struct SessionSettings {
     pub default_model: Option<String>,
     pub model_params: Option<ModelParams>,
     pub project_root: Option<String>,
     pub tool_policies: Option<ToolPolicies>,
     pub network_allowlist: Option<Vec<String>>,
+    pub summary_chars: Option<usize>,
+    pub read_only: Option<bool>,
+    pub default_to_cwd: Option<bool>,
+    pub max_context_items: Option<usize>,
+    pub context_overflow: Option<ContextOverflowPolicy>,
+    pub auto_apply_repo_rules: Option<bool>,
+    pub validate_model: Option<bool>,
+    pub context_budget_bytes: Option<u64>,
+    /// `Key: Value` trailers (e.g. `Signed-off-by`) appended to every commit.
+    pub commit_trailers: Option<BTreeMap<String, String>>,
+    /// Sign commits with `signing_key`; unsigned when unset.
+    pub sign_commits: Option<bool>,
+    pub signing_key: Option<String>,
+    pub signing_format: Option<SigningFormat>,
+    /// Move deleted files into the project's `.atc-trash/` instead of removing them.
+    pub soft_delete: Option<bool>,
+    /// Shared repository to check out a per-session git worktree from on session
+    /// create; the worktree becomes `project_root` and is pruned when the session is deleted.
+    pub worktree_base: Option<String>,
+    /// Reuse stored replies to identical temperature-0 requests instead of calling the model.
+    pub cache_responses: Option<bool>,
+    /// Non-dry-run writes, moves and deletes must pass the `confirm_token` from a
+    /// dry-run preview of the same change, so nothing is applied without being
+    /// previewed first. A token stops confirming once the files involved change.
+    pub require_confirm_token: Option<bool>,
+    /// Messages whose assembled prompt is larger are refused before the model is called.
+    pub max_prompt_bytes: Option<usize>,
+    /// Token caps, counted with [`crate::tokens::estimate_tokens`]; unset means only
+    /// the byte limits apply.
+    pub context_budget_tokens: Option<u64>,
+    pub max_prompt_tokens: Option<usize>,
+    /// Oldest tool events beyond this many are dropped as new ones are recorded.
+    pub max_tool_events: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -42,6 +106,50 @@ pub struct SessionSettingsPatch {
     pub project_root: Option<Option<String>>,
     pub tool_policies: Option<ToolPoliciesPatch>,
     pub network_allowlist: Option<Option<Vec<String>>>,
+    pub summary_chars: Option<Option<usize>>,
+    pub read_only: Option<Option<bool>>,
+    pub default_to_cwd: Option<Option<bool>>,
+    pub max_context_items: Option<Option<usize>>,
+    pub context_overflow: Option<Option<ContextOverflowPolicy>>,
+    pub auto_apply_repo_rules: Option<Option<bool>>,
+    pub validate_model: Option<Option<bool>>,
+    pub context_budget_bytes: Option<Option<u64>>,
+    pub commit_trailers: Option<Option<BTreeMap<String, String>>>,
+    pub sign_commits: Option<Option<bool>>,
+    pub signing_key: Option<Option<String>>,
+    pub signing_format: Option<Option<SigningFormat>>,
+    pub soft_delete: Option<Option<bool>>,
+    pub worktree_base: Option<Option<String>>,
+    pub cache_responses: Option<Option<bool>>,
+    pub require_confirm_token: Option<Option<bool>>,
+    pub max_prompt_bytes: Option<Option<usize>>,
+    pub context_budget_tokens: Option<Option<u64>>,
+    pub max_prompt_tokens: Option<Option<usize>>,
+    pub max_tool_events: Option<Option<usize>>,
+}
+
+/// Caps for discovery list/search/read/grep calls that don't pass their own.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiscoveryDefaults {
+    pub max_entries: usize,
+    pub read_bytes: usize,
+    /// Grep skips files larger than this.
+    pub grep_max_file_bytes: u64,
+    /// Grep stops, reporting `truncated`, once this many bytes have been read.
+    pub grep_max_total_bytes: u64,
+}
+
+impl DiscoveryDefaults {
+    pub const BUILTIN: Self = Self {
+        max_entries: 500,
+        read_bytes: 64 * 1024,
+        grep_max_file_bytes: 1024 * 1024,
+        grep_max_total_bytes: 64 * 1024 * 1024,
+    };
+}
+
+impl Default for DiscoveryDefaults {
+    fn default() -> Self { Self::BUILTIN }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -49,6 +157,56 @@ pub struct GlobalConfigDefaults {
     pub default_model: Option<String>,
     pub model_params: Option<ModelParams>,
     pub tool_policies: Option<ToolPolicies>,
+    pub discovery_max_entries: Option<usize>,
+    pub discovery_read_bytes: Option<usize>,
+    pub grep_max_file_bytes: Option<u64>,
+    pub grep_max_total_bytes: Option<u64>,
+    pub max_prompt_bytes: Option<usize>,
+    pub max_prompt_tokens: Option<usize>,
+    /// Named parameter sets (e.g. `"precise"` = temperature 0) a request can pick by `profile`.
+    pub model_profiles: BTreeMap<String, ModelParams>,
+}
+
+impl GlobalConfigDefaults {
+    /// Server-wide defaults from the environment. `ATC_DEFAULT_DRY_RUN=false`
+    /// makes file tools apply changes unless a session or request says otherwise;
+    /// `ATC_DISCOVERY_MAX_ENTRIES` / `ATC_DISCOVERY_READ_BYTES` raise discovery caps,
+    /// `ATC_GREP_MAX_FILE_BYTES` / `ATC_GREP_MAX_TOTAL_BYTES` bound content grep, and
+    /// `ATC_MAX_PROMPT_BYTES` / `ATC_MAX_PROMPT_TOKENS` cap prompts sent to the model.
+    /// `ATC_MODEL_PROFILES` is a JSON object of profile name to `ModelParams`, e.g.
+    /// `{"precise": {"temperature": 0}, "creative": {"temperature": 0.9}}`.
+    pub fn from_env() -> Self {
+        let dry_run = std::env::var("ATC_DEFAULT_DRY_RUN").ok().and_then(|v| v.parse::<bool>().ok());
+        let env_usize = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<usize>().ok());
+        let env_u64 = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            tool_policies: dry_run.map(|d| ToolPolicies { dry_run: Some(d), max_read_bytes: None }),
+            discovery_max_entries: env_usize("ATC_DISCOVERY_MAX_ENTRIES"),
+            discovery_read_bytes: env_usize("ATC_DISCOVERY_READ_BYTES"),
+            grep_max_file_bytes: env_u64("ATC_GREP_MAX_FILE_BYTES"),
+            grep_max_total_bytes: env_u64("ATC_GREP_MAX_TOTAL_BYTES"),
+            max_prompt_bytes: env_usize("ATC_MAX_PROMPT_BYTES"),
+            max_prompt_tokens: env_usize("ATC_MAX_PROMPT_TOKENS"),
+            model_profiles: std::env::var("ATC_MODEL_PROFILES").ok().and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Built-in discovery caps with any configured overrides applied.
+    pub fn discovery(&self) -> DiscoveryDefaults {
+        DiscoveryDefaults {
+            max_entries: self.discovery_max_entries.unwrap_or(DiscoveryDefaults::BUILTIN.max_entries),
+            read_bytes: self.discovery_read_bytes.unwrap_or(DiscoveryDefaults::BUILTIN.read_bytes),
+            grep_max_file_bytes: self.grep_max_file_bytes.unwrap_or(DiscoveryDefaults::BUILTIN.grep_max_file_bytes),
+            grep_max_total_bytes: self.grep_max_total_bytes.unwrap_or(DiscoveryDefaults::BUILTIN.grep_max_total_bytes),
+        }
+    }
+}
+
+/// Process-wide global defaults, loaded once from the environment.
+pub fn global_defaults() -> &'static GlobalConfigDefaults {
+    static GLOBAL: OnceLock<GlobalConfigDefaults> = OnceLock::new();
+    GLOBAL.get_or_init(GlobalConfigDefaults::from_env)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -56,6 +214,26 @@ pub struct RequestOverrides {
     pub model: Option<String>,
     pub model_params: Option<ModelParams>,
     pub tool_policies: Option<ToolPolicies>,
+    /// Name of a [`GlobalConfigDefaults::model_profiles`] entry; see [`Self::expand_profile`].
+    pub profile: Option<String>,
+}
+
+impl RequestOverrides {
+    /// Folds the named profile into `model_params` so [`resolve_effective_settings`]
+    /// sees it as request-level params. Params set explicitly on the request win
+    /// over the profile's.
+    pub fn expand_profile(&self, global: &GlobalConfigDefaults) -> crate::error::Result<Self> {
+        let mut out = self.clone();
+        let Some(name) = &self.profile else { return Ok(out) };
+        let profile = global.model_profiles.get(name).ok_or_else(|| crate::error::Error::InvalidInput(format!("unknown model profile: {}", name)))?;
+        let explicit = self.model_params.clone().unwrap_or_default();
+        out.model_params = Some(ModelParams {
+            temperature: explicit.temperature.or(profile.temperature),
+            max_tokens: explicit.max_tokens.or(profile.max_tokens),
+            top_p: explicit.top_p.or(profile.top_p),
+        });
+        Ok(out)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -127,6 +305,46 @@ pub fn resolve_effective_settings(
     }
 }
 
+/// Resolves dry_run through request > session > global, falling back to `true`.
+pub fn resolve_dry_run(global: &GlobalConfigDefaults, session: &SessionSettings, request: Option<bool>) -> bool {
+    let request = RequestOverrides {
+        tool_policies: Some(ToolPolicies { dry_run: request, max_read_bytes: None }),
+        ..Default::default()
+    };
+    resolve_effective_settings(global, session, &request).tool_policies.dry_run.unwrap_or(true)
+}
+
+/// Prompt size cap: session > global > [`DEFAULT_MAX_PROMPT_BYTES`].
+pub fn resolve_max_prompt_bytes(global: &GlobalConfigDefaults, session: &SessionSettings) -> usize {
+    session.max_prompt_bytes.or(global.max_prompt_bytes).unwrap_or(DEFAULT_MAX_PROMPT_BYTES)
+}
+
+/// Prompt token cap: session > global; `None` when neither sets one.
+pub fn resolve_max_prompt_tokens(global: &GlobalConfigDefaults, session: &SessionSettings) -> Option<usize> {
+    session.max_prompt_tokens.or(global.max_prompt_tokens)
+}
+
+/// Dry-run for git mutations: same precedence and `true` fallback as [`resolve_dry_run`],
+/// so commits, merges and the like preview unless a policy or the request opts out.
+pub fn resolve_git_dry_run(global: &GlobalConfigDefaults, session: &SessionSettings, request: Option<bool>) -> bool {
+    resolve_dry_run(global, session, request)
+}
+
+/// Dry-run for push, fetch and pull: request > session only. A global `dry_run: false`
+/// does not reach the network; the session or the call has to opt in.
+pub fn resolve_git_remote_dry_run(session: &SessionSettings, request: Option<bool>) -> bool {
+    request
+        .or_else(|| session.tool_policies.as_ref().and_then(|p| p.dry_run))
+        .unwrap_or(true)
+}
+
+/// Session trailers followed by ad-hoc ones; an ad-hoc key replaces the session value.
+pub fn resolve_commit_trailers(session: &SessionSettings, extra: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    let mut merged = session.commit_trailers.clone().unwrap_or_default();
+    merged.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+    merged.into_iter().collect()
+}
+
 impl SessionSettings {
     pub fn apply_patch(&mut self, patch: SessionSettingsPatch) {
         if let Some(dm) = patch.default_model {
@@ -151,6 +369,107 @@ impl SessionSettings {
         if let Some(na) = patch.network_allowlist {
             self.network_allowlist = na;
         }
+        if let Some(sc) = patch.summary_chars {
+            self.summary_chars = sc;
+        }
+        if let Some(ro) = patch.read_only {
+            self.read_only = ro;
+        }
+        if let Some(dc) = patch.default_to_cwd {
+            self.default_to_cwd = dc;
+        }
+        if let Some(mc) = patch.max_context_items {
+            self.max_context_items = mc;
+        }
+        if let Some(co) = patch.context_overflow {
+            self.context_overflow = co;
+        }
+        if let Some(ar) = patch.auto_apply_repo_rules {
+            self.auto_apply_repo_rules = ar;
+        }
+        if let Some(vm) = patch.validate_model {
+            self.validate_model = vm;
+        }
+        if let Some(cb) = patch.context_budget_bytes {
+            self.context_budget_bytes = cb;
+        }
+        if let Some(ct) = patch.commit_trailers {
+            self.commit_trailers = ct;
+        }
+        if let Some(sc) = patch.sign_commits {
+            self.sign_commits = sc;
+        }
+        if let Some(sk) = patch.signing_key {
+            self.signing_key = sk;
+        }
+        if let Some(sf) = patch.signing_format {
+            self.signing_format = sf;
+        }
+        if let Some(sd) = patch.soft_delete {
+            self.soft_delete = sd;
+        }
+        if let Some(wb) = patch.worktree_base {
+            self.worktree_base = wb;
+        }
+        if let Some(cr) = patch.cache_responses {
+            self.cache_responses = cr;
+        }
+        if let Some(rc) = patch.require_confirm_token {
+            self.require_confirm_token = rc;
+        }
+        if let Some(mp) = patch.max_prompt_bytes {
+            self.max_prompt_bytes = mp;
+        }
+        if let Some(ct) = patch.context_budget_tokens {
+            self.context_budget_tokens = ct;
+        }
+        if let Some(mt) = patch.max_prompt_tokens {
+            self.max_prompt_tokens = mt;
+        }
+        if let Some(me) = patch.max_tool_events {
+            self.max_tool_events = me;
+        }
+    }
+
+    /// Why `patch` can't be applied to these settings, if it can't.
+    pub fn check_patch(&self, patch: &SessionSettingsPatch) -> Result<(), String> {
+        if patch.max_tool_events == Some(Some(0)) {
+            return Err("max_tool_events must be at least 1; send null to keep every event".into());
+        }
+        // the session's worktree is found through both when it is pruned on delete
+        if self.worktree_base.is_some() {
+            if patch.worktree_base.as_ref().is_some_and(|b| *b != self.worktree_base) {
+                return Err("worktree_base can't change on a session with its own worktree".into());
+            }
+            if patch.project_root.as_ref().is_some_and(|r| *r != self.project_root) {
+                return Err("project_root can't change on a session with its own worktree".into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool { self.read_only.unwrap_or(false) }
+
+    pub fn soft_delete(&self) -> bool { self.soft_delete.unwrap_or(false) }
+
+    /// Root for discovery and reads: `project_root`, or the server's CWD when
+    /// `default_to_cwd` is enabled. Writes and git must use `project_root` directly.
+    pub fn read_root(&self) -> Option<String> {
+        if let Some(root) = &self.project_root { return Some(root.clone()); }
+        if self.default_to_cwd.unwrap_or(false) {
+            return std::env::current_dir().ok().map(|p| p.to_string_lossy().to_string());
+        }
+        None
+    }
+
+    /// Defaults for everything, so global defaults apply again. A session provisioned
+    /// from `worktree_base` keeps it and its worktree `project_root`, since the
+    /// worktree belongs to the session and is pruned through them on delete.
+    pub fn reset(&self) -> Self {
+        match &self.worktree_base {
+            Some(base) => Self { worktree_base: Some(base.clone()), project_root: self.project_root.clone(), ..Default::default() },
+            None => Self::default(),
+        }
     }
 }
 
@@ -171,6 +490,7 @@ mod tests {
                 dry_run: Some(true),
                 max_read_bytes: Some(1024),
             }),
+            ..Default::default()
         };
 
         let session = SessionSettings {
@@ -186,6 +506,7 @@ mod tests {
                 max_read_bytes: None,
             }),
             network_allowlist: None,
+            ..Default::default()
         };
 
         let request = RequestOverrides {
@@ -199,6 +520,7 @@ mod tests {
                 dry_run: None,
                 max_read_bytes: Some(2048),
             }),
+            profile: None,
         };
 
         let eff = resolve_effective_settings(&global, &session, &request);
@@ -220,6 +542,7 @@ mod tests {
             project_root: Some("/repo".into()),
             tool_policies: Some(ToolPolicies { dry_run: Some(true), max_read_bytes: Some(1024) }),
             network_allowlist: Some(vec!["example.com".into()]),
+            ..Default::default()
         };
 
         let patch = SessionSettingsPatch {
@@ -228,6 +551,7 @@ mod tests {
             project_root: Some(None),
             tool_policies: Some(ToolPoliciesPatch { dry_run: Some(Some(false)), max_read_bytes: Some(Some(2048)) }),
             network_allowlist: Some(Some(vec!["docs.rs".into()])),
+            ..Default::default()
         };
 
         session.apply_patch(patch);
@@ -243,6 +567,155 @@ mod tests {
         assert_eq!(tp.max_read_bytes, Some(2048));
         assert_eq!(session.network_allowlist, Some(vec!["docs.rs".into()]));
     }
-}
 
+    #[test]
+    fn patch_sets_and_clears_summary_chars() {
+        let mut session = SessionSettings::default();
+        session.apply_patch(SessionSettingsPatch { summary_chars: Some(Some(80)), ..Default::default() });
+        assert_eq!(session.summary_chars, Some(80));
+        session.apply_patch(SessionSettingsPatch { summary_chars: Some(None), ..Default::default() });
+        assert_eq!(session.summary_chars, None);
+    }
+
+    #[test]
+    fn reset_clears_everything_but_the_session_worktree() {
+        let plain = SessionSettings { project_root: Some("/repo".into()), read_only: Some(true), max_prompt_bytes: Some(10), ..Default::default() };
+        assert_eq!(plain.reset(), SessionSettings::default());
+        let worktree = SessionSettings { worktree_base: Some("/base".into()), project_root: Some("/base/.atc/x".into()), summary_chars: Some(5), ..Default::default() };
+        assert_eq!(worktree.reset(), SessionSettings { worktree_base: Some("/base".into()), project_root: Some("/base/.atc/x".into()), ..Default::default() });
+    }
+
+    #[test]
+    fn read_root_falls_back_to_cwd_only_when_opted_in() {
+        let mut session = SessionSettings::default();
+        assert_eq!(session.read_root(), None);
+
+        session.default_to_cwd = Some(true);
+        let cwd = std::env::current_dir().unwrap().to_string_lossy().to_string();
+        assert_eq!(session.read_root(), Some(cwd));
+
+        session.project_root = Some("/repo".into());
+        assert_eq!(session.read_root().as_deref(), Some("/repo"));
+    }
+
+    #[test]
+    fn dry_run_resolves_through_global_with_true_fallback() {
+        let session = SessionSettings::default();
+        assert!(resolve_dry_run(&GlobalConfigDefaults::default(), &session, None));
+        assert!(resolve_git_dry_run(&GlobalConfigDefaults::default(), &session, None));
+        assert!(!resolve_git_dry_run(&GlobalConfigDefaults::default(), &session, Some(false)));
+
+        let permissive = GlobalConfigDefaults {
+            tool_policies: Some(ToolPolicies { dry_run: Some(false), max_read_bytes: None }),
+            ..Default::default()
+        };
+        assert!(!resolve_git_dry_run(&permissive, &session, None));
+        assert!(resolve_git_remote_dry_run(&session, None));
+        assert!(!resolve_git_remote_dry_run(&session, Some(false)));
+        let allowed = SessionSettings { tool_policies: Some(ToolPolicies { dry_run: Some(false), max_read_bytes: None }), ..Default::default() };
+        assert!(!resolve_git_remote_dry_run(&allowed, None));
+
+        let global = GlobalConfigDefaults {
+            tool_policies: Some(ToolPolicies { dry_run: Some(false), max_read_bytes: None }),
+            ..Default::default()
+        };
+        assert!(!resolve_dry_run(&global, &session, None));
+
+        let strict = SessionSettings { tool_policies: Some(ToolPolicies { dry_run: Some(true), max_read_bytes: None }), ..Default::default() };
+        assert!(resolve_dry_run(&global, &strict, None));
+        assert!(!resolve_dry_run(&global, &strict, Some(false)));
+    }
+
+    #[test]
+    fn patch_rejects_a_zero_tool_event_cap() {
+        let s = SessionSettings::default();
+        assert!(s.check_patch(&SessionSettingsPatch { max_tool_events: Some(Some(0)), ..Default::default() }).is_err());
+        assert!(s.check_patch(&SessionSettingsPatch { max_tool_events: Some(Some(1)), ..Default::default() }).is_ok());
+        assert!(s.check_patch(&SessionSettingsPatch { max_tool_events: Some(None), ..Default::default() }).is_ok());
+    }
+
+    #[test]
+    fn patch_keeps_a_worktree_session_pointed_at_its_worktree() {
+        let wt = SessionSettings { worktree_base: Some("/repo".into()), project_root: Some("/repo-wt/s1".into()), ..Default::default() };
+        let root = |r: Option<&str>| SessionSettingsPatch { project_root: Some(r.map(String::from)), ..Default::default() };
+        let base = |b: Option<&str>| SessionSettingsPatch { worktree_base: Some(b.map(String::from)), ..Default::default() };
+        assert!(wt.check_patch(&root(Some("/elsewhere"))).is_err());
+        assert!(wt.check_patch(&root(None)).is_err());
+        assert!(wt.check_patch(&base(Some("/other"))).is_err());
+        assert!(wt.check_patch(&base(None)).is_err());
+        assert!(wt.check_patch(&root(Some("/repo-wt/s1"))).is_ok());
+        assert!(wt.check_patch(&SessionSettingsPatch { summary_chars: Some(Some(10)), ..Default::default() }).is_ok());
+        assert!(SessionSettings::default().check_patch(&root(Some("/elsewhere"))).is_ok());
+    }
+
+    #[test]
+    fn commit_trailers_merge_with_adhoc_overrides() {
+        let session = SessionSettings {
+            commit_trailers: Some(BTreeMap::from([("Signed-off-by".to_string(), "Bot <bot@example.com>".to_string())])),
+            ..Default::default()
+        };
+        let extra = BTreeMap::from([("Co-authored-by".to_string(), "Ann <ann@example.com>".to_string())]);
+        assert_eq!(resolve_commit_trailers(&session, &extra), vec![
+            ("Co-authored-by".to_string(), "Ann <ann@example.com>".to_string()),
+            ("Signed-off-by".to_string(), "Bot <bot@example.com>".to_string()),
+        ]);
+        let replaced = BTreeMap::from([("Signed-off-by".to_string(), "Ann <ann@example.com>".to_string())]);
+        assert_eq!(resolve_commit_trailers(&session, &replaced), vec![("Signed-off-by".to_string(), "Ann <ann@example.com>".to_string())]);
+    }
+
+    #[test]
+    fn discovery_defaults_fall_back_to_builtin() {
+        assert_eq!(GlobalConfigDefaults::default().discovery(), DiscoveryDefaults::BUILTIN);
+        let global = GlobalConfigDefaults { discovery_max_entries: Some(5000), ..Default::default() };
+        assert_eq!(global.discovery(), DiscoveryDefaults { max_entries: 5000, ..DiscoveryDefaults::BUILTIN });
+    }
 
+    #[test]
+    fn max_prompt_bytes_prefers_session_then_global() {
+        let session = SessionSettings::default();
+        assert_eq!(resolve_max_prompt_bytes(&GlobalConfigDefaults::default(), &session), DEFAULT_MAX_PROMPT_BYTES);
+        let global = GlobalConfigDefaults { max_prompt_bytes: Some(4096), ..Default::default() };
+        assert_eq!(resolve_max_prompt_bytes(&global, &session), 4096);
+        let tight = SessionSettings { max_prompt_bytes: Some(64), ..Default::default() };
+        assert_eq!(resolve_max_prompt_bytes(&global, &tight), 64);
+    }
+
+    #[test]
+    fn profile_expands_under_explicit_params() {
+        let global = GlobalConfigDefaults {
+            model_profiles: BTreeMap::from([("precise".to_string(), ModelParams { temperature: Some(0.0), max_tokens: Some(512), top_p: None })]),
+            ..Default::default()
+        };
+        let session = SessionSettings { model_params: Some(ModelParams { temperature: Some(0.7), max_tokens: None, top_p: Some(0.5) }), ..Default::default() };
+        let request = RequestOverrides {
+            profile: Some("precise".into()),
+            model_params: Some(ModelParams { max_tokens: Some(64), ..Default::default() }),
+            ..Default::default()
+        };
+        let eff = resolve_effective_settings(&global, &session, &request.expand_profile(&global).unwrap());
+        assert_eq!(eff.model_params, ModelParams { temperature: Some(0.0), max_tokens: Some(64), top_p: Some(0.5) });
+
+        let unknown = RequestOverrides { profile: Some("wild".into()), ..Default::default() };
+        assert!(matches!(unknown.expand_profile(&global), Err(crate::error::Error::InvalidInput(_))));
+        assert_eq!(RequestOverrides::default().expand_profile(&global).unwrap(), RequestOverrides::default());
+    }
+
+    #[test]
+    fn max_prompt_tokens_is_unset_by_default() {
+        let session = SessionSettings::default();
+        assert_eq!(resolve_max_prompt_tokens(&GlobalConfigDefaults::default(), &session), None);
+        let global = GlobalConfigDefaults { max_prompt_tokens: Some(8000), ..Default::default() };
+        assert_eq!(resolve_max_prompt_tokens(&global, &session), Some(8000));
+        let tight = SessionSettings { max_prompt_tokens: Some(100), ..Default::default() };
+        assert_eq!(resolve_max_prompt_tokens(&global, &tight), Some(100));
+    }
+
+    #[test]
+    fn settings_schema_describes_fields() {
+        let schema = serde_json::to_value(schemars::schema_for!(SessionSettings)).unwrap();
+        let props = &schema["properties"];
+        assert!(props.get("project_root").is_some());
+        assert!(props.get("tool_policies").is_some());
+        assert!(schema["definitions"].get("ModelParams").is_some());
+    }
+}
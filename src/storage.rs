@@ -1,48 +1,218 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use sqlx::{Pool, Sqlite, sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous}, Row};
+use sqlx::{Pool, Sqlite, sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous, SqlitePoolOptions}, Row};
 use uuid::Uuid;
 
-use crate::session::{Session, Message, ToolEvent};
-use crate::settings::SessionSettings;
+use crate::error::{Error, Result};
+use crate::session::{Session, Message, MessageStatus, ToolEvent, ContextItem};
+use crate::settings::{SessionSettings, ContextOverflowPolicy, DEFAULT_MAX_CONTEXT_ITEMS};
 
 #[derive(Clone)]
 pub struct SqliteSessionRepository {
     pool: Pool<Sqlite>,
 }
 
+/// Returned when a session is at `max_context_items` under the `reject` policy.
+#[derive(Debug, thiserror::Error)]
+#[error("context item limit of {limit} reached")]
+pub struct ContextLimitExceeded {
+    pub limit: usize,
+}
+
+/// Connection pool settings for the SQLite repository.
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub acquire_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self { max_connections: 8, acquire_timeout: Duration::from_secs(30) }
+    }
+}
+
+impl PoolConfig {
+    /// Reads `DATABASE_MAX_CONNECTIONS` and `DATABASE_ACQUIRE_TIMEOUT_SECS`, falling back to defaults.
+    pub fn from_env() -> Self {
+        let mut cfg = Self::default();
+        if let Some(n) = std::env::var("DATABASE_MAX_CONNECTIONS").ok().and_then(|v| v.parse().ok()) {
+            cfg.max_connections = n;
+        }
+        if let Some(secs) = std::env::var("DATABASE_ACQUIRE_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()) {
+            cfg.acquire_timeout = Duration::from_secs(secs);
+        }
+        cfg
+    }
+}
+
+/// Aggregate size of a session's context items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+pub struct ContextUsage {
+    pub used_bytes: u64,
+    /// Estimated via [`crate::tokens`]; items stored before token counting use bytes/4.
+    pub used_tokens: u64,
+    pub item_count: u64,
+}
+
+/// One row of [`SessionRepository::recent_sessions`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, utoipa::ToSchema)]
+pub struct SessionPreview {
+    pub id: Uuid,
+    pub client_id: Option<String>,
+    /// Summary of the session's first user message.
+    pub title: Option<String>,
+    /// Summary of the most recent message.
+    pub last_message: Option<String>,
+    pub message_count: u64,
+    /// Latest of the session's creation, messages, and tool events.
+    pub last_active_at: DateTime<Utc>,
+}
+
+/// A session's metadata and settings with history sizes in place of the history itself.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, utoipa::ToSchema)]
+pub struct SessionSummary {
+    pub id: Uuid,
+    pub client_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Object)]
+    pub settings: SessionSettings,
+    pub settings_version: i64,
+    pub message_count: u64,
+    pub tool_event_count: u64,
+    pub context_item_count: u64,
+    /// Tool events dropped so far by the session's `max_tool_events` cap.
+    pub tool_events_trimmed: u64,
+}
+
+/// Row counts for a session's history, without loading it.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+pub struct HistoryCounts {
+    pub messages: u64,
+    pub tool_events: u64,
+    /// Tool events per tool name.
+    pub by_tool: std::collections::BTreeMap<String, u64>,
+}
+
+/// Optional filters for listing a session's tool events.
+#[derive(Debug, Clone, Default)]
+pub struct ToolEventFilter {
+    pub status: Option<String>,
+    pub tool: Option<String>,
+}
+
+/// Filter for bulk session deletion. At least one field must be set.
+#[derive(Debug, Clone, Default)]
+pub struct SessionDeleteFilter {
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl SessionDeleteFilter {
+    pub fn is_empty(&self) -> bool { self.before.is_none() }
+}
+
 #[async_trait]
 pub trait SessionRepository: Send + Sync {
-    async fn create_session(&self, client_id: Option<String>, settings: SessionSettings) -> anyhow::Result<Uuid>;
-    async fn delete_session(&self, id: Uuid) -> anyhow::Result<bool>;
-    async fn list_sessions(&self) -> anyhow::Result<Vec<Uuid>>;
-    async fn get_session(&self, id: Uuid) -> anyhow::Result<Option<Session>>;
-    async fn update_settings(&self, id: Uuid, settings: SessionSettings) -> anyhow::Result<()>;
-    async fn append_message(&self, id: Uuid, msg: Message) -> anyhow::Result<()>;
-    async fn append_tool_event(&self, id: Uuid, ev: ToolEvent) -> anyhow::Result<()>;
-    // System rules CRUD
-    async fn upsert_rule(&self, name: &str, content: &str) -> anyhow::Result<()>;
-    async fn get_rule(&self, name: &str) -> anyhow::Result<Option<(String, String)>>; // (name, content)
-    async fn list_rules(&self) -> anyhow::Result<Vec<(String, String)>>;
-    // Context items for includes
-    async fn add_context_item(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str, byte_len: i64) -> anyhow::Result<()>;
+    async fn create_session(&self, client_id: Option<String>, settings: SessionSettings) -> Result<Uuid>;
+    // Creates the session under a caller-chosen id unless it already exists; true when created.
+    // An existing session is left untouched, settings included.
+    async fn get_or_create_session(&self, id: Uuid, client_id: Option<String>, settings: SessionSettings) -> Result<bool>;
+    async fn delete_session(&self, id: Uuid) -> Result<bool>;
+    async fn delete_sessions_where(&self, filter: &SessionDeleteFilter) -> Result<u64>;
+    async fn list_sessions(&self) -> Result<Vec<Uuid>>;
+    /// Sessions created with `client_id`, newest first.
+    async fn list_sessions_for_client(&self, client_id: &str) -> Result<Vec<Uuid>>;
+    /// The `limit` most recently active sessions, most recent first, with enough detail for a picker.
+    async fn recent_sessions(&self, limit: usize) -> Result<Vec<SessionPreview>>;
+    async fn get_session(&self, id: Uuid) -> Result<Option<Session>>;
+    /// Metadata and counts for one session, without reading its messages or tool events.
+    async fn session_summary(&self, id: Uuid) -> Result<Option<SessionSummary>>;
+    async fn update_settings(&self, id: Uuid, settings: SessionSettings) -> Result<()>;
+    /// Writes `settings` only if the session is still at `expected` settings_version;
+    /// the new version on success, `None` when another write got there first.
+    async fn update_settings_if_version(&self, id: Uuid, settings: SessionSettings, expected: i64) -> Result<Option<i64>>;
+    async fn append_message(&self, id: Uuid, msg: Message) -> Result<()>;
+    async fn append_tool_event(&self, id: Uuid, ev: ToolEvent) -> Result<()>;
+    // Bulk forms for imports/forks: all rows are written in one transaction, or none are
+    async fn append_messages(&self, id: Uuid, msgs: Vec<Message>) -> Result<()>;
+    async fn append_tool_events(&self, id: Uuid, evs: Vec<ToolEvent>) -> Result<()>;
+    // Marks the pending user message `turn` complete, storing `reply` in the same transaction
+    async fn complete_turn(&self, id: Uuid, turn: Uuid, reply: Option<Message>) -> Result<()>;
+    async fn get_tool_events(&self, id: Uuid, filter: &ToolEventFilter) -> Result<Vec<ToolEvent>>;
+    async fn get_tool_event(&self, id: Uuid, event_id: Uuid) -> Result<Option<ToolEvent>>;
+    /// `None` for an unknown session.
+    async fn history_counts(&self, id: Uuid) -> Result<Option<HistoryCounts>>;
+    // System rules CRUD; rules are global (not per session) and keyed by unique name
+    // Inserts the rule or replaces the content of an existing rule with the same name
+    async fn upsert_rule(&self, name: &str, content: &str) -> Result<()>;
+    async fn get_rule(&self, name: &str) -> Result<Option<(String, String)>>; // (name, content)
+    // All rules as (name, content), ordered by name
+    async fn list_rules(&self) -> Result<Vec<(String, String)>>;
+    async fn delete_rule(&self, name: &str) -> Result<bool>;
+    // Prompt templates, by name; see `crate::templates`
+    async fn upsert_template(&self, name: &str, body: &str) -> Result<()>;
+    async fn get_template(&self, name: &str) -> Result<Option<String>>;
+    // All templates as (name, body), ordered by name
+    async fn list_templates(&self) -> Result<Vec<(String, String)>>;
+    async fn delete_template(&self, name: &str) -> Result<bool>;
+    // Project-relative paths the file tools changed in a session, for `git.commit_changes`.
+    // Recording a path again only bumps its time.
+    async fn record_written_file(&self, session_id: Uuid, path: &str) -> Result<()>;
+    // Tracked paths, ordered by path
+    async fn written_files(&self, session_id: Uuid) -> Result<Vec<String>>;
+    async fn clear_written_files(&self, session_id: Uuid) -> Result<u64>;
+    // Context items for includes; `kind` is "file", "url" or "text" and `key` is the path, URL or
+    // caller-supplied label it came from.
+    // Returns true when a new item was stored, false when an identical one was refreshed.
+    // Implementations enforce the session's `max_context_items` / `context_overflow` settings.
+    async fn add_context_item(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str, byte_len: i64) -> Result<bool>;
+    async fn add_context_item_with_metadata(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str, byte_len: i64, metadata: Option<serde_json::Value>) -> Result<bool>;
+    // Oldest first
+    async fn list_context_items(&self, session_id: Uuid) -> Result<Vec<ContextItem>>;
+    // One page of `list_context_items`, skipping `offset` items
+    async fn list_context_items_page(&self, session_id: Uuid, offset: usize, limit: usize) -> Result<Vec<ContextItem>>;
+    async fn get_context_item(&self, session_id: Uuid, item_id: Uuid) -> Result<Option<ContextItem>>;
+    // The item adding `excerpt` under (kind, key) again would refresh rather than insert
+    async fn find_context_item(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str) -> Result<Option<ContextItem>>;
+    async fn delete_context_item(&self, session_id: Uuid, item_id: Uuid) -> Result<bool>;
+    async fn context_usage(&self, session_id: Uuid) -> Result<ContextUsage>;
+    async fn set_context_embedding(&self, context_item_id: Uuid, model: &str, vector: &[f32]) -> Result<()>;
+    // (context_item_id, vector) for a session's items embedded with `model`
+    async fn list_context_embeddings(&self, session_id: Uuid, model: &str) -> Result<Vec<(Uuid, Vec<f32>)>>;
+    // Reclaims free pages and truncates the WAL; returns the number of bytes freed
+    async fn maintenance(&self) -> Result<u64>;
+    /// Unexpired cached model response for exactly `request` (serialized): (model, content).
+    async fn get_cached_response(&self, request: &str) -> Result<Option<(String, String)>>;
+    /// Caches a model response for `ttl`, replacing any entry for `request` and dropping expired ones.
+    async fn put_cached_response(&self, request: &str, model: &str, content: &str, ttl: Duration) -> Result<()>;
 }
 
 impl SqliteSessionRepository {
-    pub async fn initialize(database_url: Option<String>) -> anyhow::Result<Self> {
+    pub async fn initialize(database_url: Option<String>) -> Result<Self> {
+        Self::initialize_with(database_url, PoolConfig::from_env()).await
+    }
+
+    pub async fn initialize_with(database_url: Option<String>, pool_config: PoolConfig) -> Result<Self> {
         let url = match database_url {
             Some(u) => u,
             None => resolve_default_db_url()?,
         };
+        // NORMAL is durable enough in WAL mode and avoids an fsync per commit;
+        // pragmas are set on the options so every pooled connection gets them
         let options = url.parse::<SqliteConnectOptions>()?
             .create_if_missing(true)
             .journal_mode(SqliteJournalMode::Wal)
-            .synchronous(SqliteSynchronous::Full);
-        let pool = Pool::<Sqlite>::connect_with(options).await?;
-        // busy_timeout via PRAGMA
-        sqlx::query("PRAGMA busy_timeout = 5000;").execute(&pool).await?;
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(Duration::from_millis(5000))
+            .pragma("wal_autocheckpoint", "1000");
+        let pool = SqlitePoolOptions::new()
+            .max_connections(pool_config.max_connections.max(1))
+            .acquire_timeout(pool_config.acquire_timeout)
+            .connect_with(options)
+            .await?;
         // apply migrations
         sqlx::migrate!("./migrations").run(&pool).await?;
         Ok(Self { pool })
@@ -52,7 +222,53 @@ impl SqliteSessionRepository {
     pub fn pool(&self) -> &Pool<Sqlite> { &self.pool }
 }
 
-fn resolve_default_db_url() -> anyhow::Result<String> {
+impl SqliteSessionRepository {
+    async fn db_size_bytes(&self) -> Result<i64> {
+        let page_count: i64 = sqlx::query("PRAGMA page_count;").fetch_one(&self.pool).await?.get(0);
+        let page_size: i64 = sqlx::query("PRAGMA page_size;").fetch_one(&self.pool).await?.get(0);
+        Ok(page_count * page_size)
+    }
+}
+
+fn tool_event_from_row(t: &sqlx::sqlite::SqliteRow) -> ToolEvent {
+    let id_str: String = t.get("id");
+    let created_at: String = t.get("created_at");
+    ToolEvent {
+        id: Uuid::parse_str(&id_str).unwrap_or_else(|_| Uuid::new_v4()),
+        tool: t.get("tool"),
+        summary: t.get("summary"),
+        status: t.get("status"),
+        error: t.try_get("error").ok(),
+        args: t.try_get::<String, _>("args_json").ok().and_then(|a| serde_json::from_str(&a).ok()),
+        created_at: DateTime::parse_from_rfc3339(&created_at).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
+    }
+}
+
+fn context_item_from_row(r: &sqlx::sqlite::SqliteRow) -> ContextItem {
+    let id_str: String = r.get("id");
+    let created_at: String = r.get("created_at");
+    ContextItem {
+        id: Uuid::parse_str(&id_str).unwrap_or_else(|_| Uuid::new_v4()),
+        kind: r.get("kind"),
+        key: r.get("key"),
+        content_excerpt: r.get("content_excerpt"),
+        byte_len: r.get("byte_len"),
+        metadata: r.try_get::<String, _>("metadata_json").ok().and_then(|m| serde_json::from_str(&m).ok()),
+        created_at: DateTime::parse_from_rfc3339(&created_at).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
+    }
+}
+
+/// Stable 64-bit FNV-1a hex digest; persisted, so it must not depend on std's hasher.
+fn content_hash(content: &str) -> String {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in content.as_bytes() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", h)
+}
+
+fn resolve_default_db_url() -> Result<String> {
     let base = std::env::var("XDG_DATA_HOME").ok().map(PathBuf::from).unwrap_or_else(|| {
         let home = std::env::var("HOME").unwrap_or_else(|_| ".".into());
         PathBuf::from(home).join(".local").join("share")
@@ -63,9 +279,74 @@ fn resolve_default_db_url() -> anyhow::Result<String> {
     Ok(format!("sqlite://{}", path.to_string_lossy()))
 }
 
+async fn insert_message<'e>(exec: impl sqlx::Executor<'e, Database = Sqlite>, id: Uuid, msg: Message) -> Result<()> {
+    sqlx::query("INSERT INTO messages (id, session_id, role, content_summary, content, model_used, created_at, status) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")
+        .bind(msg.id.to_string())
+        .bind(id.to_string())
+        .bind(msg.role)
+        .bind(msg.content_summary)
+        .bind(msg.content)
+        .bind(msg.model_used)
+        .bind(msg.created_at.to_rfc3339())
+        .bind(msg.status.as_str())
+        .execute(exec).await?;
+    Ok(())
+}
+
+async fn insert_tool_event<'e>(exec: impl sqlx::Executor<'e, Database = Sqlite>, id: Uuid, ev: ToolEvent) -> Result<()> {
+    sqlx::query("INSERT INTO tool_events (id, session_id, tool, summary, status, error, args_json, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")
+        .bind(ev.id.to_string())
+        .bind(id.to_string())
+        .bind(ev.tool)
+        .bind(ev.summary)
+        .bind(ev.status)
+        .bind(ev.error)
+        .bind(ev.args.map(|a| a.to_string()))
+        .bind(ev.created_at.to_rfc3339())
+        .execute(exec).await?;
+    Ok(())
+}
+
+/// Drops a session's oldest tool events beyond its `max_tool_events` setting and adds
+/// them to `sessions.tool_events_trimmed`; returns how many were dropped.
+async fn trim_tool_events(tx: &mut sqlx::Transaction<'_, Sqlite>, id: Uuid) -> Result<u64> {
+    let settings_json: Option<String> = sqlx::query("SELECT settings_json FROM sessions WHERE id = ?1")
+        .bind(id.to_string())
+        .fetch_optional(&mut **tx).await?
+        .map(|r| r.get("settings_json"));
+    let Some(settings_json) = settings_json else { return Ok(0) };
+    let settings: SessionSettings = serde_json::from_str(&settings_json)?;
+    let Some(cap) = settings.max_tool_events else { return Ok(0) };
+    // PATCH refuses 0, but a session created with it must still keep the event just recorded
+    let cap = cap.max(1);
+    let trimmed = sqlx::query("DELETE FROM tool_events WHERE id IN (SELECT id FROM tool_events WHERE session_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT -1 OFFSET ?2)")
+        .bind(id.to_string())
+        .bind(cap as i64)
+        .execute(&mut **tx).await?
+        .rows_affected();
+    if trimmed > 0 {
+        sqlx::query("UPDATE sessions SET tool_events_trimmed = tool_events_trimmed + ?1 WHERE id = ?2")
+            .bind(trimmed as i64)
+            .bind(id.to_string())
+            .execute(&mut **tx).await?;
+    }
+    Ok(trimmed)
+}
+
 #[async_trait]
 impl SessionRepository for SqliteSessionRepository {
-    async fn create_session(&self, client_id: Option<String>, settings: SessionSettings) -> anyhow::Result<Uuid> {
+    async fn get_or_create_session(&self, id: Uuid, client_id: Option<String>, settings: SessionSettings) -> Result<bool> {
+        let settings_json = serde_json::to_string(&settings)?;
+        let res = sqlx::query("INSERT INTO sessions (id, client_id, created_at, settings_json) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(id) DO NOTHING")
+            .bind(id.to_string())
+            .bind(client_id)
+            .bind(Utc::now().to_rfc3339())
+            .bind(settings_json)
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn create_session(&self, client_id: Option<String>, settings: SessionSettings) -> Result<Uuid> {
         let id = Uuid::new_v4();
         let now: DateTime<Utc> = Utc::now();
         let settings_json = serde_json::to_string(&settings)?;
@@ -78,7 +359,7 @@ impl SessionRepository for SqliteSessionRepository {
         Ok(id)
     }
 
-    async fn delete_session(&self, id: Uuid) -> anyhow::Result<bool> {
+    async fn delete_session(&self, id: Uuid) -> Result<bool> {
         let res = sqlx::query("DELETE FROM sessions WHERE id = ?1")
             .bind(id.to_string())
             .execute(&self.pool)
@@ -86,7 +367,17 @@ impl SessionRepository for SqliteSessionRepository {
         Ok(res.rows_affected() > 0)
     }
 
-    async fn list_sessions(&self) -> anyhow::Result<Vec<Uuid>> {
+    async fn delete_sessions_where(&self, filter: &SessionDeleteFilter) -> Result<u64> {
+        // refuse an unfiltered bulk delete
+        let Some(before) = filter.before else { return Err(Error::InvalidInput("at least one filter is required".into())) };
+        let res = sqlx::query("DELETE FROM sessions WHERE created_at < ?1")
+            .bind(before.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<Uuid>> {
         let rows = sqlx::query("SELECT id FROM sessions ORDER BY created_at DESC").fetch_all(&self.pool).await?;
         let ids = rows.into_iter().filter_map(|r| {
             let id_str: String = r.get::<String, _>("id");
@@ -95,51 +386,75 @@ impl SessionRepository for SqliteSessionRepository {
         Ok(ids)
     }
 
-    async fn get_session(&self, id: Uuid) -> anyhow::Result<Option<Session>> {
+    async fn list_sessions_for_client(&self, client_id: &str) -> Result<Vec<Uuid>> {
+        let rows = sqlx::query("SELECT id FROM sessions WHERE client_id = ?1 ORDER BY created_at DESC")
+            .bind(client_id)
+            .fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().filter_map(|r| Uuid::parse_str(&r.get::<String, _>("id")).ok()).collect())
+    }
+
+    async fn recent_sessions(&self, limit: usize) -> Result<Vec<SessionPreview>> {
+        let rows = sqlx::query(
+            "SELECT s.id, s.client_id, \
+               (SELECT content_summary FROM messages WHERE session_id = s.id AND role = 'user' ORDER BY created_at ASC LIMIT 1) AS title, \
+               (SELECT content_summary FROM messages WHERE session_id = s.id ORDER BY created_at DESC LIMIT 1) AS last_message, \
+               (SELECT count(*) FROM messages WHERE session_id = s.id) AS message_count, \
+               max(s.created_at, \
+                   COALESCE((SELECT max(created_at) FROM messages WHERE session_id = s.id), ''), \
+                   COALESCE((SELECT max(created_at) FROM tool_events WHERE session_id = s.id), '')) AS last_active_at \
+             FROM sessions s ORDER BY last_active_at DESC LIMIT ?1")
+            .bind(limit as i64)
+            .fetch_all(&self.pool).await?;
+        let previews = rows.into_iter().filter_map(|r| {
+            let id_str: String = r.get("id");
+            let last_active_at: String = r.get("last_active_at");
+            let message_count: i64 = r.get("message_count");
+            Some(SessionPreview {
+                id: Uuid::parse_str(&id_str).ok()?,
+                client_id: r.try_get("client_id").ok().flatten(),
+                title: r.try_get("title").ok().flatten(),
+                last_message: r.try_get("last_message").ok().flatten(),
+                message_count: message_count.max(0) as u64,
+                last_active_at: DateTime::parse_from_rfc3339(&last_active_at).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
+            })
+        }).collect();
+        Ok(previews)
+    }
+
+    async fn get_session(&self, id: Uuid) -> Result<Option<Session>> {
         use sqlx::Row;
-        let row = sqlx::query("SELECT id, client_id, created_at, settings_json FROM sessions WHERE id = ?1")
+        let row = sqlx::query("SELECT id, client_id, created_at, settings_json, settings_version FROM sessions WHERE id = ?1")
             .bind(id.to_string())
             .fetch_optional(&self.pool)
             .await?;
         let Some(r) = row else { return Ok(None) };
         let settings_json: String = r.get("settings_json");
         let settings: SessionSettings = serde_json::from_str(&settings_json)?;
-        let messages_rows = sqlx::query("SELECT id, role, content_summary, model_used, created_at FROM messages WHERE session_id = ?1 ORDER BY created_at ASC")
+        let messages_rows = sqlx::query("SELECT id, role, content_summary, content, model_used, created_at, status FROM messages WHERE session_id = ?1 ORDER BY created_at ASC")
             .bind(id.to_string())
             .fetch_all(&self.pool).await?;
-        let tool_rows = sqlx::query("SELECT id, tool, summary, status, error, created_at FROM tool_events WHERE session_id = ?1 ORDER BY created_at ASC")
+        let tool_rows = sqlx::query("SELECT id, tool, summary, status, error, args_json, created_at FROM tool_events WHERE session_id = ?1 ORDER BY created_at ASC")
             .bind(id.to_string())
             .fetch_all(&self.pool).await?;
         let messages = messages_rows.into_iter().map(|m| {
             let id_str: String = m.get("id");
             let role: String = m.get("role");
             let content_summary: String = m.get("content_summary");
+            let content: Option<String> = m.try_get("content").ok().flatten();
             let model_used: Option<String> = m.try_get("model_used").ok();
             let created_at: String = m.get("created_at");
+            let status: String = m.get("status");
             Message {
                 id: Uuid::parse_str(&id_str).unwrap_or_else(|_| Uuid::new_v4()),
                 role,
                 content_summary,
+                content,
                 model_used,
                 created_at: DateTime::parse_from_rfc3339(&created_at).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
+                status: MessageStatus::parse(&status),
             }
         }).collect();
-        let tool_history = tool_rows.into_iter().map(|t| {
-            let id_str: String = t.get("id");
-            let tool: String = t.get("tool");
-            let summary: String = t.get("summary");
-            let status: String = t.get("status");
-            let error: Option<String> = t.try_get("error").ok();
-            let created_at: String = t.get("created_at");
-            ToolEvent {
-                id: Uuid::parse_str(&id_str).unwrap_or_else(|_| Uuid::new_v4()),
-                tool,
-                summary,
-                status,
-                error,
-                created_at: DateTime::parse_from_rfc3339(&created_at).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
-            }
-        }).collect();
+        let tool_history = tool_rows.into_iter().map(|t| tool_event_from_row(&t)).collect();
         let id_parsed = {
             let id_str: String = r.get("id");
             Uuid::parse_str(&id_str).unwrap()
@@ -149,45 +464,134 @@ impl SessionRepository for SqliteSessionRepository {
             let s: String = r.get("created_at");
             DateTime::parse_from_rfc3339(&s).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now())
         };
-        let session = Session { id: id_parsed, client_id, created_at, messages, tool_history, settings };
+        let settings_version: i64 = r.get("settings_version");
+        let session = Session { id: id_parsed, client_id, created_at, messages, tool_history, settings, settings_version };
         Ok(Some(session))
     }
 
-    async fn update_settings(&self, id: Uuid, settings: SessionSettings) -> anyhow::Result<()> {
+    async fn session_summary(&self, id: Uuid) -> Result<Option<SessionSummary>> {
+        let row = sqlx::query("SELECT id, client_id, created_at, settings_json, settings_version, tool_events_trimmed, \
+            (SELECT count(*) FROM messages WHERE session_id = ?1) AS m, \
+            (SELECT count(*) FROM tool_events WHERE session_id = ?1) AS t, \
+            (SELECT count(*) FROM context_items WHERE session_id = ?1) AS c \
+            FROM sessions WHERE id = ?1")
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(r) = row else { return Ok(None) };
+        let settings_json: String = r.get("settings_json");
+        let created_at: String = r.get("created_at");
+        Ok(Some(SessionSummary {
+            id,
+            client_id: r.try_get::<Option<String>, _>("client_id")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
+            settings: serde_json::from_str(&settings_json)?,
+            settings_version: r.get("settings_version"),
+            message_count: r.get::<i64, _>("m") as u64,
+            tool_event_count: r.get::<i64, _>("t") as u64,
+            context_item_count: r.get::<i64, _>("c") as u64,
+            tool_events_trimmed: r.get::<i64, _>("tool_events_trimmed") as u64,
+        }))
+    }
+
+    async fn update_settings(&self, id: Uuid, settings: SessionSettings) -> Result<()> {
         let settings_json = serde_json::to_string(&settings)?;
-        sqlx::query("UPDATE sessions SET settings_json = ?1 WHERE id = ?2")
+        sqlx::query("UPDATE sessions SET settings_json = ?1, settings_version = settings_version + 1 WHERE id = ?2")
             .bind(settings_json)
             .bind(id.to_string())
             .execute(&self.pool).await?;
         Ok(())
     }
 
-    async fn append_message(&self, id: Uuid, msg: Message) -> anyhow::Result<()> {
-        sqlx::query("INSERT INTO messages (id, session_id, role, content_summary, model_used, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
-            .bind(msg.id.to_string())
+    async fn update_settings_if_version(&self, id: Uuid, settings: SessionSettings, expected: i64) -> Result<Option<i64>> {
+        let settings_json = serde_json::to_string(&settings)?;
+        let res = sqlx::query("UPDATE sessions SET settings_json = ?1, settings_version = settings_version + 1 WHERE id = ?2 AND settings_version = ?3")
+            .bind(settings_json)
             .bind(id.to_string())
-            .bind(msg.role)
-            .bind(msg.content_summary)
-            .bind(msg.model_used)
-            .bind(msg.created_at.to_rfc3339())
+            .bind(expected)
             .execute(&self.pool).await?;
+        Ok((res.rows_affected() > 0).then_some(expected + 1))
+    }
+
+    async fn append_message(&self, id: Uuid, msg: Message) -> Result<()> {
+        insert_message(&self.pool, id, msg).await
+    }
+
+    async fn append_tool_event(&self, id: Uuid, ev: ToolEvent) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_tool_event(&mut *tx, id, ev).await?;
+        trim_tool_events(&mut tx, id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn append_messages(&self, id: Uuid, msgs: Vec<Message>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for msg in msgs {
+            insert_message(&mut *tx, id, msg).await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
-    async fn append_tool_event(&self, id: Uuid, ev: ToolEvent) -> anyhow::Result<()> {
-        sqlx::query("INSERT INTO tool_events (id, session_id, tool, summary, status, error, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
-            .bind(ev.id.to_string())
+    async fn append_tool_events(&self, id: Uuid, evs: Vec<ToolEvent>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for ev in evs {
+            insert_tool_event(&mut *tx, id, ev).await?;
+        }
+        trim_tool_events(&mut tx, id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn complete_turn(&self, id: Uuid, turn: Uuid, reply: Option<Message>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(msg) = reply {
+            insert_message(&mut *tx, id, msg).await?;
+        }
+        sqlx::query("UPDATE messages SET status = ?1 WHERE session_id = ?2 AND id = ?3")
+            .bind(MessageStatus::Complete.as_str())
             .bind(id.to_string())
-            .bind(ev.tool)
-            .bind(ev.summary)
-            .bind(ev.status)
-            .bind(ev.error)
-            .bind(ev.created_at.to_rfc3339())
-            .execute(&self.pool).await?;
+            .bind(turn.to_string())
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    async fn upsert_rule(&self, name: &str, content: &str) -> anyhow::Result<()> {
+    async fn get_tool_events(&self, id: Uuid, filter: &ToolEventFilter) -> Result<Vec<ToolEvent>> {
+        let rows = sqlx::query("SELECT id, tool, summary, status, error, args_json, created_at FROM tool_events WHERE session_id = ?1 AND (?2 IS NULL OR status = ?2) AND (?3 IS NULL OR tool = ?3) ORDER BY created_at ASC")
+            .bind(id.to_string())
+            .bind(filter.status.as_deref())
+            .bind(filter.tool.as_deref())
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(tool_event_from_row).collect())
+    }
+
+    async fn get_tool_event(&self, id: Uuid, event_id: Uuid) -> Result<Option<ToolEvent>> {
+        let row = sqlx::query("SELECT id, tool, summary, status, error, args_json, created_at FROM tool_events WHERE session_id = ?1 AND id = ?2")
+            .bind(id.to_string())
+            .bind(event_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(tool_event_from_row))
+    }
+
+    async fn history_counts(&self, id: Uuid) -> Result<Option<HistoryCounts>> {
+        let row = sqlx::query("SELECT (SELECT count(*) FROM messages WHERE session_id = ?1) AS c FROM sessions WHERE id = ?1")
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else { return Ok(None) };
+        let rows = sqlx::query("SELECT tool, count(*) AS c FROM tool_events WHERE session_id = ?1 GROUP BY tool")
+            .bind(id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+        let by_tool: std::collections::BTreeMap<String, u64> = rows.iter().map(|r| (r.get::<String, _>("tool"), r.get::<i64, _>("c") as u64)).collect();
+        Ok(Some(HistoryCounts { messages: row.get::<i64, _>("c") as u64, tool_events: by_tool.values().sum(), by_tool }))
+    }
+
+    async fn upsert_rule(&self, name: &str, content: &str) -> Result<()> {
         let now = Utc::now().to_rfc3339();
         // try update first
         let res = sqlx::query("UPDATE rules SET content = ?1, updated_at = ?2 WHERE name = ?3")
@@ -209,7 +613,7 @@ impl SessionRepository for SqliteSessionRepository {
         Ok(())
     }
 
-    async fn get_rule(&self, name: &str) -> anyhow::Result<Option<(String, String)>> {
+    async fn get_rule(&self, name: &str) -> Result<Option<(String, String)>> {
         let row = sqlx::query("SELECT name, content FROM rules WHERE name = ?1")
             .bind(name)
             .fetch_optional(&self.pool)
@@ -217,7 +621,7 @@ impl SessionRepository for SqliteSessionRepository {
         Ok(row.map(|r| (r.get::<String, _>("name"), r.get::<String, _>("content"))))
     }
 
-    async fn list_rules(&self) -> anyhow::Result<Vec<(String, String)>> {
+    async fn list_rules(&self) -> Result<Vec<(String, String)>> {
         let rows = sqlx::query("SELECT name, content FROM rules ORDER BY name ASC")
             .fetch_all(&self.pool)
             .await?;
@@ -226,18 +630,252 @@ impl SessionRepository for SqliteSessionRepository {
         Ok(out)
     }
 
-    async fn add_context_item(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str, byte_len: i64) -> anyhow::Result<()> {
+    async fn delete_rule(&self, name: &str) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM rules WHERE name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn upsert_template(&self, name: &str, body: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query("INSERT INTO prompt_templates (name, body, created_at, updated_at) VALUES (?1, ?2, ?3, ?3) ON CONFLICT(name) DO UPDATE SET body = excluded.body, updated_at = excluded.updated_at")
+            .bind(name)
+            .bind(body)
+            .bind(now)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_template(&self, name: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT body FROM prompt_templates WHERE name = ?1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get::<String, _>("body")))
+    }
+
+    async fn list_templates(&self) -> Result<Vec<(String, String)>> {
+        let rows = sqlx::query("SELECT name, body FROM prompt_templates ORDER BY name ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|r| (r.get::<String, _>("name"), r.get::<String, _>("body"))).collect())
+    }
+
+    async fn delete_template(&self, name: &str) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM prompt_templates WHERE name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn record_written_file(&self, session_id: Uuid, path: &str) -> Result<()> {
+        sqlx::query("INSERT INTO written_files (session_id, path, written_at) VALUES (?1, ?2, ?3) ON CONFLICT(session_id, path) DO UPDATE SET written_at = excluded.written_at")
+            .bind(session_id.to_string())
+            .bind(path)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn written_files(&self, session_id: Uuid) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT path FROM written_files WHERE session_id = ?1 ORDER BY path ASC")
+            .bind(session_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|r| r.get::<String, _>("path")).collect())
+    }
+
+    async fn clear_written_files(&self, session_id: Uuid) -> Result<u64> {
+        let res = sqlx::query("DELETE FROM written_files WHERE session_id = ?1")
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn add_context_item(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str, byte_len: i64) -> Result<bool> {
+        self.add_context_item_with_metadata(session_id, kind, key, excerpt, byte_len, None).await
+    }
+
+    async fn add_context_item_with_metadata(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str, byte_len: i64, metadata: Option<serde_json::Value>) -> Result<bool> {
         let id = Uuid::new_v4().to_string();
         let now = Utc::now().to_rfc3339();
-        sqlx::query("INSERT INTO context_items (id, session_id, kind, key, content_excerpt, byte_len, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
+        let hash = content_hash(excerpt);
+        let mut tx = self.pool.begin().await?;
+        let refreshed = sqlx::query("UPDATE context_items SET last_seen = ?1 WHERE session_id = ?2 AND kind = ?3 AND key = ?4 AND content_hash = ?5")
+            .bind(&now)
+            .bind(session_id.to_string())
+            .bind(kind)
+            .bind(key)
+            .bind(&hash)
+            .execute(&mut *tx).await?;
+        if refreshed.rows_affected() > 0 {
+            tx.commit().await?;
+            return Ok(false);
+        }
+        let settings_json: Option<String> = sqlx::query("SELECT settings_json FROM sessions WHERE id = ?1")
+            .bind(session_id.to_string())
+            .fetch_optional(&mut *tx).await?
+            .map(|r| r.get("settings_json"));
+        let settings: SessionSettings = match settings_json {
+            Some(j) => serde_json::from_str(&j)?,
+            None => return Err(Error::NotFound(format!("session {}", session_id))),
+        };
+        let limit = settings.max_context_items.unwrap_or(DEFAULT_MAX_CONTEXT_ITEMS);
+        let count: i64 = sqlx::query("SELECT count(*) AS c FROM context_items WHERE session_id = ?1")
+            .bind(session_id.to_string())
+            .fetch_one(&mut *tx).await?
+            .get("c");
+        if count as usize >= limit {
+            match settings.context_overflow.unwrap_or_default() {
+                ContextOverflowPolicy::Reject => return Err(ContextLimitExceeded { limit }.into()),
+                ContextOverflowPolicy::EvictOldest => {
+                    let excess = count - limit as i64 + 1;
+                    sqlx::query("DELETE FROM context_items WHERE id IN (SELECT id FROM context_items WHERE session_id = ?1 ORDER BY created_at ASC LIMIT ?2)")
+                        .bind(session_id.to_string())
+                        .bind(excess)
+                        .execute(&mut *tx).await?;
+                }
+            }
+        }
+        sqlx::query("INSERT INTO context_items (id, session_id, kind, key, content_excerpt, byte_len, metadata_json, content_hash, last_seen, created_at, token_len) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9, ?10)")
             .bind(id)
             .bind(session_id.to_string())
             .bind(kind)
             .bind(key)
             .bind(excerpt)
             .bind(byte_len)
+            .bind(metadata.map(|m| m.to_string()))
+            .bind(hash)
             .bind(now)
-            .execute(&self.pool).await?;
+            .bind(crate::tokens::estimate_tokens(excerpt) as i64)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn list_context_items(&self, session_id: Uuid) -> Result<Vec<ContextItem>> {
+        let rows = sqlx::query("SELECT id, kind, key, content_excerpt, byte_len, metadata_json, created_at FROM context_items WHERE session_id = ?1 ORDER BY created_at ASC")
+            .bind(session_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(context_item_from_row).collect())
+    }
+
+    async fn list_context_items_page(&self, session_id: Uuid, offset: usize, limit: usize) -> Result<Vec<ContextItem>> {
+        let rows = sqlx::query("SELECT id, kind, key, content_excerpt, byte_len, metadata_json, created_at FROM context_items WHERE session_id = ?1 ORDER BY created_at ASC, id ASC LIMIT ?2 OFFSET ?3")
+            .bind(session_id.to_string())
+            .bind(limit as i64)
+            .bind(offset as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(context_item_from_row).collect())
+    }
+
+    async fn get_context_item(&self, session_id: Uuid, item_id: Uuid) -> Result<Option<ContextItem>> {
+        let row = sqlx::query("SELECT id, kind, key, content_excerpt, byte_len, metadata_json, created_at FROM context_items WHERE session_id = ?1 AND id = ?2")
+            .bind(session_id.to_string())
+            .bind(item_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(context_item_from_row))
+    }
+
+    async fn find_context_item(&self, session_id: Uuid, kind: &str, key: &str, excerpt: &str) -> Result<Option<ContextItem>> {
+        let row = sqlx::query("SELECT id, kind, key, content_excerpt, byte_len, metadata_json, created_at FROM context_items WHERE session_id = ?1 AND kind = ?2 AND key = ?3 AND content_hash = ?4")
+            .bind(session_id.to_string())
+            .bind(kind)
+            .bind(key)
+            .bind(content_hash(excerpt))
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(context_item_from_row))
+    }
+
+    async fn delete_context_item(&self, session_id: Uuid, item_id: Uuid) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM context_items WHERE session_id = ?1 AND id = ?2")
+            .bind(session_id.to_string())
+            .bind(item_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn context_usage(&self, session_id: Uuid) -> Result<ContextUsage> {
+        let row = sqlx::query("SELECT COALESCE(SUM(byte_len), 0) AS used, COALESCE(SUM(COALESCE(token_len, (byte_len + 3) / 4)), 0) AS tokens, count(*) AS c FROM context_items WHERE session_id = ?1")
+            .bind(session_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(ContextUsage { used_bytes: row.get::<i64, _>("used").max(0) as u64, used_tokens: row.get::<i64, _>("tokens").max(0) as u64, item_count: row.get::<i64, _>("c") as u64 })
+    }
+
+    async fn set_context_embedding(&self, context_item_id: Uuid, model: &str, vector: &[f32]) -> Result<()> {
+        let blob: Vec<u8> = vector.iter().flat_map(|f| f.to_le_bytes()).collect();
+        sqlx::query("INSERT INTO context_embeddings (context_item_id, model, dims, vector, created_at) VALUES (?1, ?2, ?3, ?4, ?5) ON CONFLICT(context_item_id) DO UPDATE SET model = excluded.model, dims = excluded.dims, vector = excluded.vector, created_at = excluded.created_at")
+            .bind(context_item_id.to_string())
+            .bind(model)
+            .bind(vector.len() as i64)
+            .bind(blob)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_context_embeddings(&self, session_id: Uuid, model: &str) -> Result<Vec<(Uuid, Vec<f32>)>> {
+        let rows = sqlx::query("SELECT e.context_item_id, e.vector FROM context_embeddings e JOIN context_items c ON c.id = e.context_item_id WHERE c.session_id = ?1 AND e.model = ?2")
+            .bind(session_id.to_string())
+            .bind(model)
+            .fetch_all(&self.pool)
+            .await?;
+        let out = rows.into_iter().filter_map(|r| {
+            let id: String = r.get("context_item_id");
+            let blob: Vec<u8> = r.get("vector");
+            let vector = blob.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+            Uuid::parse_str(&id).ok().map(|id| (id, vector))
+        }).collect();
+        Ok(out)
+    }
+
+    async fn maintenance(&self) -> Result<u64> {
+        let before = self.db_size_bytes().await?;
+        sqlx::query("VACUUM;").execute(&self.pool).await?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE);").execute(&self.pool).await?;
+        let after = self.db_size_bytes().await?;
+        Ok((before - after).max(0) as u64)
+    }
+
+    async fn get_cached_response(&self, request: &str) -> Result<Option<(String, String)>> {
+        let row = sqlx::query("SELECT model, content FROM response_cache WHERE key = ?1 AND request_json = ?2 AND expires_at > ?3")
+            .bind(content_hash(request))
+            .bind(request)
+            .bind(Utc::now().to_rfc3339())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| (r.get("model"), r.get("content"))))
+    }
+
+    async fn put_cached_response(&self, request: &str, model: &str, content: &str, ttl: Duration) -> Result<()> {
+        let now = Utc::now();
+        // clamp absurd TTLs so the timestamp stays a comparable RFC 3339 string
+        let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::days(36500)).min(chrono::Duration::days(36500));
+        let expires_at = now + ttl;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM response_cache WHERE expires_at <= ?1")
+            .bind(now.to_rfc3339())
+            .execute(&mut *tx).await?;
+        sqlx::query("INSERT OR REPLACE INTO response_cache (key, request_json, model, content, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+            .bind(content_hash(request))
+            .bind(request)
+            .bind(model)
+            .bind(content)
+            .bind(now.to_rfc3339())
+            .bind(expires_at.to_rfc3339())
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 }
@@ -275,6 +913,43 @@ mod tests {
         assert!(list2.is_empty());
     }
 
+    #[tokio::test]
+    async fn get_or_create_session_is_idempotent() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = Uuid::new_v4();
+        let settings = SessionSettings { project_root: Some("/a".into()), ..Default::default() };
+        assert!(repo.get_or_create_session(id, Some("c".into()), settings).await.unwrap());
+        assert!(!repo.get_or_create_session(id, Some("c".into()), SessionSettings::default()).await.unwrap());
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!(got.settings.project_root.as_deref(), Some("/a"));
+        assert_eq!(repo.list_sessions().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delete_sessions_where_before_and_requires_filter() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+
+        let old = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        sqlx::query("UPDATE sessions SET created_at = ?1 WHERE id = ?2")
+            .bind("2020-01-01T00:00:00+00:00")
+            .bind(old.to_string())
+            .execute(repo.pool()).await.unwrap();
+        let recent = repo.create_session(None, SessionSettings::default()).await.unwrap();
+
+        assert!(repo.delete_sessions_where(&SessionDeleteFilter::default()).await.is_err());
+
+        let cutoff = DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        let n = repo.delete_sessions_where(&SessionDeleteFilter { before: Some(cutoff) }).await.unwrap();
+        assert_eq!(n, 1);
+        let list = repo.list_sessions().await.unwrap();
+        assert_eq!(list, vec![recent]);
+    }
+
     #[tokio::test]
     async fn append_history_and_update_settings() {
         let dir = tempdir().unwrap();
@@ -287,8 +962,10 @@ mod tests {
             id: Uuid::new_v4(),
             role: "user".into(),
             content_summary: "hello".into(),
+            content: None,
             model_used: None,
             created_at: Utc::now(),
+            status: MessageStatus::Complete,
         };
         repo.append_message(id, msg.clone()).await.unwrap();
 
@@ -298,12 +975,12 @@ mod tests {
             summary: "ran".into(),
             status: "ok".into(),
             error: None,
+            args: None,
             created_at: Utc::now(),
         };
         repo.append_tool_event(id, ev.clone()).await.unwrap();
 
-        let mut new_settings = SessionSettings::default();
-        new_settings.project_root = Some("/tmp".into());
+        let new_settings = SessionSettings { project_root: Some("/tmp".into()), ..Default::default() };
         repo.update_settings(id, new_settings.clone()).await.unwrap();
 
         let got = repo.get_session(id).await.unwrap().unwrap();
@@ -314,6 +991,230 @@ mod tests {
         assert_eq!(got.settings.project_root.as_deref(), Some("/tmp"));
     }
 
+    #[tokio::test]
+    async fn bulk_appends_are_all_or_nothing() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let msg = |i: usize| Message { id: Uuid::new_v4(), role: "user".into(), content_summary: format!("m{}", i), content: None, model_used: None, created_at: Utc::now(), status: MessageStatus::Complete };
+
+        repo.append_messages(id, (0..50).map(msg).collect()).await.unwrap();
+        let ev = ToolEvent { id: Uuid::new_v4(), tool: "t".into(), summary: "s".into(), status: "ok".into(), error: None, args: None, created_at: Utc::now() };
+        repo.append_tool_events(id, vec![ev.clone(), ToolEvent { id: Uuid::new_v4(), ..ev.clone() }]).await.unwrap();
+
+        // a duplicate id fails the whole batch
+        let dup = msg(99);
+        assert!(repo.append_messages(id, vec![dup.clone(), dup]).await.is_err());
+
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!(got.messages.len(), 50);
+        assert_eq!(got.tool_history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn history_counts_group_tool_events() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        assert_eq!(repo.history_counts(Uuid::new_v4()).await.unwrap(), None);
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        assert_eq!(repo.history_counts(id).await.unwrap(), Some(HistoryCounts::default()));
+
+        let msg = |i: usize| Message { id: Uuid::new_v4(), role: "user".into(), content_summary: format!("m{}", i), content: None, model_used: None, created_at: Utc::now(), status: MessageStatus::Complete };
+        repo.append_messages(id, (0..3).map(msg).collect()).await.unwrap();
+        let ev = |tool: &str| ToolEvent { id: Uuid::new_v4(), tool: tool.into(), summary: "s".into(), status: "ok".into(), error: None, args: None, created_at: Utc::now() };
+        repo.append_tool_events(id, vec![ev("files.read"), ev("files.read"), ev("git.status")]).await.unwrap();
+
+        let counts = repo.history_counts(id).await.unwrap().unwrap();
+        assert_eq!((counts.messages, counts.tool_events), (3, 3));
+        assert_eq!(counts.by_tool.get("files.read"), Some(&2));
+        assert_eq!(counts.by_tool.get("git.status"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn conditional_settings_update_rejects_stale_version() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let v0 = repo.get_session(id).await.unwrap().unwrap().settings_version;
+        let a = SessionSettings { project_root: Some("/a".into()), ..Default::default() };
+        let b = SessionSettings { project_root: Some("/b".into()), ..Default::default() };
+        assert_eq!(repo.update_settings_if_version(id, a.clone(), v0).await.unwrap(), Some(v0 + 1));
+        // a second writer still holding v0 loses
+        assert_eq!(repo.update_settings_if_version(id, b, v0).await.unwrap(), None);
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!((got.settings, got.settings_version), (a, v0 + 1));
+    }
+
+    #[tokio::test]
+    async fn session_summary_counts_without_history() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        assert_eq!(repo.session_summary(Uuid::new_v4()).await.unwrap(), None);
+        let settings = SessionSettings { project_root: Some("/a".into()), ..Default::default() };
+        let id = repo.create_session(Some("cli".into()), settings.clone()).await.unwrap();
+        let msg = Message { id: Uuid::new_v4(), role: "user".into(), content_summary: "hi".into(), content: None, model_used: None, created_at: Utc::now(), status: MessageStatus::Complete };
+        repo.append_message(id, msg).await.unwrap();
+        repo.add_context_item(id, "file", "a.rs", "aaa", 3).await.unwrap();
+
+        let summary = repo.session_summary(id).await.unwrap().unwrap();
+        let session = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!((summary.id, summary.client_id.as_deref(), summary.created_at), (id, Some("cli"), session.created_at));
+        assert_eq!((summary.settings, summary.settings_version), (settings, session.settings_version));
+        assert_eq!((summary.message_count, summary.tool_event_count, summary.context_item_count), (1, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn tool_events_are_trimmed_to_the_session_cap() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings { max_tool_events: Some(3), ..Default::default() }).await.unwrap();
+        let t0 = Utc::now();
+        let ev = |i: i64| ToolEvent { id: Uuid::new_v4(), tool: "discovery.grep".into(), summary: format!("e{}", i), status: "ok".into(), error: None, args: None, created_at: t0 + chrono::Duration::seconds(i) };
+        for i in 0..4 { repo.append_tool_event(id, ev(i)).await.unwrap(); }
+        repo.append_tool_events(id, vec![ev(4), ev(5)]).await.unwrap();
+
+        let kept = repo.get_session(id).await.unwrap().unwrap().tool_history;
+        assert_eq!(kept.iter().map(|e| e.summary.as_str()).collect::<Vec<_>>(), vec!["e3", "e4", "e5"]);
+        assert_eq!(repo.session_summary(id).await.unwrap().unwrap().tool_events_trimmed, 3);
+
+        // a cap of 0 still keeps the newest event
+        let zero = repo.create_session(None, SessionSettings { max_tool_events: Some(0), ..Default::default() }).await.unwrap();
+        repo.append_tool_event(zero, ev(0)).await.unwrap();
+        repo.append_tool_events(zero, vec![ev(1), ev(2)]).await.unwrap();
+        let kept = repo.get_session(zero).await.unwrap().unwrap().tool_history;
+        assert_eq!(kept.iter().map(|e| e.summary.as_str()).collect::<Vec<_>>(), vec!["e2"]);
+
+        // uncapped sessions keep everything
+        let open = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        for i in 0..5 { repo.append_tool_event(open, ev(i)).await.unwrap(); }
+        assert_eq!(repo.session_summary(open).await.unwrap().unwrap().tool_event_count, 5);
+    }
+
+    #[tokio::test]
+    async fn complete_turn_stores_reply_and_clears_pending() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let user = Message { id: Uuid::new_v4(), role: "user".into(), content_summary: "q".into(), content: None, model_used: None, created_at: Utc::now(), status: MessageStatus::Pending };
+        repo.append_message(id, user.clone()).await.unwrap();
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!(got.messages[0].status, MessageStatus::Pending);
+
+        let reply = Message { id: Uuid::new_v4(), role: "assistant".into(), content_summary: "a".into(), status: MessageStatus::Complete, ..user.clone() };
+        repo.complete_turn(id, user.id, Some(reply)).await.unwrap();
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!(got.messages.len(), 2);
+        assert!(got.messages.iter().all(|m| m.status == MessageStatus::Complete));
+    }
+
+    #[tokio::test]
+    async fn list_sessions_for_client_scopes_by_client_id() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let a = repo.create_session(Some("a".into()), SessionSettings::default()).await.unwrap();
+        let _b = repo.create_session(Some("b".into()), SessionSettings::default()).await.unwrap();
+        let _anon = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        assert_eq!(repo.list_sessions_for_client("a").await.unwrap(), vec![a]);
+        assert!(repo.list_sessions_for_client("c").await.unwrap().is_empty());
+        assert_eq!(repo.list_sessions().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn recent_sessions_order_by_activity_with_previews() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let older = repo.create_session(Some("c1".into()), SessionSettings::default()).await.unwrap();
+        let idle = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let t0 = Utc::now() + chrono::Duration::seconds(10);
+        let msg = |role: &str, summary: &str, secs: i64| Message { id: Uuid::new_v4(), role: role.into(), content_summary: summary.into(), content: None, model_used: None, created_at: t0 + chrono::Duration::seconds(secs), status: MessageStatus::Complete };
+        repo.append_messages(older, vec![msg("user", "fix the build", 0), msg("assistant", "done", 1)]).await.unwrap();
+
+        let recent = repo.recent_sessions(10).await.unwrap();
+        assert_eq!(recent.iter().map(|p| p.id).collect::<Vec<_>>(), vec![older, idle]);
+        assert_eq!(recent[0].client_id.as_deref(), Some("c1"));
+        assert_eq!(recent[0].title.as_deref(), Some("fix the build"));
+        assert_eq!(recent[0].last_message.as_deref(), Some("done"));
+        assert_eq!(recent[0].message_count, 2);
+        assert_eq!(recent[0].last_active_at, t0 + chrono::Duration::seconds(1));
+        assert_eq!((recent[1].title.clone(), recent[1].message_count), (None, 0));
+
+        assert_eq!(repo.recent_sessions(1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn message_full_content_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let msg = Message { id: Uuid::new_v4(), role: "user".into(), content_summary: "long…".into(), content: Some("long text".into()), model_used: None, created_at: Utc::now(), status: MessageStatus::Complete };
+        repo.append_message(id, msg).await.unwrap();
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!(got.messages[0].content.as_deref(), Some("long text"));
+        // full content stays out of serialized history
+        assert!(serde_json::to_value(&got.messages[0]).unwrap().get("content").is_none());
+    }
+
+    #[tokio::test]
+    async fn settings_version_increments_on_update() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        assert_eq!(repo.get_session(id).await.unwrap().unwrap().settings_version, 0);
+
+        repo.update_settings(id, SessionSettings::default()).await.unwrap();
+        repo.update_settings(id, SessionSettings::default()).await.unwrap();
+        assert_eq!(repo.get_session(id).await.unwrap().unwrap().settings_version, 2);
+    }
+
+    #[tokio::test]
+    async fn get_tool_events_filters_by_status_and_tool() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        for (tool, status) in [("url", "error"), ("url", "ok"), ("git.commit", "error")] {
+            let ev = ToolEvent { id: Uuid::new_v4(), tool: tool.into(), summary: "s".into(), status: status.into(), error: None, args: None, created_at: Utc::now() };
+            repo.append_tool_event(id, ev).await.unwrap();
+        }
+
+        let all = repo.get_tool_events(id, &ToolEventFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 3);
+        let errors = repo.get_tool_events(id, &ToolEventFilter { status: Some("error".into()), tool: None }).await.unwrap();
+        assert_eq!(errors.len(), 2);
+        let url_errors = repo.get_tool_events(id, &ToolEventFilter { status: Some("error".into()), tool: Some("url".into()) }).await.unwrap();
+        assert_eq!(url_errors.len(), 1);
+        assert_eq!(url_errors[0].tool, "url");
+    }
+
+    #[tokio::test]
+    async fn tool_event_args_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let args = serde_json::json!({"url": "https://example.com", "max_bytes": 64});
+        let ev = ToolEvent { id: Uuid::new_v4(), tool: "include_url".into(), summary: "s".into(), status: "error".into(), error: Some("timeout".into()), args: Some(args.clone()), created_at: Utc::now() };
+        repo.append_tool_event(id, ev.clone()).await.unwrap();
+
+        let got = repo.get_tool_event(id, ev.id).await.unwrap().unwrap();
+        assert_eq!(got.args, Some(args));
+        assert!(repo.get_tool_event(id, Uuid::new_v4()).await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn pragmas_and_migrations_applied() {
         let dir = tempdir().unwrap();
@@ -335,6 +1236,48 @@ mod tests {
         let _repo2 = SqliteSessionRepository::initialize(Some(format!("sqlite://{}", path.to_string_lossy()))).await.unwrap();
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_appends_do_not_lock() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let cfg = PoolConfig { max_connections: 4, acquire_timeout: Duration::from_secs(30) };
+        let repo = SqliteSessionRepository::initialize_with(Some(url), cfg).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+
+        let mut handles = Vec::new();
+        for i in 0..64 {
+            let repo = repo.clone();
+            handles.push(tokio::spawn(async move {
+                let msg = Message { id: Uuid::new_v4(), role: "user".into(), content_summary: format!("m{}", i), content: None, model_used: None, created_at: Utc::now(), status: MessageStatus::Complete };
+                repo.append_message(id, msg).await
+            }));
+        }
+        for h in handles { h.await.unwrap().unwrap(); }
+
+        let got = repo.get_session(id).await.unwrap().unwrap();
+        assert_eq!(got.messages.len(), 64);
+    }
+
+    #[tokio::test]
+    async fn maintenance_reclaims_space_after_delete() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        for _ in 0..200 {
+            let msg = Message { id: Uuid::new_v4(), role: "user".into(), content_summary: "x".repeat(4096), content: None, model_used: None, created_at: Utc::now(), status: MessageStatus::Complete };
+            repo.append_message(id, msg).await.unwrap();
+        }
+        repo.delete_session(id).await.unwrap();
+
+        let freed = repo.maintenance().await.unwrap();
+        assert!(freed > 0, "expected vacuum to free pages");
+        // a second pass has nothing left to reclaim
+        assert_eq!(repo.maintenance().await.unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn rules_upsert_and_list_and_get() {
         let dir = tempdir().unwrap();
@@ -356,6 +1299,67 @@ mod tests {
         assert!(list.iter().any(|(n, c)| n == "security" && c == "Never commit secrets."));
     }
 
+    #[tokio::test]
+    async fn rules_contract_via_trait_object() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let sqlite = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let repo: &dyn SessionRepository = &sqlite;
+
+        repo.upsert_rule("style", "v1").await.unwrap();
+        repo.upsert_rule("style", "v2").await.unwrap();
+        assert_eq!(repo.get_rule("style").await.unwrap(), Some(("style".to_string(), "v2".to_string())));
+        assert_eq!(repo.get_rule("missing").await.unwrap(), None);
+        assert_eq!(repo.list_rules().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn templates_upsert_get_list_delete() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        repo.upsert_template("review", "Review {{diff}}").await.unwrap();
+        repo.upsert_template("explain", "Explain {{file}}").await.unwrap();
+        repo.upsert_template("review", "Review this diff:\n{{diff}}").await.unwrap();
+        assert_eq!(repo.get_template("review").await.unwrap().as_deref(), Some("Review this diff:\n{{diff}}"));
+        assert_eq!(repo.list_templates().await.unwrap().iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), vec!["explain", "review"]);
+        assert!(repo.delete_template("explain").await.unwrap());
+        assert!(!repo.delete_template("explain").await.unwrap());
+        assert_eq!(repo.get_template("explain").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn written_files_are_tracked_per_session() {
+        let dir = tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let a = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let b = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        repo.record_written_file(a, "src/main.rs").await.unwrap();
+        repo.record_written_file(a, "README.md").await.unwrap();
+        repo.record_written_file(a, "src/main.rs").await.unwrap();
+        repo.record_written_file(b, "other.rs").await.unwrap();
+        assert_eq!(repo.written_files(a).await.unwrap(), vec!["README.md", "src/main.rs"]);
+        assert_eq!(repo.clear_written_files(a).await.unwrap(), 2);
+        assert!(repo.written_files(a).await.unwrap().is_empty());
+        assert_eq!(repo.written_files(b).await.unwrap(), vec!["other.rs"]);
+    }
+
+    #[tokio::test]
+    async fn delete_rule_removes_only_named_rule() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        repo.upsert_rule("a", "one").await.unwrap();
+        repo.upsert_rule("b", "two").await.unwrap();
+
+        assert!(repo.delete_rule("a").await.unwrap());
+        assert!(!repo.delete_rule("a").await.unwrap());
+        assert_eq!(repo.list_rules().await.unwrap(), vec![("b".to_string(), "two".to_string())]);
+    }
+
     #[tokio::test]
     async fn context_items_inserted_for_session() {
         let dir = tempdir().unwrap();
@@ -375,6 +1379,161 @@ mod tests {
         let c: i64 = row.get::<i64, _>("c");
         assert_eq!(c, 1);
     }
+
+    #[tokio::test]
+    async fn list_context_items_returns_session_items() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+
+        let session_id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let other = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        repo.add_context_item(session_id, "file", "src/main.rs", "fn main(){}", 12).await.unwrap();
+        repo.add_context_item(other, "url", "https://example.com", "hi", 2).await.unwrap();
+
+        let items = repo.list_context_items(session_id).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].kind, "file");
+        assert_eq!(items[0].key, "src/main.rs");
+        assert_eq!(items[0].byte_len, 12);
+    }
+
+    #[tokio::test]
+    async fn context_item_limit_rejects_or_evicts_oldest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let settings = SessionSettings { max_context_items: Some(2), ..Default::default() };
+        let sid = repo.create_session(None, settings.clone()).await.unwrap();
+
+        repo.add_context_item(sid, "file", "a", "a", 1).await.unwrap();
+        repo.add_context_item(sid, "file", "b", "b", 1).await.unwrap();
+        let err = repo.add_context_item(sid, "file", "c", "c", 1).await.unwrap_err();
+        assert!(matches!(err, Error::ContextLimit(ContextLimitExceeded { limit: 2 })));
+        assert_eq!(repo.list_context_items(sid).await.unwrap().len(), 2);
+
+        let evicting = SessionSettings { context_overflow: Some(ContextOverflowPolicy::EvictOldest), ..settings };
+        repo.update_settings(sid, evicting).await.unwrap();
+        repo.add_context_item(sid, "file", "c", "c", 1).await.unwrap();
+        let keys: Vec<String> = repo.list_context_items(sid).await.unwrap().into_iter().map(|i| i.key).collect();
+        assert_eq!(keys, vec!["b".to_string(), "c".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn identical_context_items_are_refreshed_not_duplicated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let sid = repo.create_session(None, SessionSettings::default()).await.unwrap();
+
+        assert!(repo.add_context_item(sid, "file", "a.rs", "fn a(){}", 8).await.unwrap());
+        assert!(!repo.add_context_item(sid, "file", "a.rs", "fn a(){}", 8).await.unwrap());
+        assert_eq!(repo.list_context_items(sid).await.unwrap().len(), 1);
+
+        // changed content is a new item
+        assert!(repo.add_context_item(sid, "file", "a.rs", "fn a(){ 1 }", 11).await.unwrap());
+        assert_eq!(repo.list_context_items(sid).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn context_embeddings_roundtrip_by_model() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let sid = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        repo.add_context_item(sid, "file", "a.rs", "fn a(){}", 8).await.unwrap();
+        let item = repo.list_context_items(sid).await.unwrap().remove(0);
+
+        repo.set_context_embedding(item.id, "m1", &[0.5, -1.0, 2.0]).await.unwrap();
+        repo.set_context_embedding(item.id, "m1", &[1.0, 0.0]).await.unwrap();
+        let got = repo.list_context_embeddings(sid, "m1").await.unwrap();
+        assert_eq!(got, vec![(item.id, vec![1.0, 0.0])]);
+        assert!(repo.list_context_embeddings(sid, "m2").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_context_items_page_applies_offset_and_limit() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let sid = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        for i in 0..5 {
+            repo.add_context_item(sid, "file", &format!("f{}.rs", i), &format!("c{}", i), 2).await.unwrap();
+        }
+        let all: Vec<String> = repo.list_context_items(sid).await.unwrap().into_iter().map(|i| i.key).collect();
+        let page: Vec<String> = repo.list_context_items_page(sid, 1, 2).await.unwrap().into_iter().map(|i| i.key).collect();
+        assert_eq!(page, all[1..3].to_vec());
+        assert!(repo.list_context_items_page(sid, 5, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn delete_context_item_is_scoped_to_session() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let sid = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let other = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        repo.add_context_item(sid, "file", "big.rs", "x", 1).await.unwrap();
+        let item = repo.list_context_items(sid).await.unwrap().remove(0);
+
+        assert!(!repo.delete_context_item(other, item.id).await.unwrap());
+        assert!(repo.delete_context_item(sid, item.id).await.unwrap());
+        assert!(!repo.delete_context_item(sid, item.id).await.unwrap());
+        assert!(repo.list_context_items(sid).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn context_usage_sums_bytes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let sid = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        assert_eq!(repo.context_usage(sid).await.unwrap(), ContextUsage::default());
+        repo.add_context_item(sid, "file", "a.rs", "aaa", 3).await.unwrap();
+        repo.add_context_item(sid, "file", "b.rs", "bbbbb", 5).await.unwrap();
+        let expected_tokens = (crate::tokens::estimate_tokens("aaa") + crate::tokens::estimate_tokens("bbbbb")) as u64;
+        assert_eq!(repo.context_usage(sid).await.unwrap(), ContextUsage { used_bytes: 8, used_tokens: expected_tokens, item_count: 2 });
+    }
+
+    #[tokio::test]
+    async fn context_items_contract_via_trait_object() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let sqlite = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let repo: &dyn SessionRepository = &sqlite;
+        let sid = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let other = repo.create_session(None, SessionSettings::default()).await.unwrap();
+
+        assert!(repo.add_context_item(sid, "url", "https://example.com", "hello", 5).await.unwrap());
+        let item = repo.list_context_items(sid).await.unwrap().remove(0);
+        let got = repo.get_context_item(sid, item.id).await.unwrap().unwrap();
+        assert_eq!(got.key, "https://example.com");
+        assert_eq!(got.content_excerpt, "hello");
+        // items are scoped to their session
+        assert!(repo.get_context_item(other, item.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn context_item_metadata_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let repo = SqliteSessionRepository::initialize(Some(url)).await.unwrap();
+        let session_id = repo.create_session(None, SessionSettings::default()).await.unwrap();
+        let meta = serde_json::json!({"title": "Docs", "final_url": "https://example.com/docs/"});
+        repo.add_context_item_with_metadata(session_id, "url", "https://example.com/docs", "text", 4, Some(meta.clone())).await.unwrap();
+
+        let items = repo.list_context_items(session_id).await.unwrap();
+        assert_eq!(items[0].metadata, Some(meta));
+    }
 }
 
 
@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use crate::error::{Error, Result};
+
+/// Splits `body` into literal text and `{{name}}` placeholders (names trimmed).
+/// An unclosed `{{` is literal.
+fn segments(body: &str) -> Vec<(&str, Option<&str>)> {
+    let mut out = Vec::new();
+    let mut rest = body;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open + 2..].find("}}") else { break };
+        let name = rest[open + 2..open + 2 + close].trim();
+        out.push((&rest[..open], Some(name)));
+        rest = &rest[open + 2 + close + 2..];
+    }
+    out.push((rest, None));
+    out
+}
+
+/// Distinct placeholder names in `body`, in order of first use.
+pub fn placeholders(body: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in segments(body).into_iter().filter_map(|(_, name)| name) {
+        if !names.iter().any(|n| n == name) { names.push(name.to_string()); }
+    }
+    names
+}
+
+/// Substitutes each `{{var}}` in `body` from `vars`. Substituted values are not
+/// re-scanned. Fails with `InvalidInput` naming every placeholder without a value.
+pub fn render(body: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let missing: Vec<String> = placeholders(body).into_iter().filter(|n| !vars.contains_key(n)).collect();
+    if !missing.is_empty() {
+        return Err(Error::InvalidInput(format!("missing template variables: {}", missing.join(", "))));
+    }
+    let mut out = String::with_capacity(body.len());
+    for (text, name) in segments(body) {
+        out.push_str(text);
+        if let Some(name) = name { out.push_str(&vars[name]); }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn render_substitutes_placeholders() {
+        let body = "Review {{ file }} for {{focus}}; then re-check {{file}}.";
+        assert_eq!(placeholders(body), vec!["file".to_string(), "focus".to_string()]);
+        let out = render(body, &vars(&[("file", "a.rs"), ("focus", "{{x}}")])).unwrap();
+        assert_eq!(out, "Review a.rs for {{x}}; then re-check a.rs.");
+        assert_eq!(render("no vars {{ here", &vars(&[])).unwrap(), "no vars {{ here");
+    }
+
+    #[test]
+    fn render_reports_all_missing_variables() {
+        let err = render("{{a}} {{b}} {{c}}", &vars(&[("b", "1")])).unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(ref m) if m == "missing template variables: a, c"));
+    }
+}
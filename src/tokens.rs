@@ -0,0 +1,46 @@
+//! Token estimates for context budgeting and the prompt-length guard.
+//!
+//! With the `tokenizer` feature, text is counted with the `cl100k_base` BPE
+//! (what OpenAI-compatible chat models use). Without it, one token is assumed
+//! per four bytes, rounded up; close enough for English and code, and always
+//! available.
+
+/// Estimated token count of `text` as a model would see it.
+pub fn estimate_tokens(text: &str) -> usize {
+    #[cfg(feature = "tokenizer")]
+    {
+        use std::sync::OnceLock;
+        static BPE: OnceLock<Option<tiktoken_rs::CoreBPE>> = OnceLock::new();
+        if let Some(bpe) = BPE.get_or_init(|| tiktoken_rs::cl100k_base().ok()) {
+            return bpe.encode_ordinary(text).len();
+        }
+    }
+    estimate_tokens_from_bytes(text.len() as u64) as usize
+}
+
+/// Bytes/4 heuristic, for sizes whose text is no longer at hand.
+pub fn estimate_tokens_from_bytes(bytes: u64) -> u64 {
+    bytes.div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_heuristic_rounds_up() {
+        assert_eq!(estimate_tokens_from_bytes(0), 0);
+        assert_eq!(estimate_tokens_from_bytes(1), 1);
+        assert_eq!(estimate_tokens_from_bytes(8), 2);
+        assert_eq!(estimate_tokens_from_bytes(9), 3);
+    }
+
+    #[test]
+    fn estimates_grow_with_text() {
+        assert_eq!(estimate_tokens(""), 0);
+        let short = estimate_tokens("fn main() {}");
+        let long = estimate_tokens(&"fn main() {}\n".repeat(100));
+        assert!(short > 0);
+        assert!(long > short * 50, "{short} vs {long}");
+    }
+}